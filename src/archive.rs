@@ -0,0 +1,130 @@
+use std::{
+    fs::{self, OpenOptions},
+    io::{self, Write},
+};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    coin::CoinToss,
+    history::History,
+    net::Remote,
+    notation::{standings_notation, GameRecord},
+    rules::{Symbol, Variant},
+    ui::Status,
+    AppState, WinningEvent,
+};
+
+/// Every finished game, one JSON object per line, next to the executable's working directory.
+pub const ARCHIVE_FILE: &str = "archive.jsonl";
+/// Opponent of the games played on this computer.
+pub const LOCAL_OPPONENT: &str = "Local";
+
+pub struct ArchivePlugin;
+
+impl Plugin for ArchivePlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_system_set(SystemSet::on_update(AppState::Game).with_system(archive_system));
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ArchivedGame {
+    pub date: String,
+    pub variant: String,
+    pub opponent: String,
+    pub result: String,
+    pub moves: usize,
+    /// The game as a `.ttt` record, to replay it.
+    pub record: String,
+}
+
+impl ArchivedGame {
+    pub fn new(record: &GameRecord, opponent: &str) -> Self {
+        Self {
+            date: record.date.clone(),
+            variant: record.variant.clone(),
+            opponent: opponent.to_string(),
+            result: standings_notation(&record.result),
+            moves: record.moves.len(),
+            record: record.to_string(),
+        }
+    }
+}
+
+/// Adds a game at the end of the archive, the games already in it are never rewritten.
+pub fn append(game: &ArchivedGame) -> io::Result<()> {
+    let line = serde_json::to_string(game).map_err(io::Error::from)?;
+
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(ARCHIVE_FILE)?;
+    writeln!(file, "{}", line)
+}
+
+/// The archived games in the order they were played, and the number of lines that couldn't be read.
+pub fn load() -> (Vec<ArchivedGame>, usize) {
+    let text = match fs::read_to_string(ARCHIVE_FILE) {
+        Ok(text) => text,
+        // no game was finished yet
+        Err(_) => return (Vec::new(), 0),
+    };
+
+    let mut skipped = 0;
+    let games = text
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .filter_map(|line| {
+            let game = serde_json::from_str(line).ok();
+            if game.is_none() {
+                skipped += 1;
+            }
+
+            game
+        })
+        .collect();
+
+    (games, skipped)
+}
+
+fn archive_system(
+    mut winning_events: EventReader<WinningEvent>,
+    variant: Res<Variant>,
+    history: Res<History>,
+    coin_toss: Res<CoinToss>,
+    remote: Res<Remote>,
+    mut status: ResMut<Status>,
+) {
+    let opponent = remote
+        .0
+        .as_ref()
+        .map_or(LOCAL_OPPONENT, |remote_game| remote_game.name.as_str());
+
+    for _ in winning_events.iter() {
+        // the game of other players, not one of this computer's
+        if remote
+            .0
+            .as_ref()
+            .map_or(false, |remote_game| remote_game.symbol == Symbol::Empty)
+        {
+            continue;
+        }
+
+        let record = GameRecord::new(
+            &variant,
+            if variant.random_turns {
+                coin_toss.seed
+            } else {
+                0
+            },
+            &history.moves[..history.cursor],
+            &history.positions[..=history.cursor],
+        );
+
+        if let Err(error) = append(&ArchivedGame::new(&record, opponent)) {
+            status.0 = Some(format!("Couldn't archive the game: {}", error));
+        }
+    }
+}
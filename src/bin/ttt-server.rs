@@ -0,0 +1,65 @@
+//! Headless server for networked games, the players meet in named rooms and the server checks their moves.
+//!
+//! `ttt-server [address] [--delay seconds]` listens on `0.0.0.0:7878` unless told otherwise.
+//! Clients join a room by typing `address/room` in the Join screen, the first one in picks the
+//! variant, `address/` takes a seat in any room waiting for players of the variant picked in the
+//! menu. Players who lose the connection get `GRACE_SECONDS` to resume the game before the
+//! room closes. Spectators watch a room without playing, the moves and the players' chat reach
+//! them `--delay` seconds late. What goes on is logged, `RUST_LOG=warn` keeps the problems only.
+//!
+//! The same port takes the game's JSON lines and WebSocket clients, `ws://address/room`. Running
+//! it on `127.0.0.1` and joining `ws://127.0.0.1/room` tries the WebSocket side out locally.
+
+use std::{env, net::TcpListener, process, thread, time::Duration};
+
+use bevy_tic_tac_toe::{protocol::DEFAULT_PORT, server::Server};
+use log::info;
+
+/// Time between two looks at the connections.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+const USAGE: &str = "Usage: ttt-server [address] [--delay seconds]";
+
+fn main() {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+
+    let mut address = format!("0.0.0.0:{}", DEFAULT_PORT);
+    let mut delay = Duration::from_secs(0);
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--delay" => match args.next().and_then(|seconds| seconds.parse::<f64>().ok()) {
+                Some(seconds) if seconds >= 0.0 => delay = Duration::from_secs_f64(seconds),
+                _ => {
+                    eprintln!("{}", USAGE);
+                    process::exit(2);
+                }
+            },
+            _ if arg.starts_with('-') => {
+                eprintln!("{}", USAGE);
+                process::exit(2);
+            }
+            _ => address = arg,
+        }
+    }
+
+    let listener = match TcpListener::bind(&address).and_then(|listener| {
+        listener.set_nonblocking(true)?;
+        Ok(listener)
+    }) {
+        Ok(listener) => listener,
+        Err(error) => {
+            eprintln!("Couldn't listen on {}: {}", address, error);
+            process::exit(1);
+        }
+    };
+    info!("Listening on {}", address);
+
+    let mut server = Server::new(delay);
+    loop {
+        server.update(&listener);
+
+        thread::sleep(POLL_INTERVAL);
+    }
+}
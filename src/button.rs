@@ -1,20 +1,81 @@
 use bevy::prelude::*;
 
+use crate::theme::button_color;
+
 #[derive(Clone, Copy)]
 pub enum ButtonElement {
     StartGame,
+    /// Picks up the game of the selected save slot.
+    Continue,
+    /// Labelled with the variant picked in the menu, clicking it picks the next one.
+    Variant,
+    /// Labelled with the turn order picked in the menu, clicking it picks the next one.
+    TurnOrder,
+    /// Labelled with the save slot picked in the menu, clicking it picks the next one.
+    SaveSlot,
+    /// Starts typing a name for the save slot picked in the menu.
+    NameSlot,
     RestartGame,
+    BackToMenu,
     QuitGame,
+    Undo,
+    Redo,
+    /// Opens the list of recorded games.
+    GameList,
+    StepBack,
+    /// Labelled with what clicking it does to the replay.
+    PlayPause,
+    StepForward,
+    /// Labelled with the replay speed, clicking it picks the next one.
+    ReplaySpeed,
+    /// Waits for another player to join over the network, with the variant picked in the menu.
+    Host,
+    /// Joins a game hosted over the network.
+    Join,
+    /// Follows the game of a server room without playing.
+    Watch,
+    /// Starts a game played by exchanging signed files, one move each.
+    PlayByFile,
+    /// Asks the other player of a networked game for a new game, the symbols swapped.
+    Rematch,
+    /// Asks the other player of a networked game to take the last move back.
+    Takeback,
+    /// Asks the other player of a networked game for a draw.
+    OfferDraw,
+    /// Answers the other player's offer.
+    Accept,
+    Decline,
 }
 
 impl Into<String> for ButtonElement {
     fn into(self) -> String {
         match self {
-            Self::StartGame => "Start game",
-            Self::RestartGame => "Restart game",
-            Self::QuitGame => "Quit game",
+            Self::StartGame => "Start game".into(),
+            Self::Continue => "Continue".into(),
+            Self::Variant => "Variant".into(),
+            Self::TurnOrder => "Turn order".into(),
+            Self::SaveSlot => "Slot".into(),
+            Self::NameSlot => "Name".into(),
+            Self::RestartGame => "Restart".into(),
+            Self::BackToMenu => "Menu".into(),
+            Self::QuitGame => "Quit game".into(),
+            Self::Undo => "Undo".into(),
+            Self::Redo => "Redo".into(),
+            Self::GameList => "Games".into(),
+            Self::StepBack => "<".into(),
+            Self::PlayPause => "Pause".into(),
+            Self::StepForward => ">".into(),
+            Self::ReplaySpeed => "Speed".into(),
+            Self::Host => "Host".into(),
+            Self::Join => "Join".into(),
+            Self::Watch => "Watch".into(),
+            Self::PlayByFile => "By file".into(),
+            Self::Rematch => "Rematch".into(),
+            Self::Takeback => "Takeback".into(),
+            Self::OfferDraw => "Draw".into(),
+            Self::Accept => "Accept".into(),
+            Self::Decline => "Decline".into(),
         }
-        .into()
     }
 }
 
@@ -32,9 +93,9 @@ impl FromWorld for ButtonAssets {
             .expect("Couldn't get ColorMaterial assets");
 
         let (normal, hovered, pressed) = (
-            materials.add(Color::rgb_u8(190, 105, 177).into()),
-            materials.add(Color::rgb_u8(220, 124, 217).into()),
-            materials.add(Color::rgb_u8(120, 85, 136).into()),
+            materials.add(button_color(Interaction::None).into()),
+            materials.add(button_color(Interaction::Hovered).into()),
+            materials.add(button_color(Interaction::Clicked).into()),
         );
 
         let asset_server = world
@@ -0,0 +1,194 @@
+use std::collections::HashSet;
+
+use bevy::{
+    input::mouse::{MouseMotion, MouseWheel},
+    prelude::*,
+    render::camera::{Camera, CameraProjection, OrthographicProjection},
+};
+
+use crate::{
+    rules::{Symbol, Topology, Variant},
+    symbol::{
+        cell_position, chunk_of, update_symbols, BoardView, Symbols, SymbolsMaterials, SPACE_SIZE,
+        SYMBOL_SIZE,
+    },
+    ui::PANEL_WIDTH,
+    AppState, MainCamera,
+};
+
+const PAN_SPEED: f32 = 500.0;
+const ZOOM_STEP: f32 = 1.1;
+const MIN_ZOOM: f32 = 0.5;
+const MAX_ZOOM: f32 = 4.0;
+/// Room kept above and below the board for the texts and buttons.
+const UI_MARGIN: f32 = 96.0;
+
+pub struct CameraPlugin;
+
+impl Plugin for CameraPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_system_set(SystemSet::on_enter(AppState::Game).with_system(reset_camera))
+            .add_system_set(SystemSet::on_enter(AppState::Replay).with_system(reset_camera))
+            .add_system_set(
+                SystemSet::on_update(AppState::Game)
+                    .with_system(camera_control_system)
+                    .with_system(chunk_system),
+            );
+    }
+}
+
+/// World position under the cursor, unprojected with the matrices the camera actually renders with.
+pub fn cursor_to_world(
+    window: &Window,
+    camera: &Camera,
+    camera_transform: &GlobalTransform,
+) -> Option<Vec2> {
+    let cursor_pos = window.cursor_position()?;
+    let window_size = Vec2::new(window.width(), window.height());
+    let ndc = cursor_pos / window_size * 2.0 - Vec2::ONE;
+
+    let ndc_to_world = camera_transform.compute_matrix() * camera.projection_matrix.inverse();
+    let world_pos = ndc_to_world * ndc.extend(0.0).extend(1.0);
+
+    Some(Vec2::new(world_pos.x, world_pos.y) / world_pos.w)
+}
+
+fn reset_camera(
+    windows: Res<Windows>,
+    variant: Res<Variant>,
+    mut camera_query: Query<
+        (&mut Camera, &mut Transform, &mut OrthographicProjection),
+        With<MainCamera>,
+    >,
+) {
+    let window = windows.get_primary().unwrap();
+    let (mut camera, mut transform, mut projection) = camera_query.single_mut().unwrap();
+
+    // zoom out until bigger boards fit between the texts and the buttons
+    let topology = variant.topology;
+    let scale = topology.cells().map_or(1.0, |cells| {
+        let extents = cells.iter().fold(Vec2::ZERO, |extents, &cell| {
+            extents.max(cell_position(&topology, cell).abs())
+        }) + Vec2::splat(SYMBOL_SIZE / 2.0);
+        let room = Vec2::new(
+            window.width() - 2.0 * PANEL_WIDTH,
+            window.height() - 2.0 * UI_MARGIN,
+        ) / 2.0;
+
+        (extents / room).max_element().max(1.0)
+    });
+
+    transform.translation.x = 0.0;
+    transform.translation.y = 0.0;
+    projection.scale = scale;
+    camera.projection_matrix = projection.get_projection_matrix();
+}
+
+fn camera_control_system(
+    time: Res<Time>,
+    windows: Res<Windows>,
+    variant: Res<Variant>,
+    keyboard_input: Res<Input<KeyCode>>,
+    mouse_button_input: Res<Input<MouseButton>>,
+    mut mouse_motion_events: EventReader<MouseMotion>,
+    mut mouse_wheel_events: EventReader<MouseWheel>,
+    mut camera_query: Query<
+        (&mut Camera, &mut Transform, &mut OrthographicProjection),
+        With<MainCamera>,
+    >,
+) {
+    let drag = mouse_motion_events
+        .iter()
+        .fold(Vec2::ZERO, |drag, event| drag + event.delta);
+    let scroll = mouse_wheel_events
+        .iter()
+        .fold(0.0, |scroll, event| scroll + event.y);
+
+    if variant.topology != Topology::Infinite {
+        return;
+    }
+
+    let (mut camera, mut transform, mut projection) = camera_query.single_mut().unwrap();
+
+    let mut pan = Vec2::ZERO;
+    for (key, direction) in [
+        (KeyCode::Left, -Vec2::X),
+        (KeyCode::Right, Vec2::X),
+        (KeyCode::Down, -Vec2::Y),
+        (KeyCode::Up, Vec2::Y),
+    ]
+    .iter()
+    {
+        if keyboard_input.pressed(*key) {
+            pan += *direction * PAN_SPEED * time.delta_seconds();
+        }
+    }
+
+    // the board follows the cursor while dragging with the right button
+    if mouse_button_input.pressed(MouseButton::Right) {
+        pan += Vec2::new(-drag.x, drag.y);
+    }
+
+    transform.translation += (pan * projection.scale).extend(0.0);
+
+    // the wheel scrolls the move list while the cursor is over it
+    let window = windows.get_primary().unwrap();
+    let over_panel = window
+        .cursor_position()
+        .map_or(false, |cursor| cursor.x > window.width() - PANEL_WIDTH);
+
+    if scroll != 0.0 && !over_panel {
+        projection.scale = (projection.scale * ZOOM_STEP.powf(-scroll)).clamp(MIN_ZOOM, MAX_ZOOM);
+        // the projection matrix is otherwise only refreshed when the window is resized
+        camera.projection_matrix = projection.get_projection_matrix();
+    }
+}
+
+fn chunk_system(
+    mut commands: Commands,
+    windows: Res<Windows>,
+    variant: Res<Variant>,
+    camera_query: Query<(&Transform, &OrthographicProjection), With<MainCamera>>,
+    symbols_query: Query<Entity, With<Symbol>>,
+    symbols_materials: Res<SymbolsMaterials>,
+    symbols: ResMut<Symbols>,
+    mut board_view: ResMut<BoardView>,
+) {
+    if variant.topology != Topology::Infinite {
+        return;
+    }
+
+    let window = windows.get_primary().unwrap();
+    let (transform, projection) = camera_query.single().unwrap();
+
+    let center = Vec2::new(transform.translation.x, transform.translation.y);
+    let extents = Vec2::new(window.width(), window.height()) / 2.0 * projection.scale;
+    let step = SYMBOL_SIZE + SPACE_SIZE;
+
+    // rows go down the screen
+    let (min_x, min_y) = chunk_of((
+        ((center.x - extents.x) / step).floor() as i32,
+        (-(center.y + extents.y) / step).floor() as i32,
+    ));
+    let (max_x, max_y) = chunk_of((
+        ((center.x + extents.x) / step).ceil() as i32,
+        (-(center.y - extents.y) / step).ceil() as i32,
+    ));
+
+    let chunks = (min_y..=max_y)
+        .flat_map(|y| (min_x..=max_x).map(move |x| (x, y)))
+        .collect::<HashSet<_>>();
+
+    if chunks != board_view.chunks {
+        board_view.chunks = chunks;
+
+        update_symbols(
+            &mut commands,
+            &symbols_query,
+            &symbols_materials,
+            &symbols,
+            &variant,
+            &board_view,
+        );
+    }
+}
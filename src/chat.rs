@@ -0,0 +1,387 @@
+use std::collections::HashSet;
+
+use bevy::prelude::*;
+
+use crate::{
+    button::ButtonAssets,
+    history::History,
+    net::Remote,
+    notation::ChatLine,
+    protocol::{chat_text, player_name, Message, CHAT_LIMIT, CHAT_WINDOW_SECONDS},
+    ui::{NodeElement, Status, TextElement, PANEL_WIDTH},
+    AppState,
+};
+
+/// Said with a click on the emote bar.
+const EMOTES: [&str; 3] = ["gg", "nice", "oops"];
+/// Lines shown at once, the latest ones.
+const CHAT_ROWS: usize = 6;
+const CHAT_FONT_SIZE: f32 = 16.0;
+
+pub struct ChatPlugin;
+
+impl Plugin for ChatPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<Chat>()
+            .add_system_set(SystemSet::on_enter(AppState::Menu).with_system(clear_chat))
+            .add_system_set(SystemSet::on_enter(AppState::Lobby).with_system(clear_chat))
+            .add_system_set(SystemSet::on_enter(AppState::Game).with_system(setup_chat))
+            .add_system_set(
+                SystemSet::on_update(AppState::Game)
+                    .with_system(chat_input_system)
+                    .with_system(chat_list_system)
+                    .with_system(emote_click_system)
+                    .with_system(mute_click_system),
+            );
+    }
+}
+
+/// What was said during the networked game, saved with its record.
+#[derive(Default)]
+pub struct Chat {
+    pub lines: Vec<ChatLine>,
+    /// Players whose lines aren't shown.
+    pub muted: HashSet<String>,
+    /// Message being typed, `None` until Enter is pressed.
+    typing: Option<String>,
+    /// Seconds since startup the last messages were sent at.
+    sent: Vec<f64>,
+}
+
+impl Chat {
+    /// Sends a message to the other players and adds it to the chat, unless it's sent too often.
+    fn say(&mut self, text: &str, now: f64, ply: usize, remote: &Remote, status: &mut Status) {
+        let remote_game = match remote.0 {
+            Some(ref remote_game) => remote_game,
+            None => return,
+        };
+        let text = chat_text(text);
+        if text.is_empty() {
+            return;
+        }
+
+        // the server would drop them anyway
+        self.sent
+            .retain(|sent| now - sent < CHAT_WINDOW_SECONDS as f64);
+        if self.sent.len() >= CHAT_LIMIT {
+            status.0 = Some("Too many chat messages, slow down".to_string());
+            return;
+        }
+        self.sent.push(now);
+
+        remote_game.send(&Message::Chat {
+            name: player_name(),
+            text: text.clone(),
+        });
+        self.lines.push(ChatLine {
+            ply,
+            name: player_name(),
+            text,
+        });
+    }
+}
+
+pub struct EmoteEntry(&'static str);
+pub struct MuteEntry(String);
+
+fn clear_chat(mut chat: ResMut<Chat>) {
+    *chat = Chat::default();
+}
+
+fn setup_chat(
+    mut commands: Commands,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    button_assets: Res<ButtonAssets>,
+    remote: Res<Remote>,
+    chat: Res<Chat>,
+) {
+    // saved games keep their chat to read, only networked games get to add to it
+    if remote.0.is_none() && chat.lines.is_empty() {
+        return;
+    }
+
+    let none = materials.add(Color::NONE.into());
+    let style = TextStyle {
+        font: button_assets.font.clone(),
+        font_size: CHAT_FONT_SIZE,
+        color: Color::rgb_u8(171, 178, 191),
+    };
+
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    left: Val::Px(0.0),
+                    top: Val::Px(96.0),
+                    ..Default::default()
+                },
+                size: Size::new(Val::Px(PANEL_WIDTH), Val::Auto),
+                flex_direction: FlexDirection::ColumnReverse,
+                padding: Rect::all(Val::Px(8.0)),
+                ..Default::default()
+            },
+            material: none.clone(),
+            ..Default::default()
+        })
+        .insert(NodeElement::Root)
+        .with_children(|panel| {
+            panel
+                .spawn_bundle(NodeBundle {
+                    style: Style {
+                        flex_direction: FlexDirection::ColumnReverse,
+                        ..Default::default()
+                    },
+                    material: none.clone(),
+                    ..Default::default()
+                })
+                .insert(NodeElement::Chat);
+
+            if remote.0.is_none() {
+                return;
+            }
+
+            panel
+                .spawn_bundle(TextBundle {
+                    text: Text::with_section("", style.clone(), Default::default()),
+                    style: Style {
+                        max_size: Size::new(Val::Px(PANEL_WIDTH - 16.0), Val::Undefined),
+                        margin: Rect {
+                            top: Val::Px(8.0),
+                            ..Default::default()
+                        },
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                })
+                .insert(TextElement::ChatInput(None));
+
+            // clickable without looking like the buttons
+            panel
+                .spawn_bundle(NodeBundle {
+                    material: none.clone(),
+                    ..Default::default()
+                })
+                .with_children(|bar| {
+                    for &emote in EMOTES.iter() {
+                        bar.spawn_bundle(NodeBundle {
+                            style: Style {
+                                margin: Rect {
+                                    right: Val::Px(8.0),
+                                    ..Default::default()
+                                },
+                                ..Default::default()
+                            },
+                            material: none.clone(),
+                            ..Default::default()
+                        })
+                        .insert(Interaction::default())
+                        .insert(EmoteEntry(emote))
+                        .with_children(|entry| {
+                            entry.spawn_bundle(TextBundle {
+                                text: Text::with_section(
+                                    emote,
+                                    TextStyle {
+                                        color: Color::WHITE,
+                                        ..style.clone()
+                                    },
+                                    Default::default(),
+                                ),
+                                ..Default::default()
+                            });
+                        });
+                    }
+                });
+        });
+}
+
+/// Enter starts a message and sends it, Escape gives it up.
+fn chat_input_system(
+    mut received_characters: EventReader<ReceivedCharacter>,
+    keyboard_input: Res<Input<KeyCode>>,
+    time: Res<Time>,
+    history: Res<History>,
+    remote: Res<Remote>,
+    mut chat: ResMut<Chat>,
+    mut status: ResMut<Status>,
+    mut text_query: Query<&mut TextElement>,
+) {
+    if remote.0.is_none() {
+        return;
+    }
+
+    // the chat is only touched when something happens, it's rebuilt when it changes
+    let enter = keyboard_input.just_pressed(KeyCode::Return);
+    if chat.typing.is_none() {
+        if enter {
+            chat.typing = Some(String::new());
+        }
+    } else if enter {
+        let text = chat.typing.take().unwrap_or_default();
+        chat.say(
+            &text,
+            time.seconds_since_startup(),
+            history.cursor,
+            &remote,
+            &mut status,
+        );
+    } else if keyboard_input.just_pressed(KeyCode::Escape) {
+        chat.typing = None;
+    } else {
+        let typed = received_characters
+            .iter()
+            .map(|event| event.char)
+            .filter(|character| !character.is_control())
+            .collect::<String>();
+        let back = keyboard_input.just_pressed(KeyCode::Back);
+
+        if !typed.is_empty() || back {
+            if let Some(ref mut text) = chat.typing {
+                text.push_str(&typed);
+                if back {
+                    text.pop();
+                }
+            }
+        }
+    }
+
+    for mut text_element in text_query.iter_mut() {
+        if let TextElement::ChatInput(ref mut shown) = *text_element {
+            if *shown != chat.typing {
+                *shown = chat.typing.clone();
+            }
+        }
+    }
+}
+
+fn emote_click_system(
+    interaction_query: Query<(&Interaction, &EmoteEntry), Changed<Interaction>>,
+    time: Res<Time>,
+    history: Res<History>,
+    remote: Res<Remote>,
+    mut chat: ResMut<Chat>,
+    mut status: ResMut<Status>,
+) {
+    for (interaction, entry) in interaction_query.iter() {
+        if *interaction == Interaction::Clicked {
+            chat.say(
+                entry.0,
+                time.seconds_since_startup(),
+                history.cursor,
+                &remote,
+                &mut status,
+            );
+        }
+    }
+}
+
+fn mute_click_system(
+    interaction_query: Query<(&Interaction, &MuteEntry), Changed<Interaction>>,
+    mut chat: ResMut<Chat>,
+) {
+    for (interaction, entry) in interaction_query.iter() {
+        if *interaction != Interaction::Clicked {
+            continue;
+        }
+
+        if !chat.muted.remove(&entry.0) {
+            chat.muted.insert(entry.0.clone());
+        }
+    }
+}
+
+/// Rebuilds the chat as it goes, with a mute toggle for each of the other players who spoke.
+fn chat_list_system(
+    mut commands: Commands,
+    chat: Res<Chat>,
+    button_assets: Res<ButtonAssets>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    node_query: Query<(Entity, &NodeElement, Option<&Children>)>,
+    added_query: Query<&NodeElement, Added<NodeElement>>,
+) {
+    let added = added_query
+        .iter()
+        .any(|node_element| matches!(node_element, NodeElement::Chat));
+
+    if !added && !chat.is_changed() {
+        return;
+    }
+
+    let (list, children) = match node_query
+        .iter()
+        .find(|(_, node_element, _)| matches!(node_element, NodeElement::Chat))
+    {
+        Some((list, _, children)) => (list, children),
+        None => return,
+    };
+
+    for &child in children.into_iter().flat_map(|children| children.iter()) {
+        commands.entity(child).despawn_recursive();
+    }
+
+    let none = materials.add(Color::NONE.into());
+    let style = TextStyle {
+        font: button_assets.font.clone(),
+        font_size: CHAT_FONT_SIZE,
+        color: Color::WHITE,
+    };
+
+    let shown = chat
+        .lines
+        .iter()
+        .filter(|line| !chat.muted.contains(&line.name))
+        .collect::<Vec<_>>();
+    let mut speakers = chat
+        .lines
+        .iter()
+        .map(|line| line.name.clone())
+        .filter(|name| *name != player_name())
+        .collect::<Vec<_>>();
+    speakers.sort_unstable();
+    speakers.dedup();
+
+    commands.entity(list).with_children(|list| {
+        for line in shown[shown.len().saturating_sub(CHAT_ROWS)..].iter() {
+            list.spawn_bundle(TextBundle {
+                text: Text::with_section(
+                    format!("{}: {}", line.name, line.text),
+                    style.clone(),
+                    Default::default(),
+                ),
+                style: Style {
+                    max_size: Size::new(Val::Px(PANEL_WIDTH - 16.0), Val::Undefined),
+                    ..Default::default()
+                },
+                ..Default::default()
+            });
+        }
+
+        for name in speakers {
+            let label = if chat.muted.contains(&name) {
+                format!("Unmute {}", name)
+            } else {
+                format!("Mute {}", name)
+            };
+
+            list.spawn_bundle(NodeBundle {
+                material: none.clone(),
+                ..Default::default()
+            })
+            .insert(Interaction::default())
+            .insert(MuteEntry(name))
+            .with_children(|entry| {
+                entry.spawn_bundle(TextBundle {
+                    text: Text::with_section(
+                        label,
+                        TextStyle {
+                            color: Color::rgb_u8(171, 178, 191),
+                            ..style.clone()
+                        },
+                        Default::default(),
+                    ),
+                    ..Default::default()
+                });
+            });
+        }
+    });
+}
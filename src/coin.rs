@@ -0,0 +1,93 @@
+use bevy::prelude::*;
+use rand::random;
+
+use crate::{
+    rules::{self, Symbol},
+    symbol::CurrentSymbol,
+    ui::{update_texts, TextElement},
+    AppState,
+};
+
+const FACE_DURATION: f32 = 0.08;
+const FACES: u32 = 13;
+
+pub struct CoinPlugin;
+
+impl Plugin for CoinPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<CoinToss>()
+            .add_system_set(SystemSet::on_update(AppState::Game).with_system(coin_toss_system));
+    }
+}
+
+/// Coin flips of a random turns game, derived from `seed` and recorded so the game can be replayed.
+pub struct CoinToss {
+    pub seed: u64,
+    pub flips: Vec<Symbol>,
+    faces: u32,
+    timer: Timer,
+}
+
+impl Default for CoinToss {
+    fn default() -> Self {
+        Self {
+            seed: random(),
+            flips: Vec::new(),
+            faces: 0,
+            timer: Timer::from_seconds(FACE_DURATION, true),
+        }
+    }
+}
+
+impl CoinToss {
+    pub fn start(&mut self) {
+        self.faces = FACES;
+        self.timer.reset();
+    }
+
+    pub fn is_spinning(&self) -> bool {
+        self.faces > 0
+    }
+
+    /// Goes back or forward to the first `flips` flips, they always come out the same for a seed.
+    pub fn rewind(&mut self, flips: usize) {
+        self.flips = (0..flips)
+            .map(|ply| rules::coin_flip(self.seed, ply))
+            .collect();
+        self.faces = 0;
+    }
+}
+
+fn coin_toss_system(
+    time: Res<Time>,
+    mut coin_toss: ResMut<CoinToss>,
+    mut current_symbol: ResMut<CurrentSymbol>,
+    mut text_query: Query<(Entity, &mut TextElement)>,
+) {
+    if coin_toss.faces == 0 || !coin_toss.timer.tick(time.delta()).just_finished() {
+        return;
+    }
+
+    coin_toss.faces -= 1;
+
+    if coin_toss.faces == 0 {
+        let flip = rules::coin_flip(coin_toss.seed, coin_toss.flips.len());
+        coin_toss.flips.push(flip);
+        current_symbol.0 = flip;
+
+        update_texts(&mut text_query, &current_symbol, false);
+        return;
+    }
+
+    let face = if coin_toss.faces % 2 == 0 {
+        Symbol::X
+    } else {
+        Symbol::O
+    };
+
+    for (_, mut text_element) in text_query.iter_mut() {
+        if let TextElement::CurrentSymbol(_, ref mut coin_face) = *text_element {
+            *coin_face = Some(face);
+        }
+    }
+}
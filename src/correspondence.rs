@@ -0,0 +1,240 @@
+use std::{fs, path::PathBuf};
+
+use bevy::{prelude::*, window::FileDragAndDrop};
+use rand::random;
+
+use crate::{
+    button::ButtonElement,
+    history::History,
+    notation::{today, GameRecord},
+    record::{read_record_file, PendingRecord},
+    rules::{Symbol, Variant},
+    signature::{key, sign, verify},
+    symbol::{Players, TurnOrder},
+    ui::Status,
+    variant::Variants,
+    AppState,
+};
+
+/// Folder the files to send are written to, next to the executable's working directory.
+pub const CORRESPONDENCE_FOLDER: &str = "correspondence";
+
+pub struct CorrespondencePlugin;
+
+impl Plugin for CorrespondencePlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<Correspondence>()
+            .add_system_set(
+                SystemSet::on_enter(AppState::Menu).with_system(leave_correspondence_game),
+            )
+            .add_system_set(
+                SystemSet::on_update(AppState::Menu)
+                    .with_system(play_by_file_button_system)
+                    .with_system(receive_file_system),
+            )
+            .add_system_set(SystemSet::on_update(AppState::Game).with_system(send_file_system));
+    }
+}
+
+/// Game played by exchanging signed files, `None` for the others.
+#[derive(Default)]
+pub struct Correspondence(pub Option<CorrespondenceGame>);
+
+pub struct CorrespondenceGame {
+    /// The file the game was opened from, its move is the one to answer.
+    pub record: GameRecord,
+    /// Player of this computer.
+    pub symbol: Symbol,
+}
+
+/// File this computer sends once `ply` moves were played, the next file of the game follows it.
+fn sent_file(game: &str, ply: usize) -> PathBuf {
+    PathBuf::from(CORRESPONDENCE_FOLDER).join(format!("{}-{}.ttt", game, ply))
+}
+
+/// Checks the file of the other player against the one sent from here, returns the player of
+/// this computer.
+fn receive(record: &GameRecord) -> Result<Symbol, String> {
+    verify(record, &key()?)?;
+
+    let game = record.game.as_deref().unwrap_or_default();
+    // the id names the files written
+    if game.is_empty()
+        || !game
+            .chars()
+            .all(|character| character.is_ascii_alphanumeric())
+    {
+        return Err("malformed game id".to_string());
+    }
+    if record.players.len() != 2 {
+        return Err("play by file is for two players".to_string());
+    }
+
+    let ply = record.moves.len();
+    let played = record
+        .moves
+        .last()
+        .ok_or_else(|| "there's no move to answer".to_string())?;
+    if sent_file(game, ply).exists() {
+        return Err("the file was sent from here".to_string());
+    }
+    if sent_file(game, ply + 1).exists() {
+        return Err("the move was answered already".to_string());
+    }
+
+    // the other player may have started the game, the first move follows the empty board
+    let previous = if sent_file(game, ply - 1).exists() {
+        read_record_file(&sent_file(game, ply - 1))?
+    } else if ply == 1 {
+        GameRecord {
+            result: None,
+            moves: Vec::new(),
+            signature: None,
+            ..record.clone()
+        }
+    } else {
+        return Err("the move before it wasn't sent from here".to_string());
+    };
+    record.follows(&previous)?;
+
+    record
+        .players
+        .iter()
+        .copied()
+        .find(|&symbol| symbol != played.symbol)
+        .ok_or_else(|| format!("{} plays both sides", played.symbol))
+}
+
+fn leave_correspondence_game(mut correspondence: ResMut<Correspondence>) {
+    correspondence.0 = None;
+}
+
+/// Starts a game with the variant and the turn order picked in the menu, this computer moves first.
+fn play_by_file_button_system(
+    interaction_query: Query<(&Interaction, &ButtonElement), (Changed<Interaction>, With<Button>)>,
+    variant: Res<Variant>,
+    turn_order: Res<TurnOrder>,
+    variant_assets: Res<Assets<Variant>>,
+    mut variants: ResMut<Variants>,
+    mut pending_record: ResMut<PendingRecord>,
+    mut correspondence: ResMut<Correspondence>,
+    mut status: ResMut<Status>,
+) {
+    for (interaction, button) in interaction_query.iter() {
+        if *interaction != Interaction::Clicked || !matches!(button, ButtonElement::PlayByFile) {
+            continue;
+        }
+
+        // who moves next has to be known before the file is opened
+        if variant.players != 2 || variant.random_turns {
+            status.0 =
+                Some("Play by file is for two-player games without random turns".to_string());
+            continue;
+        }
+        if variant.blind {
+            status.0 = Some("Blind games are played on one screen".to_string());
+            continue;
+        }
+        if let Err(error) = key() {
+            status.0 = Some(error);
+            continue;
+        }
+
+        let record = GameRecord {
+            variant: variant.name.clone(),
+            players: Players::new(&turn_order, &variant).0,
+            date: today(),
+            game: Some(format!("{:016x}", random::<u64>())),
+            ..Default::default()
+        };
+        let symbol = record.players[0];
+
+        match pending_record.open_record(
+            record.clone(),
+            &variant_assets,
+            &mut variants,
+            AppState::Game,
+        ) {
+            Ok(_) => correspondence.0 = Some(CorrespondenceGame { record, symbol }),
+            Err(error) => status.0 = Some(error),
+        }
+    }
+}
+
+/// Opens the files of games played by file dropped on the window, once they're checked.
+fn receive_file_system(
+    mut drop_events: EventReader<FileDragAndDrop>,
+    variant_assets: Res<Assets<Variant>>,
+    mut variants: ResMut<Variants>,
+    mut pending_record: ResMut<PendingRecord>,
+    mut correspondence: ResMut<Correspondence>,
+    mut status: ResMut<Status>,
+) {
+    for event in drop_events.iter() {
+        let path = match event {
+            FileDragAndDrop::DroppedFile { path_buf, .. } => path_buf,
+            _ => continue,
+        };
+        // the other records are opened by the record plugin
+        let record = match read_record_file(path) {
+            Ok(record) if record.game.is_some() => record,
+            _ => continue,
+        };
+
+        let opened = receive(&record).and_then(|symbol| {
+            pending_record
+                .open_record(
+                    record.clone(),
+                    &variant_assets,
+                    &mut variants,
+                    AppState::Game,
+                )
+                .map(|_| symbol)
+        });
+
+        match opened {
+            Ok(symbol) => correspondence.0 = Some(CorrespondenceGame { record, symbol }),
+            Err(error) => status.0 = Some(format!("{}: {}", path.display(), error)),
+        }
+    }
+}
+
+/// Writes the signed file to send once the move of this computer is played.
+fn send_file_system(
+    variant: Res<Variant>,
+    history: Res<History>,
+    correspondence: Res<Correspondence>,
+    mut status: ResMut<Status>,
+) {
+    let correspondence_game = match correspondence.0 {
+        Some(ref correspondence_game) => correspondence_game,
+        None => return,
+    };
+    let ply = correspondence_game.record.moves.len() + 1;
+    if !history.is_changed() || history.cursor != ply {
+        return;
+    }
+
+    // the same game as the file opened, one move further
+    let mut record = GameRecord::new(
+        &variant,
+        0,
+        &history.moves[..ply],
+        &history.positions[..=ply],
+    );
+    record.game = correspondence_game.record.game.clone();
+    record.date = correspondence_game.record.date.clone();
+
+    let game = record.game.clone().unwrap_or_default();
+    let path = sent_file(&game, ply);
+    let written = key().and_then(|key| {
+        fs::create_dir_all(CORRESPONDENCE_FOLDER)
+            .and_then(|_| fs::write(&path, sign(&record, &key).to_string()))
+            .map_err(|error| error.to_string())
+    });
+
+    status.0 = Some(match written {
+        Ok(_) => format!("Saved {}, send it to the other team", path.display()),
+        Err(error) => format!("Couldn't save {}: {}", path.display(), error),
+    });
+}
@@ -0,0 +1,276 @@
+use std::net::{SocketAddr, UdpSocket};
+
+use bevy::prelude::*;
+
+use crate::{
+    button::ButtonAssets,
+    net::Lobby,
+    protocol::{player_name, Announcement, DEFAULT_PORT, DISCOVERY_PORT, PROTOCOL_VERSION},
+    rules::Variant,
+    ui::{NodeElement, Status},
+    AppState,
+};
+
+/// Seconds between two announcements of a hosted game.
+const ANNOUNCE_INTERVAL: f32 = 1.0;
+/// Seconds a game stays listed after its last announcement.
+const GAME_EXPIRY: f64 = 4.0;
+const ENTRY_FONT_SIZE: f32 = 20.0;
+
+pub struct DiscoveryPlugin;
+
+impl Plugin for DiscoveryPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<Discovery>()
+            .add_system_set(SystemSet::on_enter(AppState::Lobby).with_system(open_discovery))
+            .add_system_set(
+                SystemSet::on_update(AppState::Lobby)
+                    .with_system(announce_system)
+                    .with_system(discovery_system)
+                    .with_system(discovered_list_system)
+                    .with_system(discovered_entry_click_system),
+            )
+            .add_system_set(SystemSet::on_exit(AppState::Lobby).with_system(close_discovery));
+    }
+}
+
+/// A game hosted on the local network.
+pub struct DiscoveredGame {
+    pub address: SocketAddr,
+    pub announcement: Announcement,
+    /// Seconds since startup when its last announcement came.
+    seen: f64,
+}
+
+/// Announces the hosted game, or lists the games other hosts announce.
+pub struct Discovery {
+    socket: Option<UdpSocket>,
+    timer: Timer,
+    pub games: Vec<DiscoveredGame>,
+}
+
+impl Default for Discovery {
+    fn default() -> Self {
+        Self {
+            socket: None,
+            timer: Timer::from_seconds(ANNOUNCE_INTERVAL, true),
+            games: Vec::new(),
+        }
+    }
+}
+
+/// Address of the game an entry joins.
+pub struct DiscoveredEntry(pub SocketAddr);
+
+fn open_discovery(lobby: Res<Lobby>, mut discovery: ResMut<Discovery>, mut status: ResMut<Status>) {
+    // hosts send from any port, the joining players listen on the discovery one
+    let socket = if lobby.hosting {
+        UdpSocket::bind(("0.0.0.0", 0)).and_then(|socket| {
+            socket.set_broadcast(true)?;
+            Ok(socket)
+        })
+    } else {
+        UdpSocket::bind(("0.0.0.0", DISCOVERY_PORT))
+    }
+    .and_then(|socket| {
+        socket.set_nonblocking(true)?;
+        Ok(socket)
+    });
+
+    *discovery = Discovery::default();
+    match socket {
+        Ok(socket) => discovery.socket = Some(socket),
+        Err(error) if lobby.hosting => {
+            status.0 = Some(format!(
+                "Couldn't announce the game on the local network: {}",
+                error
+            ))
+        }
+        Err(error) => {
+            status.0 = Some(format!(
+                "Couldn't look for games on the local network: {}",
+                error
+            ))
+        }
+    }
+}
+
+fn close_discovery(mut discovery: ResMut<Discovery>) {
+    // frees the discovery port for the other instances on this computer
+    *discovery = Discovery::default();
+}
+
+fn announce_system(
+    time: Res<Time>,
+    lobby: Res<Lobby>,
+    variant: Res<Variant>,
+    mut discovery: ResMut<Discovery>,
+) {
+    if !lobby.hosting || !lobby.listening() {
+        return;
+    }
+
+    if !discovery.timer.tick(time.delta()).just_finished() {
+        return;
+    }
+
+    let announcement = Announcement {
+        version: PROTOCOL_VERSION,
+        name: player_name(),
+        variant: variant.name.clone(),
+        players: lobby.players(),
+        seats: variant.players,
+        port: DEFAULT_PORT,
+    };
+
+    if let Some(ref socket) = discovery.socket {
+        // a lost announcement gets sent again with the next one
+        if let Ok(bytes) = serde_json::to_vec(&announcement) {
+            let _ = socket.send_to(&bytes, ("255.255.255.255", DISCOVERY_PORT));
+        }
+    }
+}
+
+fn discovery_system(time: Res<Time>, lobby: Res<Lobby>, mut discovery: ResMut<Discovery>) {
+    if lobby.hosting {
+        return;
+    }
+
+    let now = time.seconds_since_startup();
+    let mut announced = Vec::new();
+
+    if let Some(ref socket) = discovery.socket {
+        let mut buffer = [0; 1024];
+
+        while let Ok((length, sender)) = socket.recv_from(&mut buffer) {
+            // games of other versions couldn't be joined anyway
+            match serde_json::from_slice::<Announcement>(&buffer[..length]) {
+                Ok(announcement) if announcement.version == PROTOCOL_VERSION => announced.push((
+                    SocketAddr::new(sender.ip(), announcement.port),
+                    announcement,
+                )),
+                _ => {}
+            }
+        }
+    }
+
+    // the list only gets rebuilt when it changes
+    let expired = discovery
+        .games
+        .iter()
+        .any(|game| now - game.seen > GAME_EXPIRY);
+    if announced.is_empty() && !expired {
+        return;
+    }
+
+    for (address, announcement) in announced {
+        match discovery
+            .games
+            .iter_mut()
+            .find(|game| game.address == address)
+        {
+            Some(game) => {
+                game.announcement = announcement;
+                game.seen = now;
+            }
+            None => discovery.games.push(DiscoveredGame {
+                address,
+                announcement,
+                seen: now,
+            }),
+        }
+    }
+
+    discovery
+        .games
+        .retain(|game| now - game.seen <= GAME_EXPIRY);
+}
+
+fn discovered_list_system(
+    mut commands: Commands,
+    discovery: Res<Discovery>,
+    button_assets: Res<ButtonAssets>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    node_query: Query<(Entity, &NodeElement, Option<&Children>)>,
+    added_query: Query<&NodeElement, Added<NodeElement>>,
+) {
+    let added = added_query
+        .iter()
+        .any(|node_element| matches!(node_element, NodeElement::DiscoveredGames));
+
+    if !added && !discovery.is_changed() {
+        return;
+    }
+
+    let (list, children) = match node_query
+        .iter()
+        .find(|(_, node_element, _)| matches!(node_element, NodeElement::DiscoveredGames))
+    {
+        Some((list, _, children)) => (list, children),
+        None => return,
+    };
+
+    for &child in children.into_iter().flat_map(|children| children.iter()) {
+        commands.entity(child).despawn_recursive();
+    }
+
+    let none = materials.add(Color::NONE.into());
+    let style = TextStyle {
+        font: button_assets.font.clone(),
+        font_size: ENTRY_FONT_SIZE,
+        color: Color::rgb_u8(171, 178, 191),
+    };
+
+    commands.entity(list).with_children(|list| {
+        if discovery.games.is_empty() {
+            list.spawn_bundle(TextBundle {
+                text: Text::with_section(
+                    "Looking for games on the local network...",
+                    style.clone(),
+                    Default::default(),
+                ),
+                ..Default::default()
+            });
+        }
+
+        for game in discovery.games.iter() {
+            let announcement = &game.announcement;
+
+            // clickable without looking like the buttons
+            list.spawn_bundle(NodeBundle {
+                material: none.clone(),
+                ..Default::default()
+            })
+            .insert(Interaction::default())
+            .insert(DiscoveredEntry(game.address))
+            .with_children(|entry| {
+                entry.spawn_bundle(TextBundle {
+                    text: Text::with_section(
+                        format!(
+                            "{}  {}  {}/{} players  {}",
+                            announcement.name,
+                            announcement.variant,
+                            announcement.players,
+                            announcement.seats,
+                            game.address.ip()
+                        ),
+                        style.clone(),
+                        Default::default(),
+                    ),
+                    ..Default::default()
+                });
+            });
+        }
+    });
+}
+
+fn discovered_entry_click_system(
+    interaction_query: Query<(&Interaction, &DiscoveredEntry), Changed<Interaction>>,
+    mut lobby: ResMut<Lobby>,
+) {
+    for (interaction, entry) in interaction_query.iter() {
+        if *interaction == Interaction::Clicked {
+            lobby.connect(&entry.0.to_string());
+        }
+    }
+}
@@ -0,0 +1,359 @@
+use std::{
+    collections::HashMap,
+    fmt::Write,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use bevy::{asset::FileAssetIo, prelude::*};
+
+use crate::{
+    notation::{standings_notation, GameRecord},
+    render,
+    rules::{Cell, Position, Symbol, Topology, Variant},
+    script::RuleScript,
+    theme::{
+        background_color, board_bounds, button_color, cell_position, drawn_cells, strike_through,
+        symbol_color, SYMBOL_SIZE,
+    },
+};
+
+/// Time between two moves of an exported replay.
+const MOVE_SECONDS: f32 = 0.75;
+const STROKE_WIDTH: f32 = 6.0;
+
+const USAGE: &str = "Usage: export-svg <record.ttt> <replay.svg>
+       render-png <record.ttt or position> <board.png> [--lines]";
+
+/// Exports run from the command line without opening a window, `None` when the arguments aren't one.
+/// `export-svg` animates a whole record, `render-png` draws a single position.
+pub fn run_command(args: &[String]) -> Option<Result<(), String>> {
+    match args {
+        [command, record, svg] if command == "export-svg" => {
+            Some(export_svg(Path::new(record), Path::new(svg)))
+        }
+        [command, source, png] if command == "render-png" => {
+            Some(render::export_png(source, Path::new(png), false))
+        }
+        [command, source, png, lines] if command == "render-png" && lines == "--lines" => {
+            Some(render::export_png(source, Path::new(png), true))
+        }
+        [command, ..] if command == "export-svg" || command == "render-png" => {
+            Some(Err(USAGE.to_string()))
+        }
+        _ => None,
+    }
+}
+
+/// Folder the asset server reads from, the exports find the variants and sprites there too.
+pub fn assets_folder() -> PathBuf {
+    FileAssetIo::get_root_path().join("assets")
+}
+
+/// Reads a variant and its rule script straight from the assets folder, the asset server needs a running app.
+pub fn load_variant(name: &str) -> Result<(Variant, RuleScript), String> {
+    let assets = assets_folder();
+
+    let entries = fs::read_dir(assets.join("variants"))
+        .map_err(|error| format!("Couldn't read the variants folder: {}", error))?;
+    let variant = entries
+        .filter_map(|entry| fs::read(entry.ok()?.path()).ok())
+        .filter_map(|bytes| ron::de::from_bytes::<Variant>(&bytes).ok())
+        .filter(|variant| variant.validate().is_ok())
+        .find(|variant| variant.name == name)
+        .ok_or_else(|| format!("Unknown variant {}", name))?;
+
+    let mut rule_script = RuleScript::default();
+    if let Some(ref path) = variant.script {
+        let source = fs::read_to_string(assets.join(path))
+            .map_err(|error| format!("Couldn't load the script {}: {}", path, error))?;
+
+        rule_script.load(Some(&source));
+        if let Some(error) = rule_script.error.take() {
+            return Err(error);
+        }
+    }
+
+    Ok((variant, rule_script))
+}
+
+/// Reads a record and replays it, with the positions `GameRecord::replay` returns.
+pub fn read_record(path: &Path) -> Result<(GameRecord, Variant, Vec<Position>), String> {
+    let text = fs::read_to_string(path)
+        .map_err(|error| format!("Couldn't read {}: {}", path.display(), error))?;
+    let record =
+        GameRecord::parse(&text).map_err(|error| format!("{}: {}", path.display(), error))?;
+
+    let (variant, mut rule_script) = load_variant(&record.variant)?;
+    let (_, positions) = record
+        .replay(&variant, &mut rule_script)
+        .map_err(|error| format!("{}: {}", path.display(), error))?;
+
+    Ok((record, variant, positions))
+}
+
+pub fn export_svg(record_path: &Path, svg_path: &Path) -> Result<(), String> {
+    let (record, variant, positions) = read_record(record_path)?;
+
+    fs::write(svg_path, replay_svg(&variant, &record, &positions))
+        .map_err(|error| format!("Couldn't write {}: {}", svg_path.display(), error))
+}
+
+fn hex_color(color: Color) -> String {
+    let channel = |value: f32| (value * 255.0).round() as u8;
+
+    format!(
+        "#{:02x}{:02x}{:02x}",
+        channel(color.r()),
+        channel(color.g()),
+        channel(color.b())
+    )
+}
+
+/// Position on the board flipped for SVG, where y goes down.
+fn svg_position(topology: &Topology, cell: Cell) -> Vec2 {
+    let position = cell_position(topology, cell);
+
+    Vec2::new(position.x, -position.y)
+}
+
+/// `text` made safe to put in SVG text or a quoted attribute value.
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn seconds(ply: usize) -> String {
+    format!("{}s", ply as f32 * MOVE_SECONDS)
+}
+
+/// Outline of an empty cell, or the mark of a symbol, centered on `center`.
+fn shape(svg: &mut String, symbol: Symbol, topology: &Topology, center: Vec2) {
+    let color = hex_color(symbol_color(symbol));
+    let half = SYMBOL_SIZE / 2.0;
+    // marks are drawn a bit inside their cell
+    let mark = half * 0.6;
+    let (x, y) = (center.x, center.y);
+
+    let _ = match (symbol, topology) {
+        (Symbol::Empty, Topology::Hex { .. }) => {
+            let corners = (0..6)
+                .map(|corner| {
+                    let angle = (60.0 * corner as f32 + 30.0).to_radians();
+                    format!("{},{}", x + half * angle.cos(), y + half * angle.sin())
+                })
+                .collect::<Vec<_>>();
+
+            write!(
+                svg,
+                r#"<polygon points="{}" fill="none" stroke="{}"/>"#,
+                corners.join(" "),
+                color
+            )
+        }
+        (Symbol::Empty, _) => write!(
+            svg,
+            r#"<rect x="{}" y="{}" width="{}" height="{}" fill="none" stroke="{}"/>"#,
+            x - half,
+            y - half,
+            SYMBOL_SIZE,
+            SYMBOL_SIZE,
+            color
+        ),
+        (Symbol::X, _) => write!(
+            svg,
+            r#"<path d="M{} {}L{} {}M{} {}L{} {}" stroke="{}"/>"#,
+            x - mark,
+            y - mark,
+            x + mark,
+            y + mark,
+            x + mark,
+            y - mark,
+            x - mark,
+            y + mark,
+            color
+        ),
+        (Symbol::O, _) => write!(
+            svg,
+            r#"<circle cx="{}" cy="{}" r="{}" stroke="{}"/>"#,
+            x, y, mark, color
+        ),
+        (Symbol::Triangle, _) => write!(
+            svg,
+            r#"<polygon points="{},{} {},{} {},{}" stroke="{}"/>"#,
+            x,
+            y - mark,
+            x + mark,
+            y + mark,
+            x - mark,
+            y + mark,
+            color
+        ),
+        (Symbol::Square, _) => write!(
+            svg,
+            r#"<rect x="{}" y="{}" width="{}" height="{}" stroke="{}"/>"#,
+            x - mark,
+            y - mark,
+            2.0 * mark,
+            2.0 * mark,
+            color
+        ),
+    };
+}
+
+/// Animated SVG of a game, the symbols appear move after move and the winning lines get struck through.
+/// `positions` are the ones `GameRecord::replay` returns, the first one being the start of the game.
+pub fn replay_svg(variant: &Variant, record: &GameRecord, positions: &[Position]) -> String {
+    let topology = variant.topology;
+
+    // unbounded boards are drawn around the symbols of the whole game
+    let cells = drawn_cells(
+        variant,
+        positions
+            .iter()
+            .flat_map(|position| position.symbols.keys().copied()),
+    );
+    let centers = cells
+        .iter()
+        .map(|&cell| svg_position(&topology, cell))
+        .collect::<Vec<_>>();
+
+    // flipped like the positions
+    let (low, high) = board_bounds(&topology, &cells);
+    let min = Vec2::new(low.x, -high.y);
+    let size = high - low;
+
+    let mut svg = String::new();
+    let _ = writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="{} {} {} {}" width="{}" height="{}">"#,
+        min.x, min.y, size.x, size.y, size.x, size.y
+    );
+    let _ = writeln!(
+        svg,
+        "<title>{} {} {}</title>",
+        escape(&variant.to_string()),
+        escape(&record.date),
+        escape(&standings_notation(&record.result))
+    );
+    let _ = writeln!(
+        svg,
+        r#"<rect x="{}" y="{}" width="{}" height="{}" fill="{}"/>"#,
+        min.x,
+        min.y,
+        size.x,
+        size.y,
+        hex_color(background_color())
+    );
+
+    let _ = writeln!(svg, r#"<g stroke-width="2">"#);
+    for &center in centers.iter() {
+        shape(&mut svg, Symbol::Empty, &topology, center);
+        svg.push('\n');
+    }
+    let _ = writeln!(svg, "</g>");
+
+    // every stretch of time a symbol stays on a cell, scripts can flip or remove symbols
+    let mut shown = HashMap::<Cell, (Symbol, usize)>::new();
+    let mut stretches = Vec::new();
+    for (ply, position) in positions.iter().enumerate() {
+        shown.retain(|cell, (symbol, start)| {
+            let kept = position.symbols.get(cell) == Some(symbol);
+            if !kept {
+                stretches.push((*cell, *symbol, *start, Some(ply)));
+            }
+
+            kept
+        });
+
+        for (&cell, &symbol) in position.symbols.iter() {
+            shown.entry(cell).or_insert((symbol, ply));
+        }
+    }
+    stretches.extend(
+        shown
+            .into_iter()
+            .map(|(cell, (symbol, start))| (cell, symbol, start, None)),
+    );
+    stretches.sort_unstable_by_key(|&(cell, _, start, _)| (start, cell));
+
+    let _ = writeln!(
+        svg,
+        r#"<g fill="none" stroke-width="{}" stroke-linecap="round">"#,
+        STROKE_WIDTH
+    );
+    for (cell, symbol, start, end) in stretches {
+        // the symbols of a set up position are there from the start
+        let _ = write!(
+            svg,
+            r#"<g visibility="{}">"#,
+            if start == 0 { "visible" } else { "hidden" }
+        );
+        if start > 0 {
+            let _ = write!(
+                svg,
+                r#"<set attributeName="visibility" to="visible" begin="{}" fill="freeze"/>"#,
+                seconds(start)
+            );
+        }
+        if let Some(end) = end {
+            let _ = write!(
+                svg,
+                r#"<set attributeName="visibility" to="hidden" begin="{}" fill="freeze"/>"#,
+                seconds(end)
+            );
+        }
+
+        shape(&mut svg, symbol, &topology, svg_position(&topology, cell));
+        let _ = writeln!(svg, "</g>");
+    }
+    let _ = writeln!(svg, "</g>");
+
+    // struck through from the move completing them, drawn from one end to the other
+    let last = positions.last().map(|position| &position.symbols);
+    for (first, last) in last.map_or_else(Vec::new, |board| variant.winning_lines(board)) {
+        let completed = positions
+            .iter()
+            .position(|position| {
+                variant
+                    .winning_lines(&position.symbols)
+                    .contains(&(first, last))
+            })
+            .unwrap_or_default();
+
+        let (from, to) = strike_through(&topology, (first, last));
+
+        let _ = writeln!(
+            svg,
+            concat!(
+                r#"<line x1="{}" y1="{}" x2="{}" y2="{}" stroke="{}" stroke-width="{}" stroke-linecap="round" "#,
+                r#"pathLength="1" stroke-dasharray="1" stroke-dashoffset="1">"#,
+                r#"<animate attributeName="stroke-dashoffset" to="0" begin="{}" dur="{}s" fill="freeze"/></line>"#
+            ),
+            from.x,
+            -from.y,
+            to.x,
+            -to.y,
+            hex_color(button_color(Interaction::None)),
+            STROKE_WIDTH,
+            seconds(completed),
+            MOVE_SECONDS
+        );
+    }
+
+    let _ = writeln!(svg, "</svg>");
+    svg
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escapes_markup_in_text() {
+        assert_eq!(escape(r#"<X & "O">"#), "&lt;X &amp; &quot;O&quot;&gt;");
+        assert_eq!(escape("Classic 2021.05.02"), "Classic 2021.05.02");
+    }
+}
@@ -0,0 +1,464 @@
+use std::fs;
+
+use bevy::{input::mouse::MouseWheel, prelude::*};
+
+use crate::{
+    archive::{self, ARCHIVE_FILE, LOCAL_OPPONENT},
+    button::{ButtonAssets, ButtonElement, SpawnButton},
+    notation::{standings_notation, GameRecord},
+    record::{PendingRecord, RECORDS_FOLDER},
+    rules::Variant,
+    ui::{status_text, NodeElement, Status, TextElement},
+    variant::Variants,
+    AppState,
+};
+
+/// Entries shown at once, the wheel scrolls through the others.
+const GAME_LIST_ROWS: usize = 12;
+const ENTRY_FONT_SIZE: f32 = 20.0;
+
+pub struct GameListPlugin;
+
+impl Plugin for GameListPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<GameList>()
+            .add_system_set(
+                SystemSet::on_enter(AppState::GameList)
+                    .with_system(scan_games)
+                    .with_system(setup_game_list),
+            )
+            .add_system_set(
+                SystemSet::on_update(AppState::GameList)
+                    .with_system(game_list_system)
+                    .with_system(game_list_scroll_system)
+                    .with_system(game_entry_click_system)
+                    .with_system(game_filter_system),
+            );
+    }
+}
+
+/// A game to replay, from the archive or from a file of the records folder.
+pub struct ListedGame {
+    pub name: String,
+    pub opponent: String,
+    pub record: GameRecord,
+}
+
+#[derive(Clone, Copy)]
+pub enum GameFilter {
+    Opponent,
+    Variant,
+    Result,
+    /// Month the game was played in.
+    Date,
+}
+
+impl GameFilter {
+    const ALL: [GameFilter; 4] = [
+        GameFilter::Opponent,
+        GameFilter::Variant,
+        GameFilter::Result,
+        GameFilter::Date,
+    ];
+
+    fn value(self, game: &ListedGame) -> String {
+        match self {
+            Self::Opponent => game.opponent.clone(),
+            Self::Variant => game.record.variant.clone(),
+            Self::Result => standings_notation(&game.record.result),
+            Self::Date => game.record.date.chars().take(7).collect(),
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Opponent => "Opponent",
+            Self::Variant => "Variant",
+            Self::Result => "Result",
+            Self::Date => "Date",
+        }
+    }
+}
+
+/// The archived and recorded games, the latest first, and the filters picked for them.
+#[derive(Default)]
+pub struct GameList {
+    pub games: Vec<ListedGame>,
+    /// Value each `GameFilter` keeps, `None` keeps every game.
+    pub filters: [Option<String>; 4],
+    pub scroll: usize,
+}
+
+impl GameList {
+    /// Games kept by the filters, with their index in `games`.
+    pub fn shown(&self) -> impl Iterator<Item = (usize, &ListedGame)> {
+        self.games.iter().enumerate().filter(move |(_, game)| {
+            GameFilter::ALL
+                .iter()
+                .zip(self.filters.iter())
+                .all(|(filter, kept)| {
+                    kept.as_ref()
+                        .map_or(true, |kept| filter.value(game) == *kept)
+                })
+        })
+    }
+
+    /// Goes through the values found in the games, then back to all of them.
+    pub fn next_filter(&mut self, filter: GameFilter) {
+        let mut values = self
+            .games
+            .iter()
+            .map(|game| filter.value(game))
+            .collect::<Vec<_>>();
+        values.sort_unstable();
+        values.dedup();
+
+        let kept = &mut self.filters[filter as usize];
+        let next = match kept.as_deref() {
+            Some(kept) => values
+                .iter()
+                .position(|value| value == kept)
+                .map(|index| index + 1),
+            None => Some(0),
+        };
+        *kept = next.and_then(|index| values.get(index)).cloned();
+
+        self.scroll = 0;
+    }
+
+    fn label(&self, filter: GameFilter) -> String {
+        format!(
+            "{}: {}",
+            filter.name(),
+            self.filters[filter as usize].as_deref().unwrap_or("all")
+        )
+    }
+}
+
+/// Index in `GameList::games` of the game an entry opens.
+pub struct GameEntry(pub usize);
+
+fn read_record(text: &str) -> Result<GameRecord, String> {
+    GameRecord::parse(text).map_err(|error| error.to_string())
+}
+
+fn scan_games(mut game_list: ResMut<GameList>, mut status: ResMut<Status>) {
+    game_list.scroll = 0;
+    game_list.games.clear();
+
+    let (archived, skipped) = archive::load();
+    if skipped > 0 {
+        status.0 = Some(format!(
+            "Skipped {} unreadable lines of {}",
+            skipped, ARCHIVE_FILE
+        ));
+    }
+
+    // latest first
+    for (index, game) in archived.iter().enumerate().rev() {
+        match read_record(&game.record) {
+            Ok(record) => game_list.games.push(ListedGame {
+                name: format!("#{}", index + 1),
+                opponent: game.opponent.clone(),
+                record,
+            }),
+            Err(error) => status.0 = Some(format!("Archived game #{}: {}", index + 1, error)),
+        }
+    }
+
+    let paths = fs::read_dir(RECORDS_FOLDER)
+        .map(|entries| {
+            entries
+                .filter_map(|entry| Some(entry.ok()?.path()))
+                .filter(|path| {
+                    path.extension()
+                        .map_or(false, |extension| extension == "ttt")
+                })
+                .collect::<Vec<_>>()
+        })
+        // nothing was saved yet
+        .unwrap_or_default();
+
+    for path in paths {
+        match fs::read_to_string(&path)
+            .map_err(|error| error.to_string())
+            .and_then(|text| read_record(&text))
+        {
+            Ok(record) => game_list.games.push(ListedGame {
+                name: path
+                    .file_stem()
+                    .map(|name| name.to_string_lossy().into_owned())
+                    .unwrap_or_default(),
+                // records saved with Ctrl+S come from games on this computer
+                opponent: LOCAL_OPPONENT.to_string(),
+                record,
+            }),
+            Err(error) => status.0 = Some(format!("{}: {}", path.display(), error)),
+        }
+    }
+
+    // the sort is stable, the archive keeps its order within a day
+    game_list
+        .games
+        .sort_by(|a, b| b.record.date.cmp(&a.record.date));
+}
+
+fn setup_game_list(
+    mut commands: Commands,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    button_assets: Res<ButtonAssets>,
+) {
+    let none = materials.add(Color::NONE.into());
+
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                justify_content: JustifyContent::SpaceBetween,
+                align_items: AlignItems::Center,
+                flex_direction: FlexDirection::ColumnReverse,
+                ..Default::default()
+            },
+            material: none.clone(),
+            ..Default::default()
+        })
+        .insert(NodeElement::Root)
+        .with_children(|root| {
+            root.spawn_bundle(TextBundle {
+                text: Text::with_section(
+                    "Games",
+                    TextStyle {
+                        font: button_assets.font.clone(),
+                        font_size: 40.0,
+                        color: Color::WHITE,
+                    },
+                    Default::default(),
+                ),
+                style: Style {
+                    margin: Rect::all(Val::Px(16.0)),
+                    ..Default::default()
+                },
+                ..Default::default()
+            });
+
+            root.spawn_bundle(NodeBundle {
+                style: Style {
+                    size: Size::new(Val::Percent(100.0), Val::Auto),
+                    flex_wrap: FlexWrap::Wrap,
+                    justify_content: JustifyContent::Center,
+                    ..Default::default()
+                },
+                material: none.clone(),
+                ..Default::default()
+            })
+            .with_children(|filters| {
+                for &filter in GameFilter::ALL.iter() {
+                    // clickable without reacting like the buttons
+                    filters
+                        .spawn_bundle(NodeBundle {
+                            style: Style {
+                                margin: Rect::all(Val::Px(4.0)),
+                                padding: Rect::all(Val::Px(4.0)),
+                                ..Default::default()
+                            },
+                            material: button_assets.normal.clone(),
+                            ..Default::default()
+                        })
+                        .insert(Interaction::default())
+                        .insert(filter)
+                        .with_children(|chip| {
+                            chip.spawn_bundle(TextBundle {
+                                text: Text::with_section(
+                                    "",
+                                    TextStyle {
+                                        font: button_assets.font.clone(),
+                                        font_size: ENTRY_FONT_SIZE,
+                                        color: Color::BLACK,
+                                    },
+                                    Default::default(),
+                                ),
+                                ..Default::default()
+                            });
+                        });
+                }
+            });
+
+            root.spawn_bundle(NodeBundle {
+                style: Style {
+                    size: Size::new(Val::Percent(100.0), Val::Auto),
+                    flex_direction: FlexDirection::ColumnReverse,
+                    align_items: AlignItems::FlexStart,
+                    flex_grow: 1.0,
+                    ..Default::default()
+                },
+                material: none,
+                ..Default::default()
+            })
+            .insert(NodeElement::GameList);
+
+            root.spawn_button(&button_assets, ButtonElement::BackToMenu);
+
+            root.spawn_bundle(status_text(&button_assets))
+                .insert(TextElement::Status(None));
+        });
+}
+
+fn game_list_system(
+    mut commands: Commands,
+    game_list: Res<GameList>,
+    button_assets: Res<ButtonAssets>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    node_query: Query<(Entity, &NodeElement, Option<&Children>)>,
+    added_query: Query<&NodeElement, Added<NodeElement>>,
+) {
+    let added = added_query
+        .iter()
+        .any(|node_element| matches!(node_element, NodeElement::GameList));
+
+    if !added && !game_list.is_changed() {
+        return;
+    }
+
+    let (list, children) = match node_query
+        .iter()
+        .find(|(_, node_element, _)| matches!(node_element, NodeElement::GameList))
+    {
+        Some((list, _, children)) => (list, children),
+        None => return,
+    };
+
+    for &child in children.into_iter().flat_map(|children| children.iter()) {
+        commands.entity(child).despawn_recursive();
+    }
+
+    let none = materials.add(Color::NONE.into());
+    let style = TextStyle {
+        font: button_assets.font.clone(),
+        font_size: ENTRY_FONT_SIZE,
+        color: Color::rgb_u8(171, 178, 191),
+    };
+
+    commands.entity(list).with_children(|list| {
+        if game_list.shown().next().is_none() {
+            let message = if game_list.games.is_empty() {
+                "No games yet, finished games are archived and Ctrl+S saves one"
+            } else {
+                "No games match the filters"
+            };
+
+            list.spawn_bundle(TextBundle {
+                text: Text::with_section(message, style.clone(), Default::default()),
+                style: Style {
+                    margin: Rect::all(Val::Px(16.0)),
+                    ..Default::default()
+                },
+                ..Default::default()
+            });
+        }
+
+        for (index, game) in game_list
+            .shown()
+            .skip(game_list.scroll)
+            .take(GAME_LIST_ROWS)
+        {
+            // clickable without looking like the buttons
+            list.spawn_bundle(NodeBundle {
+                style: Style {
+                    margin: Rect {
+                        left: Val::Px(16.0),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                },
+                material: none.clone(),
+                ..Default::default()
+            })
+            .insert(Interaction::default())
+            .insert(GameEntry(index))
+            .with_children(|entry| {
+                entry.spawn_bundle(TextBundle {
+                    text: Text::with_section(
+                        format!(
+                            "{} {}  {}  {}  {}  {} moves",
+                            game.record.date,
+                            game.name,
+                            game.opponent,
+                            game.record.variant,
+                            standings_notation(&game.record.result),
+                            game.record.moves.len()
+                        ),
+                        style.clone(),
+                        Default::default(),
+                    ),
+                    ..Default::default()
+                });
+            });
+        }
+    });
+}
+
+fn game_list_scroll_system(
+    mut mouse_wheel_events: EventReader<MouseWheel>,
+    mut game_list: ResMut<GameList>,
+) {
+    let scroll = mouse_wheel_events
+        .iter()
+        .fold(0.0, |scroll, event| scroll + event.y);
+
+    if scroll == 0.0 {
+        return;
+    }
+
+    let max_scroll = game_list.shown().count().saturating_sub(GAME_LIST_ROWS);
+    // scrolling up goes back to the latest games
+    let scroll = game_list.scroll as f32 - scroll.signum();
+    game_list.scroll = (scroll.max(0.0) as usize).min(max_scroll);
+}
+
+fn game_entry_click_system(
+    interaction_query: Query<(&Interaction, &GameEntry), Changed<Interaction>>,
+    game_list: Res<GameList>,
+    variant_assets: Res<Assets<Variant>>,
+    mut variants: ResMut<Variants>,
+    mut pending_record: ResMut<PendingRecord>,
+    mut status: ResMut<Status>,
+) {
+    for (interaction, entry) in interaction_query.iter() {
+        if *interaction != Interaction::Clicked {
+            continue;
+        }
+
+        if let Some(game) = game_list.games.get(entry.0) {
+            // the record plugin starts the replay once the variant is ready
+            if let Err(error) = pending_record.open_record(
+                game.record.clone(),
+                &variant_assets,
+                &mut variants,
+                AppState::Replay,
+            ) {
+                status.0 = Some(error);
+            }
+        }
+    }
+}
+
+fn game_filter_system(
+    mut game_list: ResMut<GameList>,
+    filter_query: Query<(&Interaction, &GameFilter), Changed<Interaction>>,
+    label_query: Query<(&GameFilter, &Children)>,
+    mut text_query: Query<&mut Text>,
+) {
+    for (interaction, &filter) in filter_query.iter() {
+        if *interaction == Interaction::Clicked {
+            game_list.next_filter(filter);
+        }
+    }
+
+    for (&filter, children) in label_query.iter() {
+        for child in children.iter() {
+            if let Ok(mut text) = text_query.get_mut(*child) {
+                text.sections[0].value = game_list.label(filter);
+            }
+        }
+    }
+}
@@ -0,0 +1,201 @@
+use bevy::prelude::*;
+
+use crate::{
+    button::ButtonElement,
+    coin::CoinToss,
+    net::Remote,
+    rules::{Cell, Move, Position, Standings, Symbol, Variant},
+    symbol::{update_symbols, BoardView, CurrentSymbol, Players, Symbols, SymbolsMaterials},
+    ui::{update_texts, TextElement},
+    AppState,
+};
+
+pub struct HistoryPlugin;
+
+impl Plugin for HistoryPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<History>().add_system_set(
+            SystemSet::on_update(AppState::Game)
+                .with_system(undo_redo_system)
+                .with_system(restore_system),
+        );
+    }
+}
+
+/// Moves of the game and the positions between them, `positions[0]` being the empty board.
+#[derive(Default)]
+pub struct History {
+    pub moves: Vec<Move>,
+    pub positions: Vec<Position>,
+    /// Index of the position on the board, positions after it can be redone.
+    pub cursor: usize,
+    /// Set when the position at `cursor` waits to be put back on the board.
+    pub pending: bool,
+    /// Set when a position is put back on the board, so it isn't resolved a second time.
+    pub restored: bool,
+}
+
+impl History {
+    /// A new game, the empty board is `positions[0]` from the start rather than once it's resolved.
+    pub fn start(variant: &Variant, players: &[Symbol]) -> Self {
+        Self {
+            positions: vec![Position {
+                // the coin picks the first player of random turns games
+                current_symbol: if variant.random_turns {
+                    Symbol::Empty
+                } else {
+                    players[0]
+                },
+                players: players.to_vec(),
+                ..Default::default()
+            }],
+            ..Default::default()
+        }
+    }
+
+    /// Picks a game up at the position after `ply` moves, put on the board once the game starts.
+    pub fn resume(moves: Vec<Move>, positions: Vec<Position>, ply: usize) -> Self {
+        let mut history = Self {
+            moves,
+            positions,
+            // the positions are already resolved
+            restored: true,
+            ..Default::default()
+        };
+        history.go_to(ply.min(history.positions.len() - 1));
+
+        history
+    }
+
+    /// Drops the undone moves, a new move replaces them.
+    pub fn play(&mut self, cell: Cell, symbol: Symbol) {
+        self.moves.truncate(self.cursor);
+        self.positions.truncate(self.cursor + 1);
+
+        self.moves.push(Move { cell, symbol });
+    }
+
+    /// Records the position the last move led to, or the resolved empty board in place of the one
+    /// the game started with.
+    pub fn resolve(&mut self, position: Position) {
+        self.positions.truncate(self.moves.len());
+        self.positions.push(position);
+        self.cursor = self.positions.len() - 1;
+    }
+
+    pub fn can_undo(&self) -> bool {
+        self.cursor > 0
+    }
+
+    pub fn can_redo(&self) -> bool {
+        self.cursor + 1 < self.positions.len()
+    }
+
+    pub fn go_to(&mut self, cursor: usize) {
+        self.cursor = cursor;
+        self.pending = true;
+    }
+}
+
+fn undo_redo_system(
+    keyboard_input: Res<Input<KeyCode>>,
+    interaction_query: Query<(&Interaction, &ButtonElement), (Changed<Interaction>, With<Button>)>,
+    coin_toss: Res<CoinToss>,
+    remote: Res<Remote>,
+    mut history: ResMut<History>,
+) {
+    // both players of a networked game have to stay on the same position
+    if remote.0.is_some() {
+        return;
+    }
+
+    let control =
+        keyboard_input.pressed(KeyCode::LControl) || keyboard_input.pressed(KeyCode::RControl);
+    let shift = keyboard_input.pressed(KeyCode::LShift) || keyboard_input.pressed(KeyCode::RShift);
+
+    let mut undo = control && !shift && keyboard_input.just_pressed(KeyCode::Z);
+    let mut redo = control
+        && (keyboard_input.just_pressed(KeyCode::Y)
+            || shift && keyboard_input.just_pressed(KeyCode::Z));
+
+    for (interaction, button) in interaction_query.iter() {
+        if *interaction == Interaction::Clicked {
+            match button {
+                ButtonElement::Undo => undo = true,
+                ButtonElement::Redo => redo = true,
+                _ => {}
+            }
+        }
+    }
+
+    // the coin is about to pick the side to move, the position isn't settled yet
+    if coin_toss.is_spinning() {
+        return;
+    }
+
+    if undo && history.can_undo() {
+        let cursor = history.cursor - 1;
+        history.go_to(cursor);
+    } else if redo && history.can_redo() {
+        let cursor = history.cursor + 1;
+        history.go_to(cursor);
+    }
+}
+
+fn restore_system(
+    mut commands: Commands,
+    symbols_query: Query<Entity, With<Symbol>>,
+    symbols_materials: Res<SymbolsMaterials>,
+    mut text_query: Query<(Entity, &mut TextElement)>,
+    variant: Res<Variant>,
+    mut history: ResMut<History>,
+    mut symbols: ResMut<Symbols>,
+    mut current_symbol: ResMut<CurrentSymbol>,
+    mut players: ResMut<Players>,
+    mut standings: ResMut<Standings>,
+    mut coin_toss: ResMut<CoinToss>,
+    mut board_view: ResMut<BoardView>,
+) {
+    if !history.pending {
+        return;
+    }
+
+    let position = history.positions[history.cursor].clone();
+    history.pending = false;
+    history.restored = true;
+
+    symbols.0 = position.symbols;
+    current_symbol.0 = position.current_symbol;
+    players.0 = position.players;
+    *standings = position.standings;
+    board_view.revealed = position.revealed;
+    coin_toss.rewind(position.flips);
+
+    // finished games have handed every player a rank
+    let finished = players.0.is_empty();
+
+    if current_symbol.0 == Symbol::Empty && variant.random_turns && !finished {
+        coin_toss.start();
+    }
+
+    board_view.follow(&variant, &current_symbol);
+
+    // going back from a finished game clears its result, going forward to one shows it again
+    update_texts(&mut text_query, &current_symbol, true);
+    if finished {
+        for (_, mut text_element) in text_query.iter_mut() {
+            if let TextElement::Winner(ref mut winner) = *text_element {
+                *winner = Some(standings.clone());
+            }
+        }
+    }
+
+    update_symbols(
+        &mut commands,
+        &symbols_query,
+        &symbols_materials,
+        &symbols,
+        &variant,
+        &board_view,
+    );
+}
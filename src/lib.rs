@@ -0,0 +1,17 @@
+//! Rules, game records and their signatures, board exports, the network protocol with its
+//! transports and the server's rooms, none of which need a window. Web pages have no files to
+//! export nor clients to listen for, wasm32 builds leave the exports and the server out.
+
+#[cfg(not(target_arch = "wasm32"))]
+pub mod export;
+pub mod notation;
+pub mod protocol;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod render;
+pub mod rules;
+pub mod script;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod server;
+pub mod signature;
+pub mod theme;
+pub mod transport;
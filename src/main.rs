@@ -1,33 +1,96 @@
 #![windows_subsystem = "windows"]
-use std::cmp;
-
-use bevy::prelude::*;
+use archive::ArchivePlugin;
+use bevy::{prelude::*, render::camera::Camera};
+use bevy_tic_tac_toe::{export, notation, protocol, rules, script, signature, theme};
+use camera::{cursor_to_world, CameraPlugin};
+use chat::ChatPlugin;
+use coin::{CoinPlugin, CoinToss};
+use correspondence::{Correspondence, CorrespondencePlugin};
+use discovery::DiscoveryPlugin;
+use game_list::GameListPlugin;
+use history::{History, HistoryPlugin};
+use move_list::MoveListPlugin;
+use net::{NetPlugin, Remote};
+use offer::OfferPlugin;
+use protocol::Message;
+use record::RecordPlugin;
+use replay::ReplayPlugin;
+use rules::{Cell, Position, Standings, Symbol, Variant};
+use script::RuleScript;
+use session::SessionPlugin;
+use setup::SetupPlugin;
 use symbol::{
-    update_symbols, CurrentSymbol, Symbol, SymbolIndex, SymbolPlugin, Symbols, SymbolsMaterials,
-    SYMBOL_SIZE,
+    cell_at, update_symbols, BoardView, CurrentSymbol, Players, SymbolPlugin, Symbols,
+    SymbolsMaterials,
 };
 use ui::{update_texts, TextElement, UIPlugin};
+use variant::VariantPlugin;
 
+mod archive;
 mod button;
+mod camera;
+mod chat;
+mod coin;
+mod correspondence;
+mod discovery;
+mod game_list;
+mod history;
+mod move_list;
+mod net;
+mod offer;
+mod record;
+mod replay;
+mod session;
+mod setup;
 mod symbol;
 mod ui;
+mod variant;
 
 struct MainCamera;
 
+/// A move of the current symbol on a cell, already checked against the rules.
 #[derive(Debug, Clone, Copy)]
-pub enum WinningEvent {
-    X,
-    O,
-    Draw,
-}
+pub struct MoveEvent(pub Cell);
+
+/// Sent with the final standings once the game is over.
+#[derive(Debug, Clone)]
+pub struct WinningEvent(pub Standings);
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
-enum AppState {
+pub enum AppState {
     Menu,
     Game,
+    /// Pushed over `Game` between the turns of a blind game.
+    HandOver,
+    /// Pushed over `Game` while looking back at an earlier position from the move list.
+    Review,
+    /// The recorded games, to pick one to replay.
+    GameList,
+    /// Plays a recorded game back, move by move.
+    Replay,
+    /// Hosting a networked game, or joining one, until the other player is there.
+    Lobby,
+}
+
+/// Systems the plugins order their own against.
+#[derive(SystemLabel, Debug, Clone, PartialEq, Eq, Hash)]
+pub enum GameSystem {
+    /// Plays the moves sent as `MoveEvent`s.
+    PlayMove,
 }
 
 fn main() {
+    // exports run without a window
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    if let Some(result) = export::run_command(&args) {
+        if let Err(error) = result {
+            eprintln!("{}", error);
+            std::process::exit(1);
+        }
+
+        return;
+    }
+
     App::build()
         .insert_resource(Msaa { samples: 4 })
         .insert_resource(WindowDescriptor {
@@ -37,20 +100,42 @@ fn main() {
             resizable: false,
             ..Default::default()
         })
-        .insert_resource(ClearColor(Color::rgb_u8(40, 44, 52)))
+        .insert_resource(ClearColor(theme::background_color()))
         .add_state(AppState::Menu)
         .add_plugins(DefaultPlugins)
         .add_plugin(UIPlugin)
         .add_plugin(SymbolPlugin)
+        .add_plugin(CoinPlugin)
+        .add_plugin(CameraPlugin)
+        .add_plugin(VariantPlugin)
+        .add_plugin(HistoryPlugin)
+        .add_plugin(MoveListPlugin)
+        .add_plugin(RecordPlugin)
+        .add_plugin(SetupPlugin)
+        .add_plugin(SessionPlugin)
+        .add_plugin(GameListPlugin)
+        .add_plugin(ReplayPlugin)
+        .add_plugin(ArchivePlugin)
+        .add_plugin(NetPlugin)
+        .add_plugin(DiscoveryPlugin)
+        .add_plugin(ChatPlugin)
+        .add_plugin(OfferPlugin)
+        .add_plugin(CorrespondencePlugin)
         .add_startup_system(setup)
         .add_system_set(SystemSet::on_enter(AppState::Game).with_system(setup_game))
+        .add_system_set(SystemSet::on_resume(AppState::Game).with_system(setup_game))
+        .add_system_set(SystemSet::on_exit(AppState::Game).with_system(despawn_symbols))
+        .add_system_set(SystemSet::on_exit(AppState::Replay).with_system(despawn_symbols))
+        .add_system_set(SystemSet::on_update(AppState::HandOver).with_system(hand_over_system))
         .add_system_set(
             SystemSet::on_update(AppState::Game)
                 .with_system(solution_detection_system)
                 .with_system(handle_winning_events_system)
-                .with_system(mouse_input_system),
+                .with_system(mouse_input_system)
+                .with_system(play_move_system.label(GameSystem::PlayMove)),
         )
         .add_event::<WinningEvent>()
+        .add_event::<MoveEvent>()
         .run();
 }
 
@@ -66,60 +151,124 @@ fn setup_game(
     symbols_query: Query<Entity, With<Symbol>>,
     symbols_materials: Res<SymbolsMaterials>,
     symbols: ResMut<Symbols>,
+    variant: Res<Variant>,
+    current_symbol: Res<CurrentSymbol>,
+    mut board_view: ResMut<BoardView>,
 ) {
-    update_symbols(&mut commands, &symbols_query, &symbols_materials, &symbols);
+    board_view.follow(&variant, &current_symbol);
+
+    update_symbols(
+        &mut commands,
+        &symbols_query,
+        &symbols_materials,
+        &symbols,
+        &variant,
+        &board_view,
+    );
+}
+
+fn despawn_symbols(mut commands: Commands, symbols_query: Query<Entity, With<Symbol>>) {
+    for entity in symbols_query.iter() {
+        commands.entity(entity).despawn_recursive()
+    }
 }
 
 fn mouse_input_system(
     mut commands: Commands,
     windows: Res<Windows>,
     mouse_button_input: Res<Input<MouseButton>>,
-    camera_query: Query<&Transform, With<MainCamera>>,
-    query: Query<(&GlobalTransform, &SymbolIndex), With<Symbol>>,
+    camera_query: Query<(&Camera, &GlobalTransform), With<MainCamera>>,
     symbols_query: Query<Entity, With<Symbol>>,
     symbols_materials: Res<SymbolsMaterials>,
-    mut text_query: Query<(Entity, &mut TextElement)>,
-    mut current_symbol: ResMut<CurrentSymbol>,
-    mut symbols: ResMut<Symbols>,
+    current_symbol: Res<CurrentSymbol>,
+    symbols: Res<Symbols>,
+    variant: Res<Variant>,
+    mut board_view: ResMut<BoardView>,
+    mut rule_script: ResMut<RuleScript>,
+    history: Res<History>,
+    remote: Res<Remote>,
+    correspondence: Res<Correspondence>,
+    mut move_events: EventWriter<MoveEvent>,
+    interaction_query: Query<&Interaction>,
 ) {
+    // clicks on the buttons and the move list don't reach the board behind them
+    if interaction_query
+        .iter()
+        .any(|interaction| *interaction != Interaction::None)
+    {
+        return;
+    }
+
+    // in networked games the other player's turns are played on the other computer,
+    // and nobody plays while a player is away or an offer waits for an answer
+    if let Some(ref remote_game) = remote.0 {
+        if current_symbol.0 != remote_game.symbol
+            || remote_game.gap.is_some()
+            || remote_game.offer.is_some()
+        {
+            return;
+        }
+    }
+
+    // games played by file take a single move, from the position of the file
+    if let Some(ref correspondence_game) = correspondence.0 {
+        if current_symbol.0 != correspondence_game.symbol
+            || history.cursor != correspondence_game.record.moves.len()
+        {
+            return;
+        }
+    }
+
     if current_symbol.0 != Symbol::Empty {
         if mouse_button_input.just_pressed(MouseButton::Left) {
-            for (symbol_global_transform, symbol_index) in query.iter() {
-                let window = windows.get_primary().unwrap();
-
-                if let Some(cursor_pos) = window.cursor_position() {
-                    let window_size = Vec2::new(window.width(), window.height());
-                    let p = cursor_pos - window_size / 2.0;
-
-                    let camera_transform = camera_query.single().unwrap();
-                    let world_pos = camera_transform.compute_matrix() * p.extend(0.0).extend(1.0);
-
-                    let position = symbol_global_transform.translation;
-                    let ui_position = Vec2::new(position.x, position.y);
-                    let extents = Vec2::splat(SYMBOL_SIZE) / 2.0;
-                    let min = ui_position - extents;
-                    let max = ui_position + extents;
-
-                    // if the current cursor position is within the bounds of the node, consider it for clicking
-                    if (min.x..max.x).contains(&world_pos.x)
-                        && (min.y..max.y).contains(&world_pos.y)
-                    {
-                        let symbol = &mut symbols.0[symbol_index.0];
-
-                        if symbol != &Symbol::Empty {
-                            return;
+            let window = windows.get_primary().unwrap();
+
+            let (camera, camera_transform) = camera_query.single().unwrap();
+
+            if let Some(world_pos) = cursor_to_world(window, camera, camera_transform) {
+                // the topology knows the shape of its cells, squares and hexagons alike
+                if let Some(cell) = cell_at(&variant.topology, world_pos) {
+                    if let Some(&symbol) = symbols.0.get(&cell) {
+                        // bumping into a hidden symbol costs nothing but reveals it
+                        if !board_view.shows(cell, symbol) {
+                            board_view
+                                .revealed
+                                .entry(current_symbol.0)
+                                .or_default()
+                                .insert(cell);
+
+                            update_symbols(
+                                &mut commands,
+                                &symbols_query,
+                                &symbols_materials,
+                                &symbols,
+                                &variant,
+                                &board_view,
+                            );
                         }
 
-                        *symbol = current_symbol.0;
+                        return;
+                    }
 
-                        current_symbol.0 = match current_symbol.0 {
-                            Symbol::X => Symbol::O,
-                            Symbol::O => Symbol::X,
-                            Symbol::Empty => unreachable!(),
-                        };
+                    // with gravity the symbol falls down the column
+                    let cell = match variant.landing_cell(&symbols.0, cell) {
+                        Some(cell) => cell,
+                        None => return,
+                    };
 
-                        update_texts(&mut text_query, &current_symbol, false);
-                        update_symbols(&mut commands, &symbols_query, &symbols_materials, &symbols);
+                    if !rule_script.legal_move(&variant, &symbols.0, cell, current_symbol.0) {
+                        return;
+                    }
+
+                    match remote.0 {
+                        // the host checks the move again and sends it back once played
+                        Some(ref remote_game) if !remote_game.host => {
+                            remote_game.send(&Message::Move {
+                                ply: history.cursor,
+                                cell,
+                            })
+                        }
+                        _ => move_events.send(MoveEvent(cell)),
                     }
                 }
             }
@@ -127,61 +276,153 @@ fn mouse_input_system(
     }
 }
 
-fn solution_detection_system(symbols: Res<Symbols>, mut winning_events: EventWriter<WinningEvent>) {
+/// Plays the moves of the current symbol, from this computer or from the other player's.
+fn play_move_system(
+    mut commands: Commands,
+    mut move_events: EventReader<MoveEvent>,
+    symbols_query: Query<Entity, With<Symbol>>,
+    symbols_materials: Res<SymbolsMaterials>,
+    mut text_query: Query<(Entity, &mut TextElement)>,
+    mut current_symbol: ResMut<CurrentSymbol>,
+    mut symbols: ResMut<Symbols>,
+    variant: Res<Variant>,
+    players: Res<Players>,
+    board_view: Res<BoardView>,
+    mut rule_script: ResMut<RuleScript>,
+    mut history: ResMut<History>,
+    remote: Res<Remote>,
+) {
+    for &MoveEvent(cell) in move_events.iter() {
+        if let Some(ref remote_game) = remote.0 {
+            if remote_game.host {
+                remote_game.send(&Message::Move {
+                    ply: history.cursor,
+                    cell,
+                });
+            }
+        }
+
+        history.play(cell, current_symbol.0);
+        rule_script.apply_move(&variant, &mut symbols.0, cell, current_symbol.0);
+
+        // in random turns games the coin picks the next symbol once the move is checked
+        current_symbol.0 = if variant.random_turns {
+            Symbol::Empty
+        } else {
+            players.next_after(current_symbol.0)
+        };
+
+        update_texts(&mut text_query, &current_symbol, false);
+        update_symbols(
+            &mut commands,
+            &symbols_query,
+            &symbols_materials,
+            &symbols,
+            &variant,
+            &board_view,
+        );
+    }
+}
+
+fn hand_over_system(
+    mut state: ResMut<State<AppState>>,
+    mut mouse_button_input: ResMut<Input<MouseButton>>,
+) {
+    if mouse_button_input.just_pressed(MouseButton::Left) {
+        // the click only dismisses the hand over screen, it mustn't reach the board
+        mouse_button_input.reset(MouseButton::Left);
+
+        state.pop().expect("Couldn't leave the HandOver state");
+    }
+}
+
+fn solution_detection_system(
+    symbols: Res<Symbols>,
+    variant: Res<Variant>,
+    mut rule_script: ResMut<RuleScript>,
+    mut players: ResMut<Players>,
+    mut standings: ResMut<Standings>,
+    mut coin_toss: ResMut<CoinToss>,
+    mut current_symbol: ResMut<CurrentSymbol>,
+    mut state: ResMut<State<AppState>>,
+    mut winning_events: EventWriter<WinningEvent>,
+    mut history: ResMut<History>,
+    board_view: Res<BoardView>,
+) {
     if !symbols.is_changed() {
         return;
     }
-    /*
-    012
-    345
-    678
-    */
-    let solutions = [
-        // Lines
-        [0, 1, 2],
-        [3, 4, 5],
-        [6, 7, 8],
-        // Rows
-        [0, 3, 6],
-        [1, 4, 7],
-        [2, 5, 8],
-        // Diagonals
-        [0, 4, 8],
-        [6, 4, 2],
-    ];
-
-    let (mut x, mut o) = (0, 0);
-
-    solutions.iter().for_each(|solution| {
-        let (solution_x, solution_o) =
-            solution
-                .iter()
-                .map(|case| symbols.0[*case])
-                .fold((0, 0), |acc, x| match x {
-                    Symbol::X => (acc.0 + 1, acc.1),
-                    Symbol::O => (acc.0, acc.1 + 1),
-                    Symbol::Empty => acc,
-                });
 
-        x = cmp::max(solution_x, x);
-        o = cmp::max(solution_o, o);
-    });
+    // undone and redone positions were resolved when first played
+    if history.restored {
+        history.restored = false;
+        return;
+    }
+
+    let lines = rule_script.outcome(&variant, &symbols.0);
+    let full = variant.is_full(&symbols.0);
 
-    let empties = symbols
-        .0
-        .iter()
-        .fold(0, |acc, s| acc + (if s == &Symbol::Empty { 1 } else { 0 }));
-
-    winning_events.send(match (x, o, empties) {
-        (3, 3, _) => WinningEvent::Draw,
-        (3, _, _) => WinningEvent::X,
-        (_, 3, _) => WinningEvent::O,
-        (_, _, 0) => WinningEvent::Draw,
-        _ => return,
-    });
+    if rules::resolve(&variant, &lines, full, &mut players.0, &mut standings) {
+        current_symbol.0 = Symbol::Empty;
+        history.resolve(position(
+            &symbols,
+            &current_symbol,
+            &players,
+            &standings,
+            &board_view,
+            &coin_toss,
+        ));
+
+        winning_events.send(WinningEvent(standings.clone()));
+        return;
+    }
+
+    if variant.random_turns {
+        current_symbol.0 = Symbol::Empty;
+        coin_toss.start();
+    }
+
+    history.resolve(position(
+        &symbols,
+        &current_symbol,
+        &players,
+        &standings,
+        &board_view,
+        &coin_toss,
+    ));
+
+    if variant.blind && !symbols.0.is_empty() {
+        state
+            .push(AppState::HandOver)
+            .expect("Couldn't enter the HandOver state");
+    }
+}
+
+fn position(
+    symbols: &Symbols,
+    current_symbol: &CurrentSymbol,
+    players: &Players,
+    standings: &Standings,
+    board_view: &BoardView,
+    coin_toss: &CoinToss,
+) -> Position {
+    Position {
+        symbols: symbols.0.clone(),
+        current_symbol: current_symbol.0,
+        players: players.0.clone(),
+        standings: standings.clone(),
+        revealed: board_view.revealed.clone(),
+        flips: coin_toss.flips.len(),
+    }
 }
 
 fn handle_winning_events_system(
+    mut commands: Commands,
+    symbols_query: Query<Entity, With<Symbol>>,
+    symbols_materials: Res<SymbolsMaterials>,
+    symbols: ResMut<Symbols>,
+    variant: Res<Variant>,
+    mut board_view: ResMut<BoardView>,
     mut current_symbol: ResMut<CurrentSymbol>,
     mut winning_events: EventReader<WinningEvent>,
     mut text_query: Query<&mut TextElement, With<Text>>,
@@ -189,9 +430,22 @@ fn handle_winning_events_system(
     for event in winning_events.iter() {
         (*current_symbol).0 = Symbol::Empty;
 
+        if board_view.viewer.is_some() {
+            board_view.follow(&variant, &current_symbol);
+
+            update_symbols(
+                &mut commands,
+                &symbols_query,
+                &symbols_materials,
+                &symbols,
+                &variant,
+                &board_view,
+            );
+        }
+
         for mut text_element in text_query.iter_mut() {
             match *text_element {
-                TextElement::Winner(ref mut winner) => *winner = Some(*event),
+                TextElement::Winner(ref mut winner) => *winner = Some(event.0.clone()),
                 _ => {}
             }
         }
@@ -0,0 +1,307 @@
+use bevy::{input::mouse::MouseWheel, prelude::*};
+
+use crate::{
+    button::ButtonAssets,
+    history::History,
+    rules::{Symbol, Topology, Variant},
+    symbol::{
+        cell_position, update_symbols, BoardView, Symbols, SymbolsMaterials, SPACE_SIZE,
+        SYMBOL_SIZE,
+    },
+    ui::{NodeElement, PANEL_WIDTH},
+    AppState,
+};
+
+/// Entries shown at once, the wheel scrolls through the others.
+const MOVE_LIST_ROWS: usize = 8;
+const ENTRY_FONT_SIZE: f32 = 20.0;
+
+pub struct MoveListPlugin;
+
+impl Plugin for MoveListPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<MoveList>()
+            .add_system_set(
+                SystemSet::on_enter(AppState::Game).with_system(spawn_coordinate_labels),
+            )
+            .add_system_set(
+                SystemSet::on_exit(AppState::Game).with_system(despawn_coordinate_labels),
+            )
+            .add_system_set(
+                SystemSet::on_update(AppState::Game)
+                    .with_system(move_list_system)
+                    .with_system(move_list_scroll_system)
+                    .with_system(move_entry_click_system),
+            )
+            .add_system_set(
+                SystemSet::on_update(AppState::Review)
+                    .with_system(move_list_system)
+                    .with_system(move_list_scroll_system)
+                    .with_system(move_entry_click_system)
+                    .with_system(review_system),
+            );
+    }
+}
+
+/// Scroll of the move list, and the position picked for review.
+#[derive(Default)]
+pub struct MoveList {
+    pub scroll: usize,
+    pub reviewed: usize,
+}
+
+/// Index in `History::positions` of the position a move led to.
+pub struct MoveEntry(pub usize);
+
+pub struct CoordinateLabel;
+
+fn spawn_coordinate_labels(
+    mut commands: Commands,
+    variant: Res<Variant>,
+    button_assets: Res<ButtonAssets>,
+) {
+    let (width, height) = match variant.topology {
+        Topology::Square { width, height } => (width, height),
+        // hex boards have no straight edge for all the files, unbounded ones no edge at all
+        _ => return,
+    };
+
+    let step = SYMBOL_SIZE + SPACE_SIZE;
+    let style = TextStyle {
+        font: button_assets.font.clone(),
+        font_size: SYMBOL_SIZE / 2.0,
+        color: Color::rgb_u8(171, 178, 191),
+    };
+    let alignment = TextAlignment {
+        vertical: VerticalAlign::Center,
+        horizontal: HorizontalAlign::Center,
+    };
+
+    let files = (0..width).map(|column| ((column, height - 1), -Vec2::Y));
+    let ranks = (0..height).map(|row| ((0, row), -Vec2::X));
+
+    for (cell, side) in files.chain(ranks) {
+        let name = variant.topology.notation(cell);
+        // files are the letters, ranks the digits
+        let label = if side.x == 0.0 {
+            name.trim_end_matches(|c: char| c.is_ascii_digit())
+        } else {
+            name.trim_start_matches(|c: char| c.is_ascii_lowercase())
+        };
+
+        commands
+            .spawn_bundle(Text2dBundle {
+                text: Text::with_section(label, style.clone(), alignment),
+                transform: Transform::from_translation(
+                    (cell_position(&variant.topology, cell) + side * step).extend(0.0),
+                ),
+                ..Default::default()
+            })
+            .insert(CoordinateLabel);
+    }
+}
+
+fn despawn_coordinate_labels(
+    mut commands: Commands,
+    label_query: Query<Entity, With<CoordinateLabel>>,
+) {
+    for entity in label_query.iter() {
+        commands.entity(entity).despawn_recursive()
+    }
+}
+
+fn move_list_system(
+    mut commands: Commands,
+    state: Res<State<AppState>>,
+    history: Res<History>,
+    variant: Res<Variant>,
+    button_assets: Res<ButtonAssets>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut move_list: ResMut<MoveList>,
+    node_query: Query<(Entity, &NodeElement, Option<&Children>)>,
+    added_query: Query<&NodeElement, Added<NodeElement>>,
+) {
+    let added = added_query
+        .iter()
+        .any(|node_element| matches!(node_element, NodeElement::MoveList));
+
+    // the latest move stays in sight as the game goes on
+    if history.is_changed() {
+        move_list.scroll = history.moves.len().saturating_sub(MOVE_LIST_ROWS);
+    }
+
+    if !added && !history.is_changed() && !move_list.is_changed() && !state.is_changed() {
+        return;
+    }
+
+    let (list, children) = match node_query
+        .iter()
+        .find(|(_, node_element, _)| matches!(node_element, NodeElement::MoveList))
+    {
+        Some((list, _, children)) => (list, children),
+        None => return,
+    };
+
+    for &child in children.into_iter().flat_map(|children| children.iter()) {
+        commands.entity(child).despawn_recursive();
+    }
+
+    let reviewing = *state.current() == AppState::Review;
+    let none = materials.add(Color::NONE.into());
+
+    commands.entity(list).with_children(|list| {
+        if reviewing {
+            list.spawn_bundle(TextBundle {
+                text: Text::with_section(
+                    "Esc to go back",
+                    TextStyle {
+                        font: button_assets.font.clone(),
+                        font_size: ENTRY_FONT_SIZE,
+                        color: Color::WHITE,
+                    },
+                    Default::default(),
+                ),
+                ..Default::default()
+            });
+        }
+
+        for (index, played) in history
+            .moves
+            .iter()
+            .enumerate()
+            .skip(move_list.scroll)
+            .take(MOVE_LIST_ROWS)
+        {
+            let position = index + 1;
+            // the position on the board stands out, the one under review even more
+            let color = if reviewing && position == move_list.reviewed {
+                Color::rgb_u8(81, 183, 194)
+            } else if position == history.cursor {
+                Color::WHITE
+            } else {
+                Color::rgb_u8(171, 178, 191)
+            };
+
+            // clickable without looking like the buttons
+            list.spawn_bundle(NodeBundle {
+                style: Style {
+                    margin: Rect {
+                        left: Val::Px(16.0),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                },
+                material: none.clone(),
+                ..Default::default()
+            })
+            .insert(Interaction::default())
+            .insert(MoveEntry(position))
+            .with_children(|entry| {
+                entry.spawn_bundle(TextBundle {
+                    text: Text::with_section(
+                        format!(
+                            "{}. {} {}",
+                            position,
+                            played.symbol.short_name(),
+                            variant.topology.notation(played.cell)
+                        ),
+                        TextStyle {
+                            font: button_assets.font.clone(),
+                            font_size: ENTRY_FONT_SIZE,
+                            color,
+                        },
+                        Default::default(),
+                    ),
+                    ..Default::default()
+                });
+            });
+        }
+    });
+}
+
+fn move_list_scroll_system(
+    windows: Res<Windows>,
+    history: Res<History>,
+    mut mouse_wheel_events: EventReader<MouseWheel>,
+    mut move_list: ResMut<MoveList>,
+) {
+    let scroll = mouse_wheel_events
+        .iter()
+        .fold(0.0, |scroll, event| scroll + event.y);
+
+    let window = windows.get_primary().unwrap();
+    let over_panel = window
+        .cursor_position()
+        .map_or(false, |cursor| cursor.x > window.width() - PANEL_WIDTH);
+
+    if scroll == 0.0 || !over_panel {
+        return;
+    }
+
+    let max_scroll = history.moves.len().saturating_sub(MOVE_LIST_ROWS);
+    // scrolling up goes back to the first moves
+    let scroll = move_list.scroll as f32 - scroll.signum();
+    move_list.scroll = (scroll.max(0.0) as usize).min(max_scroll);
+}
+
+fn move_entry_click_system(
+    mut state: ResMut<State<AppState>>,
+    history: Res<History>,
+    mut move_list: ResMut<MoveList>,
+    interaction_query: Query<(&Interaction, &MoveEntry), Changed<Interaction>>,
+) {
+    for (interaction, entry) in interaction_query.iter() {
+        if *interaction != Interaction::Clicked {
+            continue;
+        }
+
+        let reviewing = *state.current() == AppState::Review;
+
+        // the position on the board is the one the game goes on from
+        if entry.0 == history.cursor {
+            if reviewing {
+                state.pop().expect("Couldn't leave the Review state");
+            }
+        } else {
+            move_list.reviewed = entry.0;
+
+            if !reviewing {
+                state
+                    .push(AppState::Review)
+                    .expect("Couldn't enter the Review state");
+            }
+        }
+    }
+}
+
+fn review_system(
+    mut commands: Commands,
+    mut state: ResMut<State<AppState>>,
+    keyboard_input: Res<Input<KeyCode>>,
+    symbols_query: Query<Entity, With<Symbol>>,
+    symbols_materials: Res<SymbolsMaterials>,
+    history: Res<History>,
+    move_list: Res<MoveList>,
+    variant: Res<Variant>,
+    board_view: Res<BoardView>,
+) {
+    if keyboard_input.just_pressed(KeyCode::Escape) {
+        state.pop().expect("Couldn't leave the Review state");
+        return;
+    }
+
+    if !move_list.is_changed() {
+        return;
+    }
+
+    if let Some(position) = history.positions.get(move_list.reviewed) {
+        update_symbols(
+            &mut commands,
+            &symbols_query,
+            &symbols_materials,
+            &Symbols(position.symbols.clone()),
+            &variant,
+            &board_view,
+        );
+    }
+}
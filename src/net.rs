@@ -0,0 +1,1024 @@
+use std::{
+    io,
+    net::{TcpListener, TcpStream, ToSocketAddrs},
+    sync::{
+        mpsc::{self, Receiver},
+        Mutex,
+    },
+    thread,
+    time::Duration,
+};
+
+use bevy::prelude::*;
+use rand::random;
+
+use crate::{
+    button::{ButtonAssets, ButtonElement, SpawnButton},
+    chat::Chat,
+    coin::CoinToss,
+    history::History,
+    notation::{today, ChatLine, GameRecord},
+    offer::{carry_out, PendingOffer},
+    protocol::{
+        chat_text, player_name, Connection, Message, DEFAULT_PORT, GRACE_SECONDS, PROTOCOL_VERSION,
+        WEB_SOCKET_SCHEME,
+    },
+    record::PendingRecord,
+    rules::{Symbol, Variant},
+    script::RuleScript,
+    symbol::{CurrentSymbol, Players, Symbols, TurnOrder},
+    ui::{status_text, NodeElement, Status, TextElement},
+    variant::Variants,
+    AppState, GameSystem, MoveEvent, WinningEvent,
+};
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+/// Time between two attempts to get back to a server.
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+pub struct NetPlugin;
+
+impl Plugin for NetPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<Remote>()
+            .init_resource::<Lobby>()
+            .add_system_set(SystemSet::on_enter(AppState::Menu).with_system(leave_remote_game))
+            .add_system_set(SystemSet::on_update(AppState::Menu).with_system(lobby_button_system))
+            .add_system_set(SystemSet::on_enter(AppState::Lobby).with_system(setup_lobby))
+            .add_system_set(
+                SystemSet::on_update(AppState::Lobby)
+                    .with_system(address_input_system)
+                    .with_system(watch_button_system)
+                    .with_system(lobby_system)
+                    .with_system(lobby_text_system),
+            )
+            .add_system_set(SystemSet::on_exit(AppState::Lobby).with_system(close_lobby))
+            .add_system_set(
+                SystemSet::on_update(AppState::Game)
+                    .with_system(remote_move_system.before(GameSystem::PlayMove))
+                    .with_system(reconnect_system)
+                    .with_system(gap_overlay_system)
+                    .with_system(spectators_text_system),
+            )
+            .add_system_set(SystemSet::on_exit(AppState::Game).with_system(leave_remote_game));
+    }
+}
+
+/// The other player of a networked game, `None` when everybody plays on this computer.
+#[derive(Default)]
+pub struct Remote(pub Option<RemoteGame>);
+
+pub struct RemoteGame {
+    /// `None` while reconnecting.
+    pub connection: Option<Connection>,
+    /// The host checks the other player's moves, and sends over every move played.
+    pub host: bool,
+    /// Symbol played on this computer, empty for spectators.
+    pub symbol: Symbol,
+    /// The other player's name.
+    pub name: String,
+    /// Where to resume the game after losing the connection, `None` unless it's played on a server.
+    pub seat: Option<RoomSeat>,
+    /// Set while a player is away, nobody plays until they're back.
+    pub gap: Option<Gap>,
+    /// Spectators of the server room.
+    pub spectators: usize,
+    pub offer: Option<PendingOffer>,
+}
+
+impl RemoteGame {
+    /// A failed send shows up as the connection closing, the next time a message is received.
+    pub fn send(&self, message: &Message) {
+        if let Some(ref connection) = self.connection {
+            let _ = connection.send(message);
+        }
+    }
+}
+
+pub struct RoomSeat {
+    pub address: String,
+    pub room: String,
+    pub token: String,
+}
+
+pub enum Gap {
+    /// This computer lost the connection, and tries to get it back until `deadline`.
+    Reconnecting {
+        deadline: f64,
+        attempt: Option<Mutex<Receiver<io::Result<TcpStream>>>>,
+    },
+    /// Another player lost theirs, the server keeps their seat until `deadline`.
+    Away { name: String, deadline: f64 },
+}
+
+/// Connects on a thread of its own after `delay`, the stream comes through the receiver.
+fn connect_in_background(
+    address: String,
+    delay: Duration,
+) -> Mutex<Receiver<io::Result<TcpStream>>> {
+    let (sender, receiver) = mpsc::channel();
+
+    thread::spawn(move || {
+        thread::sleep(delay);
+
+        let stream = address
+            .trim_start_matches(WEB_SOCKET_SCHEME)
+            .to_socket_addrs()
+            .and_then(|mut addresses| {
+                addresses.next().ok_or_else(|| {
+                    io::Error::new(io::ErrorKind::NotFound, "the address wasn't found")
+                })
+            })
+            .and_then(|address| TcpStream::connect_timeout(&address, CONNECT_TIMEOUT));
+
+        let _ = sender.send(stream);
+    });
+
+    Mutex::new(receiver)
+}
+
+/// Hosting or joining a networked game, until both players are there.
+#[derive(Default)]
+pub struct Lobby {
+    pub hosting: bool,
+    /// What's going on, shown in the lobby.
+    pub message: String,
+    listener: Option<TcpListener>,
+    /// Connection to the host, made on a thread of its own.
+    connecting: Option<Mutex<Receiver<io::Result<TcpStream>>>>,
+    /// Connected, waiting for the other player to say hello or welcome.
+    peer: Option<Connection>,
+    /// Address connected to when joining.
+    address: String,
+    /// Room asked for on a server, `None` when joining a host.
+    room: Option<String>,
+    /// Following the room's game rather than taking a seat.
+    watching: bool,
+    spectators: usize,
+}
+
+impl Lobby {
+    pub fn listening(&self) -> bool {
+        self.listener.is_some()
+    }
+
+    /// Players there so far, the host and the one saying hello.
+    pub fn players(&self) -> usize {
+        1 + self.peer.is_some() as usize
+    }
+
+    /// Addresses without a port get the default one, `address/room` joins a room of a server and
+    /// `address/` any room waiting for players, `ws://address` connects over WebSocket.
+    pub fn connect(&mut self, address: &str) {
+        let (scheme, address) = match address.strip_prefix(WEB_SOCKET_SCHEME) {
+            Some(address) => (WEB_SOCKET_SCHEME, address),
+            None => ("", address),
+        };
+        let (address, room) = match address.split_once('/') {
+            Some((address, room)) => (address, Some(room.to_string())),
+            None => (address, None),
+        };
+        let address = if address.contains(':') {
+            format!("{}{}", scheme, address)
+        } else {
+            format!("{}{}:{}", scheme, address, DEFAULT_PORT)
+        };
+
+        self.peer = None;
+        self.room = room;
+        self.watching = false;
+        self.connecting = Some(connect_in_background(address.clone(), Duration::ZERO));
+        self.message = format!("Connecting to {}...", address);
+        self.address = address;
+    }
+
+    /// Spectators watch a room of a server, `address/room`.
+    pub fn watch(&mut self, address: &str) {
+        if !address.contains('/') {
+            self.message = "Type the server's address and a room, address/room".to_string();
+            return;
+        }
+
+        self.connect(address);
+        self.watching = true;
+    }
+}
+
+pub struct LobbyText;
+
+fn lobby_button_system(
+    interaction_query: Query<(&Interaction, &ButtonElement), (Changed<Interaction>, With<Button>)>,
+    variant: Res<Variant>,
+    mut lobby: ResMut<Lobby>,
+    mut status: ResMut<Status>,
+    mut state: ResMut<State<AppState>>,
+) {
+    for (interaction, button) in interaction_query.iter() {
+        if *interaction != Interaction::Clicked {
+            continue;
+        }
+
+        let hosting = match button {
+            ButtonElement::Host => true,
+            ButtonElement::Join => false,
+            _ => continue,
+        };
+
+        // the joining player gets the host's variant
+        if hosting && variant.players != 2 {
+            status.0 = Some("Networked games are for two players".to_string());
+            continue;
+        }
+        if hosting && variant.blind {
+            status.0 = Some("Blind games are played on one screen".to_string());
+            continue;
+        }
+
+        *lobby = Lobby {
+            hosting,
+            ..Default::default()
+        };
+
+        state
+            .set(AppState::Lobby)
+            .expect("Couldn't enter the Lobby state");
+    }
+}
+
+fn setup_lobby(
+    mut commands: Commands,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    button_assets: Res<ButtonAssets>,
+    variant: Res<Variant>,
+    mut lobby: ResMut<Lobby>,
+) {
+    if lobby.hosting {
+        let listener = TcpListener::bind(("0.0.0.0", DEFAULT_PORT)).and_then(|listener| {
+            // polled every frame
+            listener.set_nonblocking(true)?;
+            Ok(listener)
+        });
+
+        match listener {
+            Ok(listener) => {
+                lobby.listener = Some(listener);
+                lobby.message = format!(
+                    "Hosting {} on port {}, waiting for a player...",
+                    *variant, DEFAULT_PORT
+                );
+            }
+            Err(error) => {
+                lobby.message = format!("Couldn't host on port {}: {}", DEFAULT_PORT, error)
+            }
+        }
+    } else {
+        lobby.message =
+            "Pick a game of the local network, or type the host's address then press Enter"
+                .to_string();
+    }
+
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                flex_direction: FlexDirection::ColumnReverse,
+                ..Default::default()
+            },
+            material: materials.add(Color::NONE.into()),
+            ..Default::default()
+        })
+        .insert(NodeElement::Root)
+        .with_children(|root| {
+            root.spawn_bundle(TextBundle {
+                text: Text::with_section(
+                    "",
+                    TextStyle {
+                        font: button_assets.font.clone(),
+                        font_size: 20.0,
+                        color: Color::WHITE,
+                    },
+                    Default::default(),
+                ),
+                style: Style {
+                    margin: Rect::all(Val::Px(16.0)),
+                    max_size: Size::new(Val::Px(568.0), Val::Undefined),
+                    ..Default::default()
+                },
+                ..Default::default()
+            })
+            .insert(LobbyText);
+
+            if !lobby.hosting {
+                root.spawn_bundle(NodeBundle {
+                    style: Style {
+                        flex_direction: FlexDirection::ColumnReverse,
+                        align_items: AlignItems::Center,
+                        ..Default::default()
+                    },
+                    material: materials.add(Color::NONE.into()),
+                    ..Default::default()
+                })
+                .insert(NodeElement::DiscoveredGames);
+
+                root.spawn_bundle(TextBundle {
+                    text: Text::with_section(
+                        "",
+                        TextStyle {
+                            font: button_assets.font.clone(),
+                            font_size: 20.0,
+                            color: Color::rgb_u8(171, 178, 191),
+                        },
+                        Default::default(),
+                    ),
+                    style: Style {
+                        margin: Rect::all(Val::Px(16.0)),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                })
+                .insert(TextElement::Address(String::new()));
+
+                root.spawn_button(&button_assets, ButtonElement::Watch);
+            }
+
+            root.spawn_button(&button_assets, ButtonElement::BackToMenu);
+
+            root.spawn_bundle(status_text(&button_assets))
+                .insert(TextElement::Status(None));
+        });
+}
+
+fn close_lobby(mut lobby: ResMut<Lobby>) {
+    // stops listening, and turns away a player who didn't get to play
+    *lobby = Lobby::default();
+}
+
+fn leave_remote_game(mut remote: ResMut<Remote>) {
+    remote.0 = None;
+}
+
+/// Typing goes to the host's address, Enter connects to it.
+fn address_input_system(
+    mut received_characters: EventReader<ReceivedCharacter>,
+    keyboard_input: Res<Input<KeyCode>>,
+    mut lobby: ResMut<Lobby>,
+    mut text_query: Query<&mut TextElement>,
+) {
+    for mut text_element in text_query.iter_mut() {
+        let text = match *text_element {
+            TextElement::Address(ref mut text) => text,
+            _ => continue,
+        };
+
+        for event in received_characters.iter() {
+            if !event.char.is_control() {
+                text.push(event.char);
+            }
+        }
+
+        if keyboard_input.just_pressed(KeyCode::Back) {
+            text.pop();
+        }
+
+        if keyboard_input.just_pressed(KeyCode::Escape) {
+            text.clear();
+        }
+
+        if keyboard_input.just_pressed(KeyCode::Return) && !text.is_empty() {
+            lobby.connect(text.trim());
+        }
+    }
+}
+
+fn watch_button_system(
+    interaction_query: Query<(&Interaction, &ButtonElement), (Changed<Interaction>, With<Button>)>,
+    text_query: Query<&TextElement>,
+    mut lobby: ResMut<Lobby>,
+) {
+    for (interaction, button) in interaction_query.iter() {
+        if *interaction != Interaction::Clicked || !matches!(button, ButtonElement::Watch) {
+            continue;
+        }
+
+        for text_element in text_query.iter() {
+            if let TextElement::Address(ref text) = *text_element {
+                lobby.watch(text.trim());
+            }
+        }
+    }
+}
+
+/// Brings the players together, and starts the game once they greeted each other.
+fn lobby_system(
+    mut lobby: ResMut<Lobby>,
+    mut remote: ResMut<Remote>,
+    variant: Res<Variant>,
+    turn_order: Res<TurnOrder>,
+    variant_assets: Res<Assets<Variant>>,
+    mut variants: ResMut<Variants>,
+    mut pending_record: ResMut<PendingRecord>,
+) {
+    let lobby = &mut *lobby;
+
+    if let (Some(listener), None) = (&lobby.listener, &lobby.peer) {
+        match listener.accept().and_then(|(stream, address)| {
+            Connection::accept(stream).map(|connection| (connection, address))
+        }) {
+            Ok((connection, address)) => {
+                lobby.peer = Some(connection);
+                lobby.message = format!("{} connected, saying hello...", address);
+            }
+            Err(error) if error.kind() == io::ErrorKind::WouldBlock => {}
+            Err(error) => lobby.message = format!("Couldn't accept a player: {}", error),
+        }
+    }
+
+    let connected = lobby.connecting.as_ref().and_then(|receiver| {
+        receiver
+            .lock()
+            .expect("Couldn't lock the connection")
+            .try_recv()
+            .ok()
+    });
+    if let Some(stream) = connected {
+        lobby.connecting = None;
+
+        let hello = Message::Hello {
+            version: PROTOCOL_VERSION,
+            name: player_name(),
+        };
+        // servers seat the players in rooms, with the variant picked in the menu if it's a new one
+        let join_room = lobby.room.clone().map(|room| {
+            if lobby.watching {
+                Message::Watch { room }
+            } else if room.is_empty() {
+                Message::QuickMatch {
+                    variant: variant.name.clone(),
+                }
+            } else {
+                Message::JoinRoom {
+                    room,
+                    variant: variant.name.clone(),
+                }
+            }
+        });
+        match stream
+            .and_then(|stream| Connection::open(stream, &lobby.address))
+            .and_then(|connection| connection.send(&hello).map(|_| connection))
+            .and_then(|connection| match join_room {
+                Some(ref join_room) => connection.send(join_room).map(|_| connection),
+                None => Ok(connection),
+            }) {
+            Ok(connection) => {
+                lobby.peer = Some(connection);
+                lobby.message = "Connected, waiting for the host...".to_string();
+            }
+            Err(error) => lobby.message = format!("Couldn't connect: {}", error),
+        }
+    }
+
+    let message = match lobby.peer.as_ref().map(Connection::receive) {
+        Some(Ok(Some(message))) => message,
+        Some(Ok(None)) | None => return,
+        Some(Err(error)) => {
+            lobby.peer = None;
+            lobby.message = format!("The other player left: {}", error);
+            return;
+        }
+    };
+
+    let (record, remote_game) = match (message, lobby.peer.take()) {
+        (Message::Hello { version, name }, Some(peer)) if lobby.hosting => {
+            if version != PROTOCOL_VERSION {
+                let _ = peer.send(&Message::Refused {
+                    reason: format!(
+                        "the host speaks version {} of the protocol, not {}",
+                        PROTOCOL_VERSION, version
+                    ),
+                });
+                lobby.message = format!("Turned {} away, their game is another version", name);
+                return;
+            }
+
+            // the host plays the first symbol, the turn order picked in the menu says who starts
+            let record = GameRecord {
+                variant: variant.name.clone(),
+                players: Players::new(&turn_order, &variant).0,
+                date: today(),
+                game: None,
+                result: None,
+                seed: if variant.random_turns { random() } else { 0 },
+                setup: None,
+                ply: None,
+                slot: None,
+                moves: Vec::new(),
+                chat: Vec::new(),
+                signature: None,
+            };
+            let _ = peer.send(&Message::Welcome {
+                name: player_name(),
+                record: record.to_string(),
+                symbol: Symbol::PLAYERS[1],
+                token: None,
+            });
+
+            (
+                record,
+                RemoteGame {
+                    connection: Some(peer),
+                    host: true,
+                    symbol: Symbol::PLAYERS[0],
+                    name,
+                    seat: None,
+                    gap: None,
+                    spectators: 0,
+                    offer: None,
+                },
+            )
+        }
+        (
+            Message::Welcome {
+                name,
+                record,
+                symbol,
+                token,
+            },
+            Some(peer),
+        ) if !lobby.hosting => match GameRecord::parse(&record) {
+            Ok(record) => (
+                record,
+                RemoteGame {
+                    connection: Some(peer),
+                    host: false,
+                    symbol,
+                    name,
+                    seat: token.map(|token| RoomSeat {
+                        address: lobby.address.clone(),
+                        room: lobby.room.clone().unwrap_or_default(),
+                        token,
+                    }),
+                    gap: None,
+                    spectators: lobby.spectators,
+                    offer: None,
+                },
+            ),
+            Err(error) => {
+                lobby.message = format!("Couldn't read the host's game: {}", error);
+                return;
+            }
+        },
+        (
+            Message::Waiting {
+                room,
+                players,
+                seats,
+            },
+            peer,
+        ) => {
+            lobby.peer = peer;
+            // quick matches find out their room here
+            lobby.room = Some(room.clone());
+            lobby.message = format!(
+                "Waiting in room {} for more players, {}/{} so far",
+                room, players, seats
+            );
+            return;
+        }
+        (Message::Spectators { count }, peer) => {
+            lobby.peer = peer;
+            lobby.spectators = count;
+            return;
+        }
+        // the chat starts with the game
+        (Message::Chat { .. }, peer) => {
+            lobby.peer = peer;
+            return;
+        }
+        (Message::Refused { reason }, _) => {
+            lobby.message = format!("Refused: {}", reason);
+            return;
+        }
+        (message, peer) => {
+            lobby.peer = peer;
+            lobby.message = format!("Unexpected message {:?}", message);
+            return;
+        }
+    };
+
+    // started by the record plugin, the way saved games are
+    match pending_record.open_record(record, &variant_assets, &mut variants, AppState::Game) {
+        Ok(_) => remote.0 = Some(remote_game),
+        Err(error) => lobby.message = error,
+    }
+}
+
+fn lobby_text_system(lobby: Res<Lobby>, mut text_query: Query<&mut Text, With<LobbyText>>) {
+    for mut text in text_query.iter_mut() {
+        if text.sections[0].value != lobby.message {
+            text.sections[0].value = lobby.message.clone();
+        }
+    }
+}
+
+/// Plays the other player's moves, the host checks them first.
+fn remote_move_system(
+    time: Res<Time>,
+    mut remote: ResMut<Remote>,
+    mut move_events: EventWriter<MoveEvent>,
+    mut winning_events: EventWriter<WinningEvent>,
+    variant: Res<Variant>,
+    symbols: Res<Symbols>,
+    current_symbol: Res<CurrentSymbol>,
+    players: Res<Players>,
+    mut coin_toss: ResMut<CoinToss>,
+    mut history: ResMut<History>,
+    mut rule_script: ResMut<RuleScript>,
+    mut chat: ResMut<Chat>,
+    mut status: ResMut<Status>,
+) {
+    let remote_game = match remote.0 {
+        Some(ref mut remote_game) => remote_game,
+        None => return,
+    };
+    let connection = match remote_game.connection {
+        Some(ref connection) => connection,
+        None => return,
+    };
+
+    // a message a frame, and `play_move_system` runs after this one, so a move is played before
+    // the next one gets checked
+    let message = match connection.receive() {
+        Ok(Some(message)) => message,
+        Ok(None) => return,
+        // games of a server room wait for this computer to get back
+        Err(_) if remote_game.seat.is_some() => {
+            let deadline = match remote_game.gap {
+                Some(Gap::Reconnecting { deadline, .. }) => deadline,
+                _ => time.seconds_since_startup() + GRACE_SECONDS as f64,
+            };
+
+            remote_game.connection = None;
+            remote_game.gap = Some(Gap::Reconnecting {
+                deadline,
+                attempt: None,
+            });
+            return;
+        }
+        Err(error) => {
+            status.0 = Some(format!("{} left the game: {}", remote_game.name, error));
+            remote.0 = None;
+            return;
+        }
+    };
+
+    match message {
+        Message::Move { ply, cell } if remote_game.host => {
+            let refusal = if current_symbol.0 == Symbol::Empty
+                || current_symbol.0 == remote_game.symbol
+                || coin_toss.is_spinning()
+            {
+                Some("it's not your turn")
+            } else if remote_game.offer.is_some() {
+                Some("an offer is waiting for an answer")
+            } else if ply != history.cursor {
+                Some("the game moved on")
+            } else if variant.landing_cell(&symbols.0, cell) != Some(cell)
+                || !rule_script.legal_move(&variant, &symbols.0, cell, current_symbol.0)
+            {
+                Some("the move isn't legal")
+            } else {
+                None
+            };
+
+            match refusal {
+                Some(reason) => remote_game.send(&Message::Rejected {
+                    reason: reason.to_string(),
+                }),
+                None => move_events.send(MoveEvent(cell)),
+            }
+        }
+        // checked by the host already
+        Message::Move { ply, cell } if ply == history.cursor => move_events.send(MoveEvent(cell)),
+        Message::Move { .. } => {
+            status.0 = Some(format!("Out of step with {}'s game", remote_game.name))
+        }
+        // nobody plays while an offer waits, the rejection is of the offer then
+        Message::Rejected { reason } => match remote_game.offer.take() {
+            Some(pending) if pending.mine => {
+                status.0 = Some(format!("Can't ask for {}: {}", pending.offer, reason))
+            }
+            pending => {
+                remote_game.offer = pending;
+                status.0 = Some(format!("Move rejected: {}", reason));
+            }
+        },
+        Message::Away { name, seconds } => {
+            remote_game.offer = None;
+            remote_game.gap = Some(Gap::Away {
+                name,
+                deadline: time.seconds_since_startup() + seconds as f64,
+            })
+        }
+        Message::Back { name } => {
+            remote_game.gap = None;
+            status.0 = Some(format!("{} is back", name));
+        }
+        Message::Spectators { count } => remote_game.spectators = count,
+        // the host knows who it's talking to, servers name the players themselves
+        Message::Chat { name, text } => chat.lines.push(ChatLine {
+            ply: history.cursor,
+            name: if remote_game.host {
+                remote_game.name.clone()
+            } else {
+                name
+            },
+            text: chat_text(&text),
+        }),
+        // the host checks the other player's offers the way the servers do
+        Message::Offer { offer, ply } => {
+            let allowed = if remote_game.offer.is_some() {
+                Err("an offer is waiting for an answer".to_string())
+            } else if ply != history.cursor {
+                Err("the game moved on".to_string())
+            } else {
+                offer.allowed(history.cursor, players.0.is_empty())
+            };
+
+            match allowed {
+                Err(reason) if remote_game.host => remote_game.send(&Message::Rejected { reason }),
+                _ => {
+                    remote_game.offer = Some(PendingOffer::new(
+                        offer,
+                        false,
+                        time.seconds_since_startup(),
+                    ))
+                }
+            }
+        }
+        Message::Answer { offer, accepted } => match remote_game.offer.take() {
+            Some(pending) if pending.offer == offer && !pending.mine => {
+                status.0 = Some(format!("Nobody answered the offer of {}", offer))
+            }
+            Some(pending) if pending.offer == offer && accepted => {
+                status.0 = Some(format!("{} accepted {}", remote_game.name, offer));
+
+                // servers carry it out themselves
+                if remote_game.host {
+                    if let Err(error) = carry_out(
+                        offer,
+                        remote_game,
+                        &variant,
+                        &mut rule_script,
+                        &mut history,
+                        &mut coin_toss,
+                        &mut chat,
+                        &mut winning_events,
+                    ) {
+                        status.0 = Some(format!("Couldn't carry {} out: {}", offer, error));
+                    }
+                }
+            }
+            Some(pending) if pending.offer == offer => {
+                status.0 = Some(format!("{} declined {}", remote_game.name, offer))
+            }
+            // the offer timed out already
+            pending => remote_game.offer = pending,
+        },
+        // the whole game again after reconnecting or once an offer is carried out
+        Message::Welcome { record, symbol, .. } => {
+            let started = GameRecord::parse(&record)
+                .map_err(|error| error.to_string())
+                .and_then(|record| {
+                    start_over(
+                        &record,
+                        &variant,
+                        &mut rule_script,
+                        &mut history,
+                        &mut coin_toss,
+                        &mut chat,
+                        &mut winning_events,
+                    )
+                });
+
+            match started {
+                Ok(_) => {
+                    remote_game.symbol = symbol;
+                    if remote_game.gap.take().is_some() {
+                        status.0 = Some("Back in the game".to_string());
+                    }
+                }
+                Err(error) => {
+                    status.0 = Some(format!("Couldn't resume the game: {}", error));
+                    remote.0 = None;
+                }
+            }
+        }
+        Message::Refused { reason } => {
+            status.0 = Some(format!("Couldn't resume the game: {}", reason));
+            remote.0 = None;
+        }
+        message => status.0 = Some(format!("Unexpected message {:?}", message)),
+    }
+}
+
+/// Puts a game the host or the server sent again on the board, in place of the one there.
+pub fn start_over(
+    record: &GameRecord,
+    variant: &Variant,
+    rule_script: &mut RuleScript,
+    history: &mut History,
+    coin_toss: &mut CoinToss,
+    chat: &mut Chat,
+    winning_events: &mut EventWriter<WinningEvent>,
+) -> Result<(), String> {
+    let (moves, positions) = record
+        .replay(variant, rule_script)
+        .map_err(|error| error.to_string())?;
+
+    // agreed draws end the game without a move, it's archived and its result shown all the same
+    if let Some(last) = positions
+        .last()
+        .filter(|position| position.players.is_empty())
+    {
+        winning_events.send(WinningEvent(last.standings.clone()));
+    }
+
+    *coin_toss = CoinToss::default();
+    coin_toss.seed = record.seed;
+    // put on the board by the history plugin
+    let ply = moves.len();
+    *history = History::resume(moves, positions, ply);
+    // said while this computer was away too
+    chat.lines = record.chat.clone();
+
+    Ok(())
+}
+
+/// Gets back to the server of the game after losing the connection, until the seat is given up.
+fn reconnect_system(time: Res<Time>, mut remote: ResMut<Remote>, mut status: ResMut<Status>) {
+    let remote_game = match remote.0 {
+        Some(ref mut remote_game) => remote_game,
+        None => return,
+    };
+    let seat = match remote_game.seat {
+        Some(ref seat) => seat,
+        None => return,
+    };
+    let (deadline, attempt) = match remote_game.gap {
+        // connected again, waiting for the welcome
+        Some(Gap::Reconnecting { .. }) if remote_game.connection.is_some() => return,
+        Some(Gap::Reconnecting {
+            deadline,
+            ref mut attempt,
+        }) => (deadline, attempt),
+        _ => return,
+    };
+
+    if time.seconds_since_startup() > deadline {
+        status.0 = Some("Couldn't get back to the game".to_string());
+        remote.0 = None;
+        return;
+    }
+
+    let connected = match attempt {
+        Some(ref receiver) => receiver
+            .lock()
+            .expect("Couldn't lock the connection")
+            .try_recv()
+            .ok(),
+        None => {
+            *attempt = Some(connect_in_background(seat.address.clone(), RECONNECT_DELAY));
+            return;
+        }
+    };
+
+    let stream = match connected {
+        Some(stream) => stream,
+        None => return,
+    };
+    // tried again next frame, after the delay
+    *attempt = None;
+
+    let resume = [
+        Message::Hello {
+            version: PROTOCOL_VERSION,
+            name: player_name(),
+        },
+        Message::Resume {
+            room: seat.room.clone(),
+            token: seat.token.clone(),
+        },
+    ];
+    let connection = stream
+        .and_then(|stream| Connection::open(stream, &seat.address))
+        .and_then(|connection| {
+            for message in resume.iter() {
+                connection.send(message)?;
+            }
+
+            Ok(connection)
+        });
+
+    if let Ok(connection) = connection {
+        remote_game.connection = Some(connection);
+    }
+}
+
+fn spectators_text_system(remote: Res<Remote>, mut text_query: Query<&mut TextElement>) {
+    let count = remote
+        .0
+        .as_ref()
+        .map_or(0, |remote_game| remote_game.spectators);
+
+    for mut text_element in text_query.iter_mut() {
+        if let TextElement::Spectators(ref mut shown) = *text_element {
+            if *shown != count {
+                *shown = count;
+            }
+        }
+    }
+}
+
+pub struct GapText;
+
+/// Covers the board while a player is away.
+fn gap_overlay_system(
+    mut commands: Commands,
+    time: Res<Time>,
+    remote: Res<Remote>,
+    button_assets: Res<ButtonAssets>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    overlay_query: Query<(Entity, &NodeElement)>,
+    mut text_query: Query<&mut Text, With<GapText>>,
+) {
+    let overlay = overlay_query
+        .iter()
+        .find(|(_, node_element)| matches!(node_element, NodeElement::Gap))
+        .map(|(entity, _)| entity);
+
+    let gap = remote
+        .0
+        .as_ref()
+        .and_then(|remote_game| remote_game.gap.as_ref());
+    let message = match gap {
+        Some(Gap::Reconnecting { deadline, .. }) => format!(
+            "Connection lost, reconnecting... {:.0}s",
+            (deadline - time.seconds_since_startup()).max(0.0)
+        ),
+        Some(Gap::Away { name, deadline }) => format!(
+            "Waiting for {} to come back... {:.0}s",
+            name,
+            (deadline - time.seconds_since_startup()).max(0.0)
+        ),
+        None => {
+            if let Some(overlay) = overlay {
+                commands.entity(overlay).despawn_recursive();
+            }
+
+            return;
+        }
+    };
+
+    if overlay.is_some() {
+        for mut text in text_query.iter_mut() {
+            if text.sections[0].value != message {
+                text.sections[0].value = message.clone();
+            }
+        }
+
+        return;
+    }
+
+    // the board shows through, but can't be played on
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            material: materials.add(Color::rgba(0.0, 0.0, 0.0, 0.6).into()),
+            ..Default::default()
+        })
+        .insert(NodeElement::Gap)
+        .with_children(|overlay| {
+            overlay
+                .spawn_bundle(TextBundle {
+                    text: Text::with_section(
+                        message,
+                        TextStyle {
+                            font: button_assets.font.clone(),
+                            font_size: 40.0,
+                            color: Color::WHITE,
+                        },
+                        Default::default(),
+                    ),
+                    ..Default::default()
+                })
+                .insert(GapText);
+        });
+}
@@ -0,0 +1,1367 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    rules::{self, Cell, Move, Position, Standings, Symbol, Topology, Variant},
+    script::RuleScript,
+};
+
+/// A move as written in a record, its cell is only named until the variant tells the board apart.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RecordedMove {
+    pub symbol: Symbol,
+    pub cell: String,
+    /// Where the move starts in the file, `0` for records which weren't parsed.
+    pub line: usize,
+    pub column: usize,
+}
+
+/// Said during a networked game, once `ply` moves were played.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ChatLine {
+    pub ply: usize,
+    pub name: String,
+    pub text: String,
+}
+
+/// A game as saved to `.ttt` files, header tags then the numbered moves:
+///
+/// ```text
+/// [Variant "Classic"]
+/// [Players "X O"]
+/// [Date "2021-08-14"]
+/// [Result "X > O"]
+/// [Chat "3 alice: gg"]
+///
+/// 1. X b2 2. O a1 3. X c3
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct GameRecord {
+    pub variant: String,
+    /// Turn order the game started with.
+    pub players: Vec<Symbol>,
+    pub date: String,
+    /// Id of a game played by exchanging files, the same in each of them.
+    pub game: Option<String>,
+    /// `None` for games still in progress.
+    pub result: Option<Standings>,
+    /// Seed of the coin of random turns games.
+    pub seed: u64,
+    /// Position string of the board the game started from, `None` for an empty board.
+    pub setup: Option<String>,
+    /// Number of moves on the board when the ones after it were undone, `None` for all of them.
+    pub ply: Option<usize>,
+    /// Name the player gave the save slot the game is kept in.
+    pub slot: Option<String>,
+    pub moves: Vec<RecordedMove>,
+    pub chat: Vec<ChatLine>,
+    /// Signature of the rest of the record, written last.
+    pub signature: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RecordError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl RecordError {
+    fn new(line: usize, column: usize, message: impl Into<String>) -> Self {
+        Self {
+            line,
+            column,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for RecordError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.line == 0 {
+            write!(f, "{}", self.message)
+        } else {
+            write!(
+                f,
+                "line {}, column {}: {}",
+                self.line, self.column, self.message
+            )
+        }
+    }
+}
+
+impl std::error::Error for RecordError {}
+
+/// Today's date as `YYYY-MM-DD`, in UTC.
+pub fn today() -> String {
+    let days = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs() / 86_400) as i64;
+
+    // days since 1970-01-01 to a civil date, from Howard Hinnant's algorithm
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month + 2) / 5 + 1;
+    let month = if month < 10 { month + 3 } else { month - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+pub fn standings_notation(standings: &Option<Standings>) -> String {
+    match standings {
+        None => "*".to_string(),
+        Some(standings) => standings
+            .0
+            .iter()
+            .map(|rank| {
+                rank.iter()
+                    .map(Symbol::short_name)
+                    .collect::<Vec<_>>()
+                    .join("=")
+            })
+            .collect::<Vec<_>>()
+            .join(" > "),
+    }
+}
+
+fn parse_symbols(names: &str, separator: &str) -> Option<Vec<Symbol>> {
+    names
+        .split(separator)
+        .map(|name| Symbol::from_short_name(name.trim()))
+        .collect()
+}
+
+fn parse_standings(value: &str) -> Option<Option<Standings>> {
+    if value == "*" {
+        return Some(None);
+    }
+
+    value
+        .split('>')
+        .map(|rank| parse_symbols(rank, "="))
+        .collect::<Option<Vec<_>>>()
+        .map(|ranks| Some(Standings(ranks)))
+}
+
+impl fmt::Display for GameRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let players = self
+            .players
+            .iter()
+            .map(Symbol::short_name)
+            .collect::<Vec<_>>();
+
+        writeln!(f, "[Variant \"{}\"]", self.variant)?;
+        writeln!(f, "[Players \"{}\"]", players.join(" "))?;
+        writeln!(f, "[Date \"{}\"]", self.date)?;
+        if let Some(ref game) = self.game {
+            writeln!(f, "[Game \"{}\"]", game)?;
+        }
+        writeln!(f, "[Result \"{}\"]", standings_notation(&self.result))?;
+        if self.seed != 0 {
+            writeln!(f, "[Seed \"{}\"]", self.seed)?;
+        }
+        if let Some(ref setup) = self.setup {
+            writeln!(f, "[Setup \"{}\"]", setup)?;
+        }
+        if let Some(ply) = self.ply {
+            writeln!(f, "[Ply \"{}\"]", ply)?;
+        }
+        if let Some(ref slot) = self.slot {
+            writeln!(f, "[Slot \"{}\"]", slot)?;
+        }
+        for line in self.chat.iter() {
+            writeln!(
+                f,
+                "[Chat \"{} {}: {}\"]",
+                line.ply,
+                chat_name(&line.name),
+                line.text
+            )?;
+        }
+        if let Some(ref signature) = self.signature {
+            writeln!(f, "[Signature \"{}\"]", signature)?;
+        }
+        writeln!(f)?;
+
+        let moves = self
+            .moves
+            .iter()
+            .enumerate()
+            .map(|(index, played)| {
+                format!(
+                    "{}. {} {}",
+                    index + 1,
+                    played.symbol.short_name(),
+                    played.cell
+                )
+            })
+            .collect::<Vec<_>>();
+
+        // a few moves per line keep long games readable
+        for line in moves.chunks(8) {
+            writeln!(f, "{}", line.join(" "))?;
+        }
+
+        Ok(())
+    }
+}
+
+impl GameRecord {
+    /// Record of the moves played so far, from the positions they led to.
+    pub fn new(variant: &Variant, seed: u64, moves: &[Move], positions: &[Position]) -> Self {
+        let finished = positions
+            .last()
+            .filter(|position| position.players.is_empty() && !position.standings.0.is_empty());
+
+        Self {
+            variant: variant.name.clone(),
+            players: positions
+                .first()
+                .map(|position| position.players.clone())
+                .unwrap_or_default(),
+            date: today(),
+            game: None,
+            result: finished.map(|position| position.standings.clone()),
+            seed,
+            setup: positions
+                .first()
+                .filter(|position| !position.symbols.is_empty())
+                .map(|position| {
+                    position_string(
+                        variant,
+                        &position.symbols,
+                        position.current_symbol,
+                        &position.players,
+                    )
+                }),
+            ply: None,
+            slot: None,
+            moves: moves
+                .iter()
+                .map(|played| RecordedMove {
+                    symbol: played.symbol,
+                    cell: variant.topology.notation(played.cell),
+                    line: 0,
+                    column: 0,
+                })
+                .collect(),
+            chat: Vec::new(),
+            signature: None,
+        }
+    }
+
+    pub fn parse(text: &str) -> Result<Self, RecordError> {
+        let mut record = Self::default();
+        let (mut variant, mut players) = (None, None);
+
+        for (index, line) in text.lines().enumerate() {
+            let line_number = index + 1;
+            let trimmed = line.trim_start();
+            let indent = line.len() - trimmed.len();
+
+            if trimmed.starts_with('[') {
+                let (name, value) = parse_tag(trimmed, line_number, indent + 1)?;
+                let value_error = |message: &str| {
+                    RecordError::new(
+                        line_number,
+                        indent + 1,
+                        format!("{} in the {} tag", message, name),
+                    )
+                };
+
+                match name {
+                    "Variant" => variant = Some(value.to_string()),
+                    "Players" => {
+                        players = Some(
+                            parse_symbols(value, " ")
+                                .ok_or_else(|| value_error("unknown player symbol"))?,
+                        )
+                    }
+                    "Date" => record.date = value.to_string(),
+                    "Game" => record.game = Some(value.to_string()),
+                    "Result" => {
+                        record.result =
+                            parse_standings(value).ok_or_else(|| value_error("malformed result"))?
+                    }
+                    "Seed" => {
+                        record.seed = value.parse().map_err(|_| value_error("malformed seed"))?
+                    }
+                    "Setup" => record.setup = Some(value.to_string()),
+                    "Signature" => record.signature = Some(value.to_string()),
+                    "Ply" => {
+                        record.ply = Some(value.parse().map_err(|_| value_error("malformed ply"))?)
+                    }
+                    "Slot" => record.slot = Some(value.to_string()),
+                    "Chat" => record
+                        .chat
+                        .push(parse_chat_line(value).ok_or_else(|| value_error("malformed line"))?),
+                    // left for the versions which know about them
+                    _ => {}
+                }
+
+                continue;
+            }
+
+            let mut tokens = tokens(line).peekable();
+            while let Some((column, token)) = tokens.next() {
+                // comments run to the end of the line
+                if token.starts_with(';') {
+                    break;
+                }
+
+                let expected = format!("{}.", record.moves.len() + 1);
+                if token != expected {
+                    return Err(RecordError::new(
+                        line_number,
+                        column,
+                        format!("expected {:?}, found {:?}", expected, token),
+                    ));
+                }
+
+                let (symbol, cell) = match (tokens.next(), tokens.next()) {
+                    (Some(symbol), Some(cell)) => (symbol, cell),
+                    _ => {
+                        return Err(RecordError::new(
+                            line_number,
+                            column,
+                            "a move needs a symbol and a cell",
+                        ))
+                    }
+                };
+
+                record.moves.push(RecordedMove {
+                    symbol: Symbol::from_short_name(symbol.1).ok_or_else(|| {
+                        RecordError::new(
+                            line_number,
+                            symbol.0,
+                            format!("unknown player symbol {:?}", symbol.1),
+                        )
+                    })?,
+                    cell: cell.1.to_string(),
+                    line: line_number,
+                    column,
+                });
+            }
+        }
+
+        record.variant = variant.ok_or_else(|| RecordError::new(0, 0, "missing Variant tag"))?;
+        record.players = players.ok_or_else(|| RecordError::new(0, 0, "missing Players tag"))?;
+
+        if record.ply.map_or(false, |ply| ply > record.moves.len()) {
+            return Err(RecordError::new(0, 0, "the Ply tag is past the last move"));
+        }
+
+        Ok(record)
+    }
+
+    /// Checks the record continues the game of `previous` with exactly one new move, the files of
+    /// games played by mail can't skip or change moves.
+    pub fn follows(&self, previous: &GameRecord) -> Result<(), String> {
+        if self.game.is_none() || self.game != previous.game {
+            return Err("it's another game".to_string());
+        }
+        if (
+            &self.variant,
+            &self.players,
+            &self.date,
+            self.seed,
+            &self.setup,
+        ) != (
+            &previous.variant,
+            &previous.players,
+            &previous.date,
+            previous.seed,
+            &previous.setup,
+        ) {
+            return Err("the game's tags changed".to_string());
+        }
+        if self.moves.len() != previous.moves.len() + 1 {
+            return Err(format!(
+                "expected {} moves, one more than before, found {}",
+                previous.moves.len() + 1,
+                self.moves.len()
+            ));
+        }
+
+        let same = |a: &RecordedMove, b: &RecordedMove| a.symbol == b.symbol && a.cell == b.cell;
+        if !self
+            .moves
+            .iter()
+            .zip(previous.moves.iter())
+            .all(|(a, b)| same(a, b))
+        {
+            return Err("the earlier moves changed".to_string());
+        }
+
+        // whose turn it was is checked again when the moves are replayed
+        let played = self.moves.last().expect("Couldn't find the new move");
+        match previous.moves.last() {
+            Some(last) if last.symbol == played.symbol => {
+                Err(format!("{} played twice in a row", played.symbol))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Plays the moves again, returns the position before the first move and after each one.
+    pub fn replay(
+        &self,
+        variant: &Variant,
+        rule_script: &mut RuleScript,
+    ) -> Result<(Vec<Move>, Vec<Position>), RecordError> {
+        let mut order = self.players.clone();
+        order.sort_unstable_by_key(|symbol| {
+            Symbol::PLAYERS.iter().position(|player| player == symbol)
+        });
+        order.dedup();
+        if self.players.len() != variant.players
+            || order.as_slice() != &Symbol::PLAYERS[..variant.players]
+        {
+            return Err(RecordError::new(
+                0,
+                0,
+                format!("{} needs {} different players", variant, variant.players),
+            ));
+        }
+
+        let mut position = match self.setup {
+            Some(ref setup) => Setup::parse(setup)
+                .and_then(|setup| setup.position(variant, rule_script))
+                .map_err(|error| RecordError::new(0, 0, format!("Setup tag: {}", error)))?,
+            None => Position {
+                current_symbol: if variant.random_turns {
+                    Symbol::Empty
+                } else {
+                    self.players[0]
+                },
+                players: self.players.clone(),
+                ..Default::default()
+            },
+        };
+        let mut moves = Vec::new();
+        let mut positions = vec![position.clone()];
+
+        for recorded in self.moves.iter() {
+            let error = |message: String| RecordError::new(recorded.line, recorded.column, message);
+
+            if position.players.is_empty() {
+                return Err(error("the game is already over".to_string()));
+            }
+
+            let to_move = if variant.random_turns {
+                rules::coin_flip(self.seed, position.flips)
+            } else {
+                position.current_symbol
+            };
+            if recorded.symbol != to_move {
+                return Err(error(format!("it's {}'s turn", to_move)));
+            }
+
+            let cell = variant
+                .topology
+                .parse_notation(&recorded.cell)
+                .ok_or_else(|| error(format!("{:?} isn't a cell of the board", recorded.cell)))?;
+            if variant.landing_cell(&position.symbols, cell) != Some(cell)
+                || !rule_script.legal_move(variant, &position.symbols, cell, to_move)
+            {
+                return Err(error(format!("{} can't be played", recorded.cell)));
+            }
+
+            rule_script.apply_move(variant, &mut position.symbols, cell, to_move);
+            moves.push(Move {
+                cell,
+                symbol: to_move,
+            });
+
+            if variant.random_turns {
+                position.flips += 1;
+                position.current_symbol = Symbol::Empty;
+            } else {
+                position.current_symbol = rules::next_player(&position.players, to_move);
+            }
+
+            let lines = rule_script.outcome(variant, &position.symbols);
+            let full = variant.is_full(&position.symbols);
+            if rules::resolve(
+                variant,
+                &lines,
+                full,
+                &mut position.players,
+                &mut position.standings,
+            ) {
+                position.current_symbol = Symbol::Empty;
+            }
+
+            positions.push(position.clone());
+        }
+
+        // a result the moves don't reach was agreed on by the players, a draw of those left
+        if self.result.is_some() {
+            let agreed = !position.players.is_empty();
+            if agreed {
+                rules::resolve(
+                    variant,
+                    &[],
+                    true,
+                    &mut position.players,
+                    &mut position.standings,
+                );
+            }
+            if self.result.as_ref() != Some(&position.standings) {
+                return Err(RecordError::new(
+                    0,
+                    0,
+                    "the Result tag is neither where the moves lead nor a draw",
+                ));
+            }
+            if agreed {
+                position.current_symbol = Symbol::Empty;
+                *positions
+                    .last_mut()
+                    .expect("Couldn't find the last position") = position;
+            }
+        }
+
+        Ok((moves, positions))
+    }
+}
+
+/// `[Name "value"]`, `column` being where the tag starts.
+fn parse_tag(tag: &str, line: usize, column: usize) -> Result<(&str, &str), RecordError> {
+    let inner = tag
+        .trim_end()
+        .strip_prefix('[')
+        .and_then(|tag| tag.strip_suffix(']'))
+        .ok_or_else(|| RecordError::new(line, column + tag.trim_end().len(), "expected ']'"))?;
+
+    let quote = inner
+        .find('"')
+        .ok_or_else(|| RecordError::new(line, column + 1, "expected a quoted value"))?;
+    let name = inner[..quote].trim();
+    let value = inner[quote + 1..]
+        .strip_suffix('"')
+        .ok_or_else(|| RecordError::new(line, column + inner.len(), "expected '\"'"))?;
+
+    if name.is_empty() {
+        return Err(RecordError::new(line, column + 1, "expected a tag name"));
+    }
+
+    Ok((name, value))
+}
+
+/// A player's name with its backslashes and colons escaped, for the chat lines.
+fn chat_name(name: &str) -> String {
+    name.replace('\\', "\\\\").replace(':', "\\:")
+}
+
+/// `ply name: text`, the name runs up to the first colon a backslash doesn't escape.
+fn parse_chat_line(value: &str) -> Option<ChatLine> {
+    let (ply, said) = value.split_once(' ')?;
+
+    let mut name = String::new();
+    let mut chars = said.chars();
+    loop {
+        match chars.next()? {
+            '\\' => name.push(chars.next()?),
+            ':' => break,
+            character => name.push(character),
+        }
+    }
+    let text = chars.as_str().strip_prefix(' ')?;
+
+    Some(ChatLine {
+        ply: ply.parse().ok()?,
+        name,
+        text: text.to_string(),
+    })
+}
+
+/// Whitespace separated tokens with the column they start at.
+fn tokens(line: &str) -> impl Iterator<Item = (usize, &str)> {
+    line.split_whitespace().map(move |token| {
+        let offset = token.as_ptr() as usize - line.as_ptr() as usize;
+        (line[..offset].chars().count() + 1, token)
+    })
+}
+
+/// Letter of a symbol in position strings.
+fn symbol_letter(symbol: Symbol) -> char {
+    match symbol {
+        Symbol::X => 'X',
+        Symbol::O => 'O',
+        Symbol::Triangle => 'T',
+        Symbol::Square => 'S',
+        Symbol::Empty => '-',
+    }
+}
+
+/// Cells of the board row by row from the top, `None` for unbounded boards.
+fn board_rows(topology: &Topology) -> Option<Vec<Vec<Cell>>> {
+    let mut rows: Vec<Vec<Cell>> = Vec::new();
+
+    for cell in topology.cells()? {
+        match rows.last_mut() {
+            Some(row) if row[0].1 == cell.1 => row.push(cell),
+            _ => rows.push(vec![cell]),
+        }
+    }
+
+    Some(rows)
+}
+
+/// Position string of a board, see [`Setup`].
+pub fn position_string(
+    variant: &Variant,
+    symbols: &HashMap<Cell, Symbol>,
+    current_symbol: Symbol,
+    players: &[Symbol],
+) -> String {
+    let board = match board_rows(&variant.topology) {
+        Some(rows) => rows
+            .iter()
+            .map(|row| {
+                let mut rank = String::new();
+                let mut empty = 0;
+
+                for cell in row {
+                    let letter = if variant.blocked.contains(cell) {
+                        Some('#')
+                    } else {
+                        symbols.get(cell).copied().map(symbol_letter)
+                    };
+
+                    match letter {
+                        Some(letter) => {
+                            if empty > 0 {
+                                rank += &empty.to_string();
+                                empty = 0;
+                            }
+                            rank.push(letter);
+                        }
+                        None => empty += 1,
+                    }
+                }
+
+                if empty > 0 {
+                    rank += &empty.to_string();
+                }
+
+                rank
+            })
+            .collect::<Vec<_>>()
+            .join("/"),
+        None if symbols.is_empty() => "-".to_string(),
+        None => {
+            let mut cells = symbols.iter().collect::<Vec<_>>();
+            cells.sort_unstable_by_key(|(&(q, r), _)| (r, q));
+
+            cells
+                .iter()
+                .map(|(&cell, &symbol)| {
+                    format!(
+                        "{}{}",
+                        symbol_letter(symbol),
+                        variant.topology.notation(cell)
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join(";")
+        }
+    };
+
+    let side = if current_symbol == Symbol::Empty || variant.random_turns {
+        "-".to_string()
+    } else if variant.players > 2 {
+        // the whole turn order from the side to move, the players who finished at the end
+        let start = players
+            .iter()
+            .position(|&player| player == current_symbol)
+            .unwrap_or(0);
+
+        players[start..]
+            .iter()
+            .chain(&players[..start])
+            .chain(
+                Symbol::PLAYERS[..variant.players]
+                    .iter()
+                    .filter(|symbol| !players.contains(symbol)),
+            )
+            .map(|&symbol| symbol_letter(symbol))
+            .collect()
+    } else {
+        symbol_letter(current_symbol).to_string()
+    };
+
+    format!("{} {} {}", board, side, variant.name)
+}
+
+/// A position on one line: the board, the side to move and the variant, `X2/1O1/3 O Classic`.
+///
+/// Ranks go from the top, digits count empty cells, `#` marks blocked ones and `/` ends a rank.
+/// Hex boards write their rows the same way, unbounded ones list their symbols as `X0,0;O1,-1`.
+/// The side to move is `-` while the coin picks it or once the game is over, games of more than
+/// two players can write their whole turn order from it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Setup {
+    pub variant: String,
+    board: String,
+    side: String,
+}
+
+impl Setup {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut fields = text.split_whitespace();
+
+        match (fields.next(), fields.next()) {
+            (Some(board), Some(side)) => {
+                let variant = fields.collect::<Vec<_>>().join(" ");
+                if variant.is_empty() {
+                    return Err("expected the variant after the side to move".to_string());
+                }
+
+                Ok(Self {
+                    variant,
+                    board: board.to_string(),
+                    side: side.to_string(),
+                })
+            }
+            _ => Err("expected the board, the side to move and the variant".to_string()),
+        }
+    }
+
+    fn player(variant: &Variant, letter: char) -> Result<Symbol, String> {
+        Symbol::PLAYERS[..variant.players]
+            .iter()
+            .copied()
+            .find(|&symbol| symbol_letter(symbol) == letter)
+            .ok_or_else(|| format!("{:?} isn't a player of {}", letter, variant))
+    }
+
+    fn symbols(&self, variant: &Variant) -> Result<HashMap<Cell, Symbol>, String> {
+        let mut symbols = HashMap::new();
+
+        let rows = match board_rows(&variant.topology) {
+            Some(rows) => rows,
+            None if self.board == "-" => return Ok(symbols),
+            None => {
+                for entry in self.board.split(';') {
+                    let mut letters = entry.chars();
+                    let symbol = match letters.next() {
+                        Some(letter) => Self::player(variant, letter)?,
+                        None => return Err("expected a symbol between two ';'".to_string()),
+                    };
+
+                    let name = letters.as_str();
+                    let cell = variant
+                        .topology
+                        .parse_notation(name)
+                        .ok_or_else(|| format!("{:?} isn't a cell of the board", name))?;
+
+                    if symbols.insert(cell, symbol).is_some() {
+                        return Err(format!("{} is given twice", name));
+                    }
+                }
+
+                return Ok(symbols);
+            }
+        };
+
+        let ranks = self.board.split('/').collect::<Vec<_>>();
+        if ranks.len() != rows.len() {
+            return Err(format!(
+                "expected {} ranks, found {}",
+                rows.len(),
+                ranks.len()
+            ));
+        }
+
+        for (index, (rank, row)) in ranks.iter().zip(rows.iter()).enumerate() {
+            let mut cells = row.iter().copied();
+            let mut letters = rank.chars().peekable();
+            let too_long = || {
+                format!(
+                    "rank {} from the top has more than {} cells",
+                    index + 1,
+                    row.len()
+                )
+            };
+
+            while let Some(letter) = letters.next() {
+                if let Some(digit) = letter.to_digit(10) {
+                    let mut empty = digit as usize;
+                    while let Some(digit) = letters.peek().and_then(|letter| letter.to_digit(10)) {
+                        empty = empty.saturating_mul(10).saturating_add(digit as usize);
+                        letters.next();
+                    }
+
+                    for _ in 0..empty {
+                        let cell = cells.next().ok_or_else(too_long)?;
+                        if variant.blocked.contains(&cell) {
+                            return Err(format!(
+                                "{} is blocked, expected '#'",
+                                variant.topology.notation(cell)
+                            ));
+                        }
+                    }
+
+                    continue;
+                }
+
+                let cell = cells.next().ok_or_else(too_long)?;
+                match (letter, variant.blocked.contains(&cell)) {
+                    ('#', true) => {}
+                    ('#', false) => {
+                        return Err(format!("{} isn't blocked", variant.topology.notation(cell)))
+                    }
+                    (_, true) => {
+                        return Err(format!(
+                            "{} is blocked, expected '#'",
+                            variant.topology.notation(cell)
+                        ))
+                    }
+                    (_, false) => {
+                        symbols.insert(cell, Self::player(variant, letter)?);
+                    }
+                }
+            }
+
+            if cells.next().is_some() {
+                return Err(format!(
+                    "rank {} from the top has fewer than {} cells",
+                    index + 1,
+                    row.len()
+                ));
+            }
+        }
+
+        Ok(symbols)
+    }
+
+    /// The position to play on from, once checked that a game of `variant` can lead to it.
+    ///
+    /// Scripts can change the board in any way, the boards of scripted variants are only checked
+    /// against the board's shape.
+    pub fn position(
+        &self,
+        variant: &Variant,
+        rule_script: &mut RuleScript,
+    ) -> Result<Position, String> {
+        let players = &Symbol::PLAYERS[..variant.players];
+        let symbols = self.symbols(variant)?;
+
+        let (current_symbol, order) = match self.side.as_str() {
+            "-" => (Symbol::Empty, players.to_vec()),
+            side => {
+                if variant.random_turns {
+                    return Err(format!(
+                        "the coin picks the side to move in {}, expected '-'",
+                        variant
+                    ));
+                }
+
+                let mut order = side
+                    .chars()
+                    .map(|letter| Self::player(variant, letter))
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                // a single side to move takes the others in the usual order
+                if let [current_symbol] = order[..] {
+                    let start = players
+                        .iter()
+                        .position(|&player| player == current_symbol)
+                        .unwrap_or(0);
+                    order = players[start..]
+                        .iter()
+                        .chain(&players[..start])
+                        .copied()
+                        .collect();
+                }
+
+                if order.len() != players.len()
+                    || !players.iter().all(|player| order.contains(player))
+                {
+                    return Err(format!(
+                        "the turn order has to name each player of {} once",
+                        variant
+                    ));
+                }
+
+                (order[0], order)
+            }
+        };
+
+        if variant.script.is_none() {
+            check_reachable(variant, &symbols, &order, current_symbol)?;
+        }
+
+        let mut position = Position {
+            flips: if variant.random_turns {
+                symbols.len()
+            } else {
+                0
+            },
+            symbols,
+            current_symbol,
+            players: order,
+            ..Default::default()
+        };
+
+        let lines = rule_script.outcome(variant, &position.symbols);
+        let full = variant.is_full(&position.symbols);
+        if rules::resolve(
+            variant,
+            &lines,
+            full,
+            &mut position.players,
+            &mut position.standings,
+        ) {
+            position.current_symbol = Symbol::Empty;
+        } else if current_symbol == Symbol::Empty && !variant.random_turns {
+            return Err("nobody is to move but the game isn't over".to_string());
+        }
+
+        Ok(position)
+    }
+}
+
+/// Rejects the boards no game under the built-in rules leads to.
+fn check_reachable(
+    variant: &Variant,
+    symbols: &HashMap<Cell, Symbol>,
+    order: &[Symbol],
+    current_symbol: Symbol,
+) -> Result<(), String> {
+    // dropped symbols pile up from the bottom
+    if variant.gravity {
+        let mut cells = symbols.keys().copied().collect::<Vec<_>>();
+        cells.sort_unstable();
+
+        if let Some(&cell) = cells
+            .iter()
+            .find(|&&(q, r)| variant.is_playable((q, r + 1)) && !symbols.contains_key(&(q, r + 1)))
+        {
+            return Err(format!(
+                "{} floats above an empty cell",
+                variant.topology.notation(cell)
+            ));
+        }
+    }
+
+    // the game is over for a player as soon as they complete a line, so all of their lines
+    // went through the cell of their last move
+    let lines = variant.lines(symbols);
+    for &symbol in lines.iter() {
+        let completed_at_once =
+            symbols
+                .iter()
+                .filter(|(_, &played)| played == symbol)
+                .any(|(cell, _)| {
+                    let mut before = symbols.clone();
+                    before.remove(cell);
+                    !variant.lines(&before).contains(&symbol)
+                });
+
+        if !completed_at_once {
+            return Err(format!(
+                "{} has lines no single move could complete",
+                symbol
+            ));
+        }
+    }
+
+    // with more players the others play on once someone completes a line
+    if order.len() == 2 && lines.len() > 1 {
+        return Err(format!("both {} and {} have a line", lines[0], lines[1]));
+    }
+
+    if variant.random_turns || (order.len() > 2 && !lines.is_empty()) {
+        return Ok(());
+    }
+
+    // the players take turns, the first ones of the round have one more symbol than the others
+    let players = order.len();
+    let moves = symbols.len();
+    let count = |symbol| symbols.values().filter(|&&played| played == symbol).count();
+    let firsts = match order.iter().position(|&player| player == current_symbol) {
+        Some(current) => vec![(current + players - moves % players) % players],
+        None => (0..players).collect(),
+    };
+
+    let first = firsts.into_iter().find(|&first| {
+        order.iter().enumerate().all(|(index, &symbol)| {
+            let turn = (index + players - first) % players;
+            count(symbol) == moves / players + usize::from(turn < moves % players)
+        })
+    });
+
+    let first = match first {
+        Some(first) => first,
+        None => {
+            let counts = order
+                .iter()
+                .map(|&symbol| format!("{} {}", count(symbol), symbol))
+                .collect::<Vec<_>>()
+                .join(", ");
+
+            return Err(if current_symbol == Symbol::Empty {
+                format!("{} can't come from taking turns", counts)
+            } else {
+                format!(
+                    "{} can't come from taking turns with {} to move",
+                    counts, current_symbol
+                )
+            });
+        }
+    };
+
+    if let (Some(&symbol), Some(last)) = (lines.first(), moves.checked_sub(1)) {
+        let last = order[(first + last) % players];
+        if symbol != last {
+            return Err(format!("{} has a line but {} moved last", symbol, last));
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GAME: &str = "[Variant \"Classic\"]
+[Players \"X O\"]
+[Date \"2021-08-14\"]
+[Result \"*\"]
+
+1. X b2 2. O a1
+";
+
+    /// The Classic game with `moves` in place of its moves.
+    fn game(moves: &str) -> String {
+        GAME.replace("1. X b2 2. O a1", moves)
+    }
+
+    fn parse_error(text: &str) -> (usize, usize, String) {
+        let error = GameRecord::parse(text).expect_err("The record was parsed");
+
+        (error.line, error.column, error.message)
+    }
+
+    fn replay(text: &str) -> Result<(Vec<Move>, Vec<Position>), RecordError> {
+        GameRecord::parse(text)
+            .expect("Couldn't parse the record")
+            .replay(&Variant::default(), &mut RuleScript::default())
+    }
+
+    fn replay_error(text: &str) -> (usize, usize, String) {
+        let error = replay(text).expect_err("The record was replayed");
+
+        (error.line, error.column, error.message)
+    }
+
+    #[test]
+    fn parses_the_moves_where_they_start() {
+        let record = GameRecord::parse(GAME).expect("Couldn't parse the record");
+
+        assert_eq!(record.variant, "Classic");
+        assert_eq!(record.players, vec![Symbol::X, Symbol::O]);
+        assert_eq!(record.date, "2021-08-14");
+        assert_eq!(record.result, None);
+        assert_eq!(
+            record
+                .moves
+                .iter()
+                .map(|played| (
+                    played.symbol,
+                    played.cell.as_str(),
+                    played.line,
+                    played.column
+                ))
+                .collect::<Vec<_>>(),
+            vec![(Symbol::X, "b2", 6, 1), (Symbol::O, "a1", 6, 9)]
+        );
+    }
+
+    #[test]
+    fn skips_comments_and_unknown_tags() {
+        let text = GAME.replace("[Result", "[Event \"Club night\"]\n[Result") + "; 3. X c3\n";
+        let record = GameRecord::parse(&text).expect("Couldn't parse the record");
+
+        assert_eq!(record.moves.len(), 2);
+    }
+
+    #[test]
+    fn reports_where_the_records_are_malformed() {
+        assert_eq!(
+            parse_error("[Players \"X O\"]\n"),
+            (0, 0, "missing Variant tag".to_string())
+        );
+        assert_eq!(
+            parse_error(&GAME.replace("[Variant \"Classic\"]", "[Variant \"Classic\"")),
+            (1, 19, "expected ']'".to_string())
+        );
+        assert_eq!(
+            parse_error(&GAME.replace("[Variant \"Classic\"]", "  [Variant]")),
+            (1, 4, "expected a quoted value".to_string())
+        );
+        assert_eq!(
+            parse_error(&GAME.replace("X O", "X Q")),
+            (2, 1, "unknown player symbol in the Players tag".to_string())
+        );
+        assert_eq!(
+            parse_error(&GAME.replace("\"*\"", "\"X >\"")),
+            (4, 1, "malformed result in the Result tag".to_string())
+        );
+        assert_eq!(
+            parse_error(&game("1. X b2 3. O a1")),
+            (6, 9, "expected \"2.\", found \"3.\"".to_string())
+        );
+        assert_eq!(
+            parse_error(&game("1. X b2 2. O")),
+            (6, 9, "a move needs a symbol and a cell".to_string())
+        );
+        assert_eq!(
+            parse_error(&game("1. X b2 2. Q a1")),
+            (6, 12, "unknown player symbol \"Q\"".to_string())
+        );
+        assert_eq!(
+            parse_error(&GAME.replace("[Result", "[Ply \"3\"]\n[Result")),
+            (0, 0, "the Ply tag is past the last move".to_string())
+        );
+    }
+
+    #[test]
+    fn writes_records_it_reads_back() {
+        let record = GameRecord {
+            variant: "Classic".to_string(),
+            players: vec![Symbol::O, Symbol::X],
+            date: "2021-08-14".to_string(),
+            game: Some("00c0ffee".to_string()),
+            result: Some(Standings(vec![vec![Symbol::O], vec![Symbol::X]])),
+            seed: 42,
+            setup: Some("3/3/3 O Classic".to_string()),
+            ply: Some(1),
+            slot: Some("Sunday \"rematch\"".to_string()),
+            moves: vec![
+                RecordedMove {
+                    symbol: Symbol::O,
+                    cell: "b2".to_string(),
+                    line: 0,
+                    column: 0,
+                },
+                RecordedMove {
+                    symbol: Symbol::X,
+                    cell: "a1".to_string(),
+                    line: 0,
+                    column: 0,
+                },
+            ],
+            chat: vec![ChatLine {
+                ply: 1,
+                name: "alice: the \\first".to_string(),
+                text: "good luck: have fun".to_string(),
+            }],
+            signature: Some("0123abcd".to_string()),
+        };
+
+        let text = record.to_string();
+        let parsed = GameRecord::parse(&text).expect("Couldn't parse the record");
+
+        assert_eq!(parsed.to_string(), text);
+        assert_eq!(parsed.game, record.game);
+        assert_eq!(parsed.result, record.result);
+        assert_eq!(parsed.seed, record.seed);
+        assert_eq!(parsed.setup, record.setup);
+        assert_eq!(parsed.ply, record.ply);
+        assert_eq!(parsed.slot, record.slot);
+        assert_eq!(parsed.signature, record.signature);
+        assert_eq!(parsed.chat, record.chat);
+    }
+
+    fn setup_error(text: &str, variant: &Variant) -> String {
+        Setup::parse(text)
+            .and_then(|setup| setup.position(variant, &mut RuleScript::default()))
+            .expect_err("The position was accepted")
+    }
+
+    #[test]
+    fn sets_reachable_positions_up() {
+        let position = Setup::parse("X2/1O1/3 X Classic")
+            .and_then(|setup| setup.position(&Variant::default(), &mut RuleScript::default()))
+            .expect("Couldn't set the position up");
+
+        assert_eq!(position.symbols.len(), 2);
+        assert_eq!(position.current_symbol, Symbol::X);
+    }
+
+    #[test]
+    fn rejects_floating_symbols_with_gravity() {
+        let variant = Variant {
+            gravity: true,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            setup_error("X2/3/1O1 X Classic", &variant),
+            "a3 floats above an empty cell"
+        );
+    }
+
+    #[test]
+    fn rejects_lines_no_single_move_completes() {
+        assert_eq!(
+            setup_error("XXX/OO1/XXX O Classic", &Variant::default()),
+            "X has lines no single move could complete"
+        );
+    }
+
+    #[test]
+    fn rejects_lines_for_both_players() {
+        let error = setup_error("XXX/OOO/3 X Classic", &Variant::default());
+
+        assert!(error == "both X and O have a line" || error == "both O and X have a line");
+    }
+
+    #[test]
+    fn rejects_symbol_counts_turns_cant_lead_to() {
+        assert_eq!(
+            setup_error("XX1/3/3 X Classic", &Variant::default()),
+            "2 X, 0 O can't come from taking turns with X to move"
+        );
+        assert_eq!(
+            setup_error("XXX/O2/3 - Classic", &Variant::default()),
+            "3 X, 1 O can't come from taking turns"
+        );
+    }
+
+    #[test]
+    fn rejects_lines_of_the_player_who_didnt_move_last() {
+        assert_eq!(
+            setup_error("XXX/OO1/O2 - Classic", &Variant::default()),
+            "X has a line but O moved last"
+        );
+    }
+
+    #[test]
+    fn replays_the_moves_into_positions() {
+        let (moves, positions) = replay(GAME).expect("Couldn't replay the record");
+
+        assert_eq!(moves.len(), 2);
+        assert_eq!(positions.len(), 3);
+        assert!(positions[0].symbols.is_empty());
+        assert_eq!(positions[2].symbols.len(), 2);
+        assert_eq!(positions[2].current_symbol, Symbol::X);
+    }
+
+    #[test]
+    fn rejects_moves_the_rules_dont_allow() {
+        assert_eq!(
+            replay_error(&game("1. X b2 2. X a1")),
+            (6, 9, "it's O's turn".to_string())
+        );
+        assert_eq!(
+            replay_error(&game("1. X b2 2. O b2")),
+            (6, 9, "b2 can't be played".to_string())
+        );
+        assert_eq!(
+            replay_error(&game("1. X b2 2. O d4")),
+            (6, 9, "\"d4\" isn't a cell of the board".to_string())
+        );
+        assert_eq!(
+            replay_error(&game("1. X a1 2. O a2 3. X b1 4. O b2 5. X c1 6. O c2")),
+            (6, 41, "the game is already over".to_string())
+        );
+        assert_eq!(
+            replay_error(&GAME.replace("X O", "X X")),
+            (0, 0, "Classic needs 2 different players".to_string())
+        );
+    }
+
+    /// The game of id `abc` with `moves`, as sent by mail.
+    fn mailed(moves: &str) -> GameRecord {
+        GameRecord::parse(&game(moves).replace("[Result", "[Game \"abc\"]\n[Result"))
+            .expect("Couldn't parse the record")
+    }
+
+    #[test]
+    fn follows_the_previous_file_by_one_move() {
+        let previous = mailed("1. X b2");
+        assert_eq!(mailed("1. X b2 2. O a1").follows(&previous), Ok(()));
+
+        let other_game = GameRecord {
+            game: Some("abd".to_string()),
+            ..mailed("1. X b2 2. O a1")
+        };
+        assert_eq!(
+            other_game.follows(&previous),
+            Err("it's another game".to_string())
+        );
+
+        let other_date = GameRecord {
+            date: "2021-08-15".to_string(),
+            ..mailed("1. X b2 2. O a1")
+        };
+        assert_eq!(
+            other_date.follows(&previous),
+            Err("the game's tags changed".to_string())
+        );
+
+        assert_eq!(
+            mailed("1. X b2 2. O a1 3. X c3").follows(&previous),
+            Err("expected 2 moves, one more than before, found 3".to_string())
+        );
+        assert_eq!(
+            mailed("1. X c3 2. O a1").follows(&previous),
+            Err("the earlier moves changed".to_string())
+        );
+        assert_eq!(
+            mailed("1. X b2 2. X a1").follows(&previous),
+            Err("X played twice in a row".to_string())
+        );
+    }
+
+    #[test]
+    fn takes_only_draws_as_agreed_results() {
+        let (_, positions) =
+            replay(&GAME.replace("[Result \"*\"]", "[Result \"X=O\"]")).expect("Couldn't replay");
+        let last = positions.last().expect("Couldn't find the last position");
+        assert!(last.players.is_empty());
+        assert_eq!(last.standings, Standings(vec![vec![Symbol::X, Symbol::O]]));
+
+        assert_eq!(
+            replay_error(&GAME.replace("[Result \"*\"]", "[Result \"X > O\"]")),
+            (
+                0,
+                0,
+                "the Result tag is neither where the moves lead nor a draw".to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn takes_only_the_result_of_finished_games() {
+        let won = game("1. X a1 2. O b1 3. X a2 4. O b2 5. X a3");
+        replay(&won.replace("*", "X > O")).expect("Couldn't replay");
+
+        for result in &["O > X", "X=O"] {
+            assert_eq!(
+                replay_error(&won.replace("*", result)),
+                (
+                    0,
+                    0,
+                    "the Result tag is neither where the moves lead nor a draw".to_string()
+                )
+            );
+        }
+    }
+}
@@ -0,0 +1,320 @@
+use bevy::prelude::*;
+
+use crate::{
+    button::{ButtonAssets, ButtonElement, SpawnButton},
+    chat::Chat,
+    coin::CoinToss,
+    history::History,
+    net::{start_over, Remote, RemoteGame},
+    notation::GameRecord,
+    protocol::{player_name, Message, Offer, OFFER_SECONDS},
+    rules::{Symbol, Variant},
+    script::RuleScript,
+    symbol::Players,
+    ui::{NodeElement, Status},
+    AppState, WinningEvent,
+};
+
+pub struct OfferPlugin;
+
+impl Plugin for OfferPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_system_set(
+            SystemSet::on_update(AppState::Game)
+                .with_system(offer_button_system)
+                .with_system(answer_button_system)
+                .with_system(offer_timeout_system)
+                .with_system(offer_overlay_system),
+        );
+    }
+}
+
+/// An offer waiting for an answer, nobody plays meanwhile.
+pub struct PendingOffer {
+    pub offer: Offer,
+    /// Made on this computer, the other player answers it.
+    pub mine: bool,
+    /// Seconds since startup it's declined at.
+    pub deadline: f64,
+}
+
+impl PendingOffer {
+    pub fn new(offer: Offer, mine: bool, now: f64) -> Self {
+        Self {
+            offer,
+            mine,
+            deadline: now + OFFER_SECONDS as f64,
+        }
+    }
+}
+
+/// Carries an accepted offer out on the host, the other player gets the game again.
+pub fn carry_out(
+    offer: Offer,
+    remote_game: &mut RemoteGame,
+    variant: &Variant,
+    rule_script: &mut RuleScript,
+    history: &mut History,
+    coin_toss: &mut CoinToss,
+    chat: &mut Chat,
+    winning_events: &mut EventWriter<WinningEvent>,
+) -> Result<(), String> {
+    let mut record = GameRecord::new(
+        variant,
+        if variant.random_turns {
+            coin_toss.seed
+        } else {
+            0
+        },
+        &history.moves[..history.cursor],
+        &history.positions[..=history.cursor],
+    );
+    record.chat = chat.lines.clone();
+    let record = offer.accept(&record, variant, rule_script)?;
+
+    // the host plays the first symbol and the other player the second one, rematches swap them
+    let other = |symbol| {
+        if symbol == Symbol::PLAYERS[0] {
+            Symbol::PLAYERS[1]
+        } else {
+            Symbol::PLAYERS[0]
+        }
+    };
+    if offer == Offer::Rematch {
+        remote_game.symbol = other(remote_game.symbol);
+    }
+
+    remote_game.send(&Message::Welcome {
+        name: player_name(),
+        record: record.to_string(),
+        symbol: other(remote_game.symbol),
+        token: None,
+    });
+
+    start_over(
+        &record,
+        variant,
+        rule_script,
+        history,
+        coin_toss,
+        chat,
+        winning_events,
+    )
+}
+
+fn offer_button_system(
+    interaction_query: Query<(&Interaction, &ButtonElement), (Changed<Interaction>, With<Button>)>,
+    time: Res<Time>,
+    history: Res<History>,
+    players: Res<Players>,
+    mut remote: ResMut<Remote>,
+    mut status: ResMut<Status>,
+) {
+    for (interaction, button) in interaction_query.iter() {
+        if *interaction != Interaction::Clicked {
+            continue;
+        }
+
+        let offer = match button {
+            ButtonElement::Rematch => Offer::Rematch,
+            ButtonElement::Takeback => Offer::Takeback,
+            ButtonElement::OfferDraw => Offer::Draw,
+            _ => continue,
+        };
+        let remote_game = match remote.0 {
+            Some(ref mut remote_game) if remote_game.symbol != Symbol::Empty => remote_game,
+            _ => continue,
+        };
+
+        // checked again by the host or the server
+        let allowed = if remote_game.gap.is_some() {
+            Err("a player is away".to_string())
+        } else if remote_game.offer.is_some() {
+            Err("an offer is waiting for an answer".to_string())
+        } else {
+            offer.allowed(history.cursor, players.0.is_empty())
+        };
+        if let Err(reason) = allowed {
+            status.0 = Some(format!("Can't ask for {}: {}", offer, reason));
+            continue;
+        }
+
+        remote_game.send(&Message::Offer {
+            offer,
+            ply: history.cursor,
+        });
+        remote_game.offer = Some(PendingOffer::new(offer, true, time.seconds_since_startup()));
+        status.0 = Some(format!("Asked {} for {}", remote_game.name, offer));
+    }
+}
+
+fn answer_button_system(
+    interaction_query: Query<(&Interaction, &ButtonElement), (Changed<Interaction>, With<Button>)>,
+    variant: Res<Variant>,
+    mut remote: ResMut<Remote>,
+    mut rule_script: ResMut<RuleScript>,
+    mut history: ResMut<History>,
+    mut coin_toss: ResMut<CoinToss>,
+    mut chat: ResMut<Chat>,
+    mut winning_events: EventWriter<WinningEvent>,
+    mut status: ResMut<Status>,
+) {
+    for (interaction, button) in interaction_query.iter() {
+        if *interaction != Interaction::Clicked {
+            continue;
+        }
+
+        let accepted = match button {
+            ButtonElement::Accept => true,
+            ButtonElement::Decline => false,
+            _ => continue,
+        };
+        let remote_game = match remote.0 {
+            Some(ref mut remote_game) => remote_game,
+            None => continue,
+        };
+        let offer = match remote_game.offer.take() {
+            Some(pending) if !pending.mine => pending.offer,
+            pending => {
+                remote_game.offer = pending;
+                continue;
+            }
+        };
+
+        remote_game.send(&Message::Answer { offer, accepted });
+        if !accepted {
+            continue;
+        }
+
+        // servers carry it out themselves
+        if remote_game.host {
+            if let Err(error) = carry_out(
+                offer,
+                remote_game,
+                &variant,
+                &mut rule_script,
+                &mut history,
+                &mut coin_toss,
+                &mut chat,
+                &mut winning_events,
+            ) {
+                status.0 = Some(format!("Couldn't carry {} out: {}", offer, error));
+            }
+        }
+    }
+}
+
+/// Declines the offers nobody answered in time, the host tells the other player.
+fn offer_timeout_system(time: Res<Time>, mut remote: ResMut<Remote>, mut status: ResMut<Status>) {
+    let remote_game = match remote.0 {
+        Some(ref mut remote_game) => remote_game,
+        None => return,
+    };
+    let offer = match remote_game.offer {
+        Some(ref pending) if time.seconds_since_startup() > pending.deadline => pending.offer,
+        _ => return,
+    };
+
+    remote_game.offer = None;
+    if remote_game.host {
+        remote_game.send(&Message::Answer {
+            offer,
+            accepted: false,
+        });
+    }
+    status.0 = Some(format!("Nobody answered the offer of {}", offer));
+}
+
+pub struct OfferText;
+
+/// Asks for an answer to the other player's offer, over the board.
+fn offer_overlay_system(
+    mut commands: Commands,
+    time: Res<Time>,
+    remote: Res<Remote>,
+    button_assets: Res<ButtonAssets>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    overlay_query: Query<(Entity, &NodeElement)>,
+    mut text_query: Query<&mut Text, With<OfferText>>,
+) {
+    let overlay = overlay_query
+        .iter()
+        .find(|(_, node_element)| matches!(node_element, NodeElement::Offer))
+        .map(|(entity, _)| entity);
+
+    let asked = remote.0.as_ref().and_then(|remote_game| {
+        remote_game
+            .offer
+            .as_ref()
+            .filter(|pending| !pending.mine)
+            .map(|pending| (remote_game, pending))
+    });
+    let message = match asked {
+        Some((remote_game, pending)) => format!(
+            "{} asks for {}... {:.0}s",
+            remote_game.name,
+            pending.offer,
+            (pending.deadline - time.seconds_since_startup()).max(0.0)
+        ),
+        None => {
+            if let Some(overlay) = overlay {
+                commands.entity(overlay).despawn_recursive();
+            }
+
+            return;
+        }
+    };
+
+    if overlay.is_some() {
+        for mut text in text_query.iter_mut() {
+            if text.sections[0].value != message {
+                text.sections[0].value = message.clone();
+            }
+        }
+
+        return;
+    }
+
+    let none = materials.add(Color::NONE.into());
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                flex_direction: FlexDirection::ColumnReverse,
+                ..Default::default()
+            },
+            material: materials.add(Color::rgba(0.0, 0.0, 0.0, 0.6).into()),
+            ..Default::default()
+        })
+        .insert(NodeElement::Offer)
+        .with_children(|overlay| {
+            overlay
+                .spawn_bundle(TextBundle {
+                    text: Text::with_section(
+                        message,
+                        TextStyle {
+                            font: button_assets.font.clone(),
+                            font_size: 30.0,
+                            color: Color::WHITE,
+                        },
+                        Default::default(),
+                    ),
+                    ..Default::default()
+                })
+                .insert(OfferText);
+
+            overlay
+                .spawn_bundle(NodeBundle {
+                    material: none,
+                    ..Default::default()
+                })
+                .with_children(|answers| {
+                    answers
+                        .spawn_button(&button_assets, ButtonElement::Accept)
+                        .spawn_button(&button_assets, ButtonElement::Decline);
+                });
+        });
+}
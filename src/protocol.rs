@@ -0,0 +1,482 @@
+use std::{env, fmt, io, sync::Mutex};
+#[cfg(not(target_arch = "wasm32"))]
+use std::{
+    net::TcpStream,
+    sync::mpsc::{self, Receiver, Sender, TryRecvError},
+    thread,
+    time::Duration,
+};
+
+use rand::random;
+use serde::{Deserialize, Serialize};
+
+#[cfg(not(target_arch = "wasm32"))]
+use crate::transport::TcpTransport;
+use crate::{
+    notation::{today, GameRecord},
+    rules::{self, Cell, Symbol, Variant},
+    script::RuleScript,
+    transport::{Transport, WebSocketTransport},
+};
+
+/// Bumped whenever the messages change, peers speaking another version are turned away.
+pub const PROTOCOL_VERSION: u32 = 6;
+pub const DEFAULT_PORT: u16 = 7878;
+/// Addresses starting with it are reached over WebSocket, the same port as the plain clients.
+pub const WEB_SOCKET_SCHEME: &str = "ws://";
+/// Port the hosts broadcast their games to.
+pub const DISCOVERY_PORT: u16 = 7879;
+/// Seconds a server keeps a seat for a player who lost the connection.
+pub const GRACE_SECONDS: u64 = 60;
+/// Chat messages a player may send every `CHAT_WINDOW_SECONDS`, the others are dropped.
+pub const CHAT_LIMIT: usize = 5;
+pub const CHAT_WINDOW_SECONDS: u64 = 10;
+/// Characters of a chat message kept.
+pub const CHAT_LENGTH: usize = 200;
+/// Seconds an offer waits for an answer, it's declined after them.
+pub const OFFER_SECONDS: u64 = 30;
+/// Time a peer gets to open the connection, the WebSocket handshake or the first line.
+#[cfg(not(target_arch = "wasm32"))]
+const OPENING_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Sent as one JSON object per line, in both directions.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum Message {
+    /// First message of the joining player.
+    Hello { version: u32, name: String },
+    /// The game to play as a `.ttt` record, and the symbol the joining player plays.
+    /// `name` is the host's, or the other players' of a server room.
+    /// Servers send the whole game again to players resuming it, `symbol` is empty for spectators.
+    Welcome {
+        name: String,
+        record: String,
+        symbol: Symbol,
+        /// Given by servers, to resume the game after losing the connection.
+        token: Option<String>,
+    },
+    /// Turned away, the connection gets closed right after.
+    Refused { reason: String },
+    /// Asked for by the joining player, or accepted and played by the host.
+    /// `ply` is the number of moves played before it, to catch peers out of step.
+    Move { ply: usize, cell: Cell },
+    /// The host didn't accept a move.
+    Rejected { reason: String },
+    /// Sent to a server after the hello, creates the room with the variant unless it's there already.
+    JoinRoom { room: String, variant: String },
+    /// Sent to a server after the hello instead of joining, to take a seat back.
+    Resume { room: String, token: String },
+    /// Sent to a server after the hello instead of joining, to follow the game of a room.
+    Watch { room: String },
+    /// Sent to a server after the hello instead of joining, takes a seat in any room waiting for
+    /// players of the variant, or opens one.
+    QuickMatch { variant: String },
+    /// Spectators of the room, sent whenever one comes or goes.
+    Spectators { count: usize },
+    /// Said by `name`, servers pass the spectators' messages to the other spectators only.
+    Chat { name: String, text: String },
+    /// Asked of the other player once `ply` moves were played, nobody plays until it's answered.
+    Offer { offer: Offer, ply: usize },
+    /// The other player's answer, or a decline once the offer timed out. Accepted offers are
+    /// carried out by the host or the server, the game comes again with a welcome.
+    Answer { offer: Offer, accepted: bool },
+    /// A player lost the connection, the server waits `seconds` for them to resume.
+    Away { name: String, seconds: u64 },
+    /// The player who was away resumed the game.
+    Back { name: String },
+    /// The room is waiting for more players, the game starts with a welcome once every seat is taken.
+    Waiting {
+        room: String,
+        players: usize,
+        seats: usize,
+    },
+}
+
+/// What a player of a two-player networked game can ask the other one for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Offer {
+    /// A new game, the players swap their symbols.
+    Rematch,
+    /// Taking the last move back.
+    Takeback,
+    Draw,
+}
+
+impl fmt::Display for Offer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Rematch => write!(f, "a rematch"),
+            Self::Takeback => write!(f, "a takeback"),
+            Self::Draw => write!(f, "a draw"),
+        }
+    }
+}
+
+impl Offer {
+    /// Whether it can be offered after `moves` moves, `finished` once every player got a rank.
+    pub fn allowed(self, moves: usize, finished: bool) -> Result<(), String> {
+        match self {
+            Self::Rematch if !finished => Err("the game isn't over".to_string()),
+            Self::Takeback if moves == 0 => Err("there's no move to take back".to_string()),
+            Self::Takeback | Self::Draw if finished => Err("the game is over".to_string()),
+            _ => Ok(()),
+        }
+    }
+
+    /// The game once the offer is accepted, a new one for rematches.
+    pub fn accept(
+        self,
+        record: &GameRecord,
+        variant: &Variant,
+        rule_script: &mut RuleScript,
+    ) -> Result<GameRecord, String> {
+        let mut accepted = record.clone();
+        accepted.ply = None;
+
+        match self {
+            Self::Rematch => {
+                accepted.date = today();
+                accepted.result = None;
+                accepted.seed = if variant.random_turns { random() } else { 0 };
+                accepted.moves.clear();
+                accepted.chat.clear();
+            }
+            Self::Takeback => {
+                accepted.result = None;
+                accepted.moves.pop();
+            }
+            Self::Draw => {
+                let (_, positions) = record
+                    .replay(variant, rule_script)
+                    .map_err(|error| error.to_string())?;
+                let mut last = positions
+                    .last()
+                    .cloned()
+                    .expect("Couldn't find the last position");
+
+                rules::resolve(variant, &[], true, &mut last.players, &mut last.standings);
+                accepted.result = Some(last.standings);
+            }
+        }
+
+        Ok(accepted)
+    }
+}
+
+/// Broadcast over UDP by the hosts every now and then, so the joining players can list their games.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Announcement {
+    pub version: u32,
+    pub name: String,
+    pub variant: String,
+    /// Players in the game so far, out of `seats`.
+    pub players: usize,
+    pub seats: usize,
+    /// Port the game is hosted on, at the address the announcement came from.
+    pub port: u16,
+}
+
+/// Name shown to the other players, the user's login.
+pub fn player_name() -> String {
+    env::var("USER")
+        .or_else(|_| env::var("USERNAME"))
+        .unwrap_or_else(|_| "Player".to_string())
+}
+
+/// A chat message on one line, without control characters and at most `CHAT_LENGTH` long.
+pub fn chat_text(text: &str) -> String {
+    text.chars()
+        .filter(|character| !character.is_control())
+        .take(CHAT_LENGTH)
+        .collect::<String>()
+        .trim()
+        .to_string()
+}
+
+/// Messages to and from a peer, carried on a thread of their own so nothing ever blocks. Dropping
+/// it closes the connection once the messages sent so far are on their way.
+pub struct Connection {
+    #[cfg(not(target_arch = "wasm32"))]
+    outgoing: Mutex<Sender<Message>>,
+    #[cfg(not(target_arch = "wasm32"))]
+    incoming: Mutex<Receiver<io::Result<Message>>>,
+    /// Web pages have no threads to spare, `receive` polls the transport.
+    #[cfg(target_arch = "wasm32")]
+    transport: Mutex<Box<dyn Transport>>,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Connection {
+    /// Speaks the game's own JSON lines.
+    pub fn new(stream: TcpStream) -> io::Result<Self> {
+        Self::spawn(stream, |stream| {
+            TcpTransport::new(stream).map(|transport| Box::new(transport) as Box<dyn Transport>)
+        })
+    }
+
+    /// Speaks to a server over WebSocket, its address starting with `ws://`.
+    pub fn web_socket(stream: TcpStream, address: &str) -> io::Result<Self> {
+        let url = format!("{}/", address.trim_end_matches('/'));
+
+        Self::spawn(stream, move |stream| {
+            WebSocketTransport::connect(stream, &url)
+                .map(|transport| Box::new(transport) as Box<dyn Transport>)
+        })
+    }
+
+    /// Connects over WebSocket or TCP depending on the address it's for.
+    pub fn open(stream: TcpStream, address: &str) -> io::Result<Self> {
+        if address.starts_with(WEB_SOCKET_SCHEME) {
+            Self::web_socket(stream, address)
+        } else {
+            Self::new(stream)
+        }
+    }
+
+    /// Takes a client in, whichever way it speaks.
+    pub fn accept(stream: TcpStream) -> io::Result<Self> {
+        Self::spawn(stream, |stream| {
+            // the lines start with the brace of a message, the WebSocket handshake with a GET
+            let mut first = [0];
+            stream.peek(&mut first)?;
+
+            if first[0] == b'G' {
+                WebSocketTransport::accept(stream)
+                    .map(|transport| Box::new(transport) as Box<dyn Transport>)
+            } else {
+                TcpTransport::new(stream).map(|transport| Box::new(transport) as Box<dyn Transport>)
+            }
+        })
+    }
+
+    fn spawn<F>(stream: TcpStream, open: F) -> io::Result<Self>
+    where
+        F: FnOnce(TcpStream) -> io::Result<Box<dyn Transport>> + Send + 'static,
+    {
+        // accepted streams inherit the listener's mode on some platforms
+        stream.set_nonblocking(false)?;
+        stream.set_nodelay(true)?;
+        // a peer that never opens the connection doesn't keep the thread
+        stream.set_read_timeout(Some(OPENING_TIMEOUT))?;
+
+        let (outgoing, to_send) = mpsc::channel::<Message>();
+        let (sender, incoming) = mpsc::channel();
+
+        thread::spawn(move || {
+            let mut transport = match open(stream) {
+                Ok(transport) => transport,
+                Err(error) => {
+                    let _ = sender.send(Err(error));
+                    return;
+                }
+            };
+
+            loop {
+                let mut sent = Ok(());
+                let dropped = loop {
+                    match to_send.try_recv() {
+                        Ok(message) => {
+                            sent = sent.and_then(|_| {
+                                serde_json::to_string(&message)
+                                    .map_err(io::Error::from)
+                                    .and_then(|text| transport.send(&text))
+                            })
+                        }
+                        Err(TryRecvError::Empty) => break false,
+                        Err(TryRecvError::Disconnected) => break true,
+                    }
+                };
+
+                // the connection closes with the transport, once what was sent before the drop is out
+                if dropped {
+                    return;
+                }
+
+                let message = sent.and_then(|_| transport.receive()).and_then(|text| {
+                    text.map(|text| {
+                        serde_json::from_str(&text)
+                            .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))
+                    })
+                    .transpose()
+                });
+
+                // stops once the peer broke the protocol, the drop is noticed when sending
+                match message {
+                    Ok(None) => continue,
+                    Ok(Some(message)) => {
+                        let _ = sender.send(Ok(message));
+                    }
+                    Err(error) => {
+                        let _ = sender.send(Err(error));
+                        return;
+                    }
+                }
+            }
+        });
+
+        Ok(Self {
+            outgoing: Mutex::new(outgoing),
+            incoming: Mutex::new(incoming),
+        })
+    }
+
+    pub fn send(&self, message: &Message) -> io::Result<()> {
+        self.outgoing
+            .lock()
+            .expect("Couldn't lock the outgoing messages")
+            .send(message.clone())
+            .map_err(|_| {
+                io::Error::new(
+                    io::ErrorKind::ConnectionAborted,
+                    "the connection was closed",
+                )
+            })
+    }
+
+    /// Next message of the peer, `None` until one comes, an error once the peer is gone.
+    pub fn receive(&self) -> io::Result<Option<Message>> {
+        match self
+            .incoming
+            .lock()
+            .expect("Couldn't lock the incoming messages")
+            .try_recv()
+        {
+            Ok(message) => message.map(Some),
+            Err(TryRecvError::Empty) => Ok(None),
+            Err(TryRecvError::Disconnected) => Err(io::Error::new(
+                io::ErrorKind::ConnectionAborted,
+                "the connection was closed",
+            )),
+        }
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+impl Connection {
+    /// Speaks to a server over the browser's WebSocket, its address starting with `ws://`.
+    pub fn web_socket(address: &str) -> io::Result<Self> {
+        let url = format!("{}/", address.trim_end_matches('/'));
+
+        WebSocketTransport::connect(&url).map(|transport| Self {
+            transport: Mutex::new(Box::new(transport)),
+        })
+    }
+
+    pub fn send(&self, message: &Message) -> io::Result<()> {
+        let text = serde_json::to_string(message)?;
+
+        self.transport
+            .lock()
+            .expect("Couldn't lock the transport")
+            .send(&text)
+    }
+
+    /// Next message of the peer, `None` until one comes, an error once the peer is gone.
+    pub fn receive(&self) -> io::Result<Option<Message>> {
+        let text = self
+            .transport
+            .lock()
+            .expect("Couldn't lock the transport")
+            .receive()?;
+
+        text.map(|text| {
+            serde_json::from_str(&text)
+                .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))
+        })
+        .transpose()
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use std::{net::TcpListener, time::Instant};
+
+    use super::*;
+    use crate::transport::POLL_INTERVAL;
+
+    fn hello() -> Message {
+        Message::Hello {
+            version: PROTOCOL_VERSION,
+            name: "Ann".to_string(),
+        }
+    }
+
+    fn welcome() -> Message {
+        Message::Welcome {
+            name: "Bob".to_string(),
+            record: String::new(),
+            symbol: Symbol::O,
+            token: None,
+        }
+    }
+
+    /// A client and the server's side of its connection, taken in with `accept`.
+    fn connect(web_socket: bool) -> (Connection, Connection) {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("Couldn't listen");
+        let address = listener
+            .local_addr()
+            .expect("Couldn't find the address")
+            .to_string();
+
+        let stream = TcpStream::connect(&address).expect("Couldn't connect");
+        let client = if web_socket {
+            Connection::web_socket(stream, &format!("{}{}", WEB_SOCKET_SCHEME, address))
+        } else {
+            Connection::new(stream)
+        }
+        .expect("Couldn't open the connection");
+        let (stream, _) = listener.accept().expect("Couldn't accept the client");
+
+        (
+            client,
+            Connection::accept(stream).expect("Couldn't take the client in"),
+        )
+    }
+
+    /// What the peer sends next, waiting a second at most.
+    fn next(connection: &Connection) -> io::Result<Message> {
+        let deadline = Instant::now() + Duration::from_secs(1);
+
+        loop {
+            match connection.receive()? {
+                Some(message) => return Ok(message),
+                None if Instant::now() < deadline => thread::sleep(POLL_INTERVAL),
+                None => panic!("Nothing came"),
+            }
+        }
+    }
+
+    #[test]
+    fn carries_messages_both_ways() {
+        for &web_socket in &[false, true] {
+            let (client, server) = connect(web_socket);
+
+            client.send(&hello()).expect("Couldn't send");
+            assert_eq!(next(&server).expect("Couldn't receive"), hello());
+            server.send(&welcome()).expect("Couldn't send");
+            assert_eq!(next(&client).expect("Couldn't receive"), welcome());
+        }
+    }
+
+    #[test]
+    fn sends_what_was_sent_before_the_drop() {
+        for &web_socket in &[false, true] {
+            let (client, server) = connect(web_socket);
+            let refused = Message::Refused {
+                reason: "the room is full".to_string(),
+            };
+
+            // the server tells the transports apart by what the client sends first
+            client.send(&hello()).expect("Couldn't send");
+            assert_eq!(next(&server).expect("Couldn't receive"), hello());
+
+            server.send(&welcome()).expect("Couldn't send");
+            server.send(&refused).expect("Couldn't send");
+            drop(server);
+
+            assert_eq!(next(&client).expect("Couldn't receive"), welcome());
+            assert_eq!(next(&client).expect("Couldn't receive"), refused);
+            assert!(next(&client).is_err());
+        }
+    }
+}
@@ -0,0 +1,206 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use bevy::{prelude::*, window::FileDragAndDrop};
+
+use crate::{
+    chat::Chat,
+    coin::CoinToss,
+    history::History,
+    notation::{today, GameRecord},
+    rules::Variant,
+    script::RuleScript,
+    symbol::BoardView,
+    ui::Status,
+    variant::{ScriptSource, ScriptState, Variants},
+    AppState,
+};
+
+/// Folder the games are saved to, next to the executable's working directory.
+pub const RECORDS_FOLDER: &str = "records";
+
+pub struct RecordPlugin;
+
+impl Plugin for RecordPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<PendingRecord>()
+            .add_system_set(
+                SystemSet::on_update(AppState::Menu)
+                    .with_system(drop_record_system)
+                    .with_system(start_record_system),
+            )
+            .add_system_set(
+                SystemSet::on_update(AppState::GameList).with_system(start_record_system),
+            )
+            // networked games start from the host's record
+            .add_system_set(SystemSet::on_update(AppState::Lobby).with_system(start_record_system))
+            .add_system_set(SystemSet::on_update(AppState::Game).with_system(save_record_system));
+    }
+}
+
+/// Record opened from the menu or the game list, waiting for its variant and rule script to be
+/// ready, with the state it opens in.
+#[derive(Default)]
+pub struct PendingRecord(pub Option<(GameRecord, AppState)>);
+
+impl PendingRecord {
+    /// Reads the record and selects its variant, `state` is entered once the variant is in use.
+    pub fn open(
+        &mut self,
+        path: &Path,
+        variant_assets: &Assets<Variant>,
+        variants: &mut Variants,
+        state: AppState,
+    ) -> Result<(), String> {
+        let record = read_record_file(path)?;
+
+        self.open_record(record, variant_assets, variants, state)
+    }
+
+    pub fn open_record(
+        &mut self,
+        record: GameRecord,
+        variant_assets: &Assets<Variant>,
+        variants: &mut Variants,
+        state: AppState,
+    ) -> Result<(), String> {
+        // the selected variant gets synced to the rules by the variant plugin
+        let (handle, _) = variants
+            .loaded(variant_assets)
+            .into_iter()
+            .find(|(_, variant)| variant.name == record.variant)
+            .ok_or_else(|| format!("Unknown variant {}", record.variant))?;
+
+        variants.selected = handle;
+        self.0 = Some((record, state));
+
+        Ok(())
+    }
+}
+
+/// Reads and parses a record, the errors name the file.
+pub fn read_record_file(path: &Path) -> Result<GameRecord, String> {
+    fs::read_to_string(path)
+        .map_err(|error| error.to_string())
+        .and_then(|text| GameRecord::parse(&text).map_err(|error| error.to_string()))
+        .map_err(|error| format!("{}: {}", path.display(), error))
+}
+
+fn drop_record_system(
+    mut drop_events: EventReader<FileDragAndDrop>,
+    variant_assets: Res<Assets<Variant>>,
+    mut variants: ResMut<Variants>,
+    mut pending_record: ResMut<PendingRecord>,
+    mut status: ResMut<Status>,
+) {
+    for event in drop_events.iter() {
+        if let FileDragAndDrop::DroppedFile { path_buf, .. } = event {
+            let opened = read_record_file(path_buf).and_then(|record| {
+                // games played by file are checked and opened by the correspondence plugin
+                if record.game.is_some() {
+                    return Ok(());
+                }
+
+                pending_record.open_record(record, &variant_assets, &mut variants, AppState::Game)
+            });
+
+            if let Err(error) = opened {
+                status.0 = Some(error);
+            }
+        }
+    }
+}
+
+fn start_record_system(
+    asset_server: Res<AssetServer>,
+    variant: Res<Variant>,
+    variants: Res<Variants>,
+    script_sources: Res<Assets<ScriptSource>>,
+    mut pending_record: ResMut<PendingRecord>,
+    mut rule_script: ResMut<RuleScript>,
+    mut status: ResMut<Status>,
+    mut coin_toss: ResMut<CoinToss>,
+    mut board_view: ResMut<BoardView>,
+    mut history: ResMut<History>,
+    mut chat: ResMut<Chat>,
+    mut state: ResMut<State<AppState>>,
+) {
+    let (record, next_state) = match pending_record.0 {
+        Some((ref record, ref next_state)) if record.variant == variant.name => {
+            (record, next_state.clone())
+        }
+        _ => return,
+    };
+
+    let source = match variants.script_state(&asset_server, &script_sources) {
+        ScriptState::Loading => return,
+        ScriptState::Failed => {
+            status.0 = Some(format!("Couldn't load the script of {}", variant.name));
+            pending_record.0 = None;
+            return;
+        }
+        ScriptState::Ready(source) => source,
+    };
+
+    rule_script.load(source);
+
+    match record.replay(&variant, &mut rule_script) {
+        Ok((moves, positions)) => {
+            *coin_toss = CoinToss::default();
+            coin_toss.seed = record.seed;
+            *board_view = BoardView::default();
+
+            let ply = record.ply.unwrap_or(moves.len());
+            *history = History::resume(moves, positions, ply);
+            chat.lines = record.chat.clone();
+
+            state.set(next_state).expect("Couldn't open the record");
+        }
+        Err(error) => status.0 = Some(error.to_string()),
+    }
+
+    pending_record.0 = None;
+}
+
+fn save_record_system(
+    keyboard_input: Res<Input<KeyCode>>,
+    variant: Res<Variant>,
+    history: Res<History>,
+    coin_toss: Res<CoinToss>,
+    chat: Res<Chat>,
+    mut status: ResMut<Status>,
+) {
+    let control =
+        keyboard_input.pressed(KeyCode::LControl) || keyboard_input.pressed(KeyCode::RControl);
+    if !control || !keyboard_input.just_pressed(KeyCode::S) || history.positions.is_empty() {
+        return;
+    }
+
+    // undone moves aren't part of the game anymore
+    let mut record = GameRecord::new(
+        &variant,
+        if variant.random_turns {
+            coin_toss.seed
+        } else {
+            0
+        },
+        &history.moves[..history.cursor],
+        &history.positions[..=history.cursor],
+    );
+    record.chat = chat.lines.clone();
+
+    let folder = PathBuf::from(RECORDS_FOLDER);
+    let path = (1..)
+        .map(|number| folder.join(format!("{}-{}.ttt", today(), number)))
+        .find(|path| !path.exists())
+        .unwrap();
+
+    status.0 = Some(
+        match fs::create_dir_all(&folder).and_then(|_| fs::write(&path, record.to_string())) {
+            Ok(_) => format!("Saved {}", path.display()),
+            Err(error) => format!("Couldn't save {}: {}", path.display(), error),
+        },
+    );
+}
@@ -0,0 +1,195 @@
+use std::{collections::HashMap, path::Path};
+
+use bevy::prelude::*;
+use image::{Rgba, RgbaImage};
+
+use crate::{
+    export::{assets_folder, load_variant, read_record},
+    notation::Setup,
+    rules::{Cell, Symbol, Topology, Variant},
+    theme::{
+        background_color, board_bounds, button_color, cell_position, drawn_cells, strike_through,
+        symbol_color,
+    },
+};
+
+const LINE_WIDTH: f32 = 6.0;
+
+/// The symbol textures of the window, read from `assets/textures/symbols`.
+pub struct Sprites {
+    x: RgbaImage,
+    o: RgbaImage,
+    triangle: RgbaImage,
+    square: RgbaImage,
+    empty: RgbaImage,
+    hex_empty: RgbaImage,
+}
+
+impl Sprites {
+    pub fn load() -> Result<Self, String> {
+        let folder = assets_folder().join("textures/symbols");
+        let load = |name: &str| {
+            image::open(folder.join(name))
+                .map(|image| image.into_rgba8())
+                .map_err(|error| format!("Couldn't load the texture {}: {}", name, error))
+        };
+
+        Ok(Self {
+            x: load("x.png")?,
+            o: load("o.png")?,
+            triangle: load("triangle.png")?,
+            square: load("square.png")?,
+            empty: load("empty.png")?,
+            hex_empty: load("hex_empty.png")?,
+        })
+    }
+
+    fn get(&self, symbol: Symbol, topology: &Topology) -> &RgbaImage {
+        match (symbol, topology) {
+            (Symbol::X, _) => &self.x,
+            (Symbol::O, _) => &self.o,
+            (Symbol::Triangle, _) => &self.triangle,
+            (Symbol::Square, _) => &self.square,
+            (Symbol::Empty, Topology::Hex { .. }) => &self.hex_empty,
+            (Symbol::Empty, _) => &self.empty,
+        }
+    }
+}
+
+/// Mixes `color` into the pixel, `alpha` being how much of it shows.
+fn blend(pixel: &mut Rgba<u8>, color: [f32; 3], alpha: f32) {
+    for (channel, value) in pixel.0.iter_mut().zip(color.iter()) {
+        *channel = (value * 255.0 * alpha + *channel as f32 * (1.0 - alpha)).round() as u8;
+    }
+}
+
+fn channels(color: Color) -> [f32; 3] {
+    [color.r(), color.g(), color.b()]
+}
+
+/// Thick line from `from` to `to` in pixels, with round ends and smoothed edges.
+fn stroke(image: &mut RgbaImage, from: Vec2, to: Vec2, color: Color) {
+    let color = channels(color);
+    let radius = LINE_WIDTH / 2.0;
+    let length = (to - from).length_squared();
+
+    let (min, max) = (
+        from.min(to) - Vec2::splat(radius + 1.0),
+        from.max(to) + Vec2::splat(radius + 1.0),
+    );
+    let (width, height) = (image.width() as f32, image.height() as f32);
+
+    for y in min.y.max(0.0) as u32..max.y.min(height) as u32 {
+        for x in min.x.max(0.0) as u32..max.x.min(width) as u32 {
+            let center = Vec2::new(x as f32 + 0.5, y as f32 + 0.5);
+            let along = if length == 0.0 {
+                0.0
+            } else {
+                ((center - from).dot(to - from) / length).clamp(0.0, 1.0)
+            };
+            let distance = (center - (from + (to - from) * along)).length();
+
+            // pixels on the edge are covered in part
+            let coverage = (radius + 0.5 - distance).clamp(0.0, 1.0);
+            if coverage > 0.0 {
+                blend(image.get_pixel_mut(x, y), color, coverage);
+            }
+        }
+    }
+}
+
+/// Draws a board the way the window does, the symbol textures tinted with their player's color
+/// and laid out with `SYMBOL_SIZE` and `SPACE_SIZE`, the completed lines struck through if `winning_lines`.
+pub fn render_board(
+    sprites: &Sprites,
+    variant: &Variant,
+    symbols: &HashMap<Cell, Symbol>,
+    winning_lines: bool,
+) -> RgbaImage {
+    let topology = variant.topology;
+    let cells = drawn_cells(variant, symbols.keys().copied());
+    let (min, max) = board_bounds(&topology, &cells);
+    let size = max - min;
+
+    // y goes down in images
+    let pixel = |position: Vec2| Vec2::new(position.x - min.x, max.y - position.y);
+
+    let background = channels(background_color()).map(|value| (value * 255.0).round() as u8);
+    let mut image = RgbaImage::from_pixel(
+        size.x.ceil() as u32,
+        size.y.ceil() as u32,
+        Rgba([background[0], background[1], background[2], 255]),
+    );
+
+    for cell in cells {
+        let symbol = symbols.get(&cell).copied().unwrap_or_default();
+        let sprite = sprites.get(symbol, &topology);
+
+        // player symbols are white and get tinted with their player's color, empty cells keep theirs
+        let tint = match symbol {
+            Symbol::Empty => [1.0; 3],
+            _ => channels(symbol_color(symbol)),
+        };
+
+        let corner = pixel(cell_position(&topology, cell))
+            - Vec2::new(sprite.width() as f32, sprite.height() as f32) / 2.0;
+        let (left, top) = (corner.x.round() as i64, corner.y.round() as i64);
+
+        for (x, y, texel) in sprite.enumerate_pixels() {
+            let (x, y) = (left + x as i64, top + y as i64);
+            if x < 0 || y < 0 || x >= image.width() as i64 || y >= image.height() as i64 {
+                continue;
+            }
+
+            let color = [
+                texel.0[0] as f32 / 255.0 * tint[0],
+                texel.0[1] as f32 / 255.0 * tint[1],
+                texel.0[2] as f32 / 255.0 * tint[2],
+            ];
+            blend(
+                image.get_pixel_mut(x as u32, y as u32),
+                color,
+                texel.0[3] as f32 / 255.0,
+            );
+        }
+    }
+
+    if winning_lines {
+        for line in variant.winning_lines(symbols) {
+            let (from, to) = strike_through(&topology, line);
+
+            stroke(
+                &mut image,
+                pixel(from),
+                pixel(to),
+                button_color(Interaction::None),
+            );
+        }
+    }
+
+    image
+}
+
+/// Draws the position of a record, or of a position string, to a PNG file.
+/// Records are drawn where their Ply tag points to, at their last move otherwise.
+pub fn export_png(source: &str, png_path: &Path, winning_lines: bool) -> Result<(), String> {
+    let (variant, symbols) = if Path::new(source).is_file() {
+        let (record, variant, mut positions) = read_record(Path::new(source))?;
+        let ply = record.ply.unwrap_or(record.moves.len());
+
+        (variant, positions.swap_remove(ply).symbols)
+    } else {
+        let setup = Setup::parse(source).map_err(|error| format!("Invalid position: {}", error))?;
+        let (variant, mut rule_script) = load_variant(&setup.variant)?;
+        let position = setup
+            .position(&variant, &mut rule_script)
+            .map_err(|error| format!("Invalid position: {}", error))?;
+
+        (variant, position.symbols)
+    };
+
+    let sprites = Sprites::load()?;
+    render_board(&sprites, &variant, &symbols, winning_lines)
+        .save(png_path)
+        .map_err(|error| format!("Couldn't write {}: {}", png_path.display(), error))
+}
@@ -0,0 +1,376 @@
+use bevy::prelude::*;
+
+use crate::{
+    button::{ButtonAssets, ButtonElement, SpawnButton},
+    history::History,
+    rules::{Symbol, Variant},
+    symbol::{chunk_of, update_symbols, BoardView, Symbols, SymbolsMaterials},
+    ui::{status_text, NodeElement, TextElement},
+    AppState,
+};
+
+/// Replay speeds to pick from, in moves per second.
+const SPEEDS: [f32; 4] = [0.5, 1.0, 2.0, 4.0];
+const KNOB_SIZE: f32 = 16.0;
+
+pub struct ReplayPlugin;
+
+impl Plugin for ReplayPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<Replay>()
+            .init_resource::<ReplayTimer>()
+            .add_system_set(SystemSet::on_enter(AppState::Replay).with_system(setup_replay))
+            .add_system_set(
+                SystemSet::on_update(AppState::Replay)
+                    .with_system(replay_control_system)
+                    .with_system(replay_playback_system)
+                    .with_system(replay_slider_system)
+                    .with_system(replay_label_system)
+                    .with_system(replay_board_system),
+            );
+    }
+}
+
+/// Position of the replay in `History::positions`, and how it plays.
+pub struct Replay {
+    pub ply: usize,
+    pub playing: bool,
+    /// Index in `SPEEDS`.
+    pub speed: usize,
+}
+
+impl Default for Replay {
+    fn default() -> Self {
+        Self {
+            ply: 0,
+            playing: true,
+            speed: 1,
+        }
+    }
+}
+
+/// Kept out of `Replay`, so the board is only rebuilt when the replay moves.
+struct ReplayTimer(Timer);
+
+impl Default for ReplayTimer {
+    fn default() -> Self {
+        Self(Timer::from_seconds(1.0, true))
+    }
+}
+
+/// Clicking or dragging along it scrubs through the game.
+pub struct ReplaySlider;
+
+pub struct ReplayKnob;
+
+pub struct ReplayText;
+
+fn setup_replay(
+    mut commands: Commands,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    button_assets: Res<ButtonAssets>,
+    history: Res<History>,
+    mut replay: ResMut<Replay>,
+    mut board_view: ResMut<BoardView>,
+) {
+    *replay = Replay::default();
+
+    // unbounded boards are drawn around the symbols of the whole game
+    *board_view = BoardView::default();
+    board_view.chunks = history
+        .positions
+        .iter()
+        .flat_map(|position| position.symbols.keys().copied())
+        .chain(std::iter::once((0, 0)))
+        .map(chunk_of)
+        .collect();
+
+    let none = materials.add(Color::NONE.into());
+
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                justify_content: JustifyContent::SpaceBetween,
+                align_items: AlignItems::Center,
+                flex_direction: FlexDirection::ColumnReverse,
+                ..Default::default()
+            },
+            material: none.clone(),
+            ..Default::default()
+        })
+        .insert(NodeElement::Root)
+        .with_children(|root| {
+            root.spawn_bundle(NodeBundle {
+                style: Style {
+                    size: Size::new(Val::Percent(100.0), Default::default()),
+                    justify_content: JustifyContent::SpaceBetween,
+                    align_items: AlignItems::Center,
+                    ..Default::default()
+                },
+                material: none.clone(),
+                ..Default::default()
+            })
+            .with_children(|parent| {
+                parent
+                    .spawn_bundle(TextBundle {
+                        text: Text::with_section(
+                            "",
+                            TextStyle {
+                                font: button_assets.font.clone(),
+                                font_size: 40.0,
+                                color: Color::WHITE,
+                            },
+                            Default::default(),
+                        ),
+                        style: Style {
+                            margin: Rect::all(Val::Px(16.0)),
+                            ..Default::default()
+                        },
+                        ..Default::default()
+                    })
+                    .insert(ReplayText);
+                parent.spawn_button(&button_assets, ButtonElement::GameList);
+            });
+
+            root.spawn_bundle(status_text(&button_assets))
+                .insert(TextElement::Status(None));
+
+            root.spawn_bundle(NodeBundle {
+                style: Style {
+                    size: Size::new(Val::Percent(90.0), Val::Px(KNOB_SIZE / 2.0)),
+                    margin: Rect {
+                        top: Val::Px(KNOB_SIZE),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                },
+                material: materials.add(Color::rgb_u8(171, 178, 191).into()),
+                ..Default::default()
+            })
+            .insert(Interaction::default())
+            .insert(ReplaySlider)
+            .with_children(|slider| {
+                slider
+                    .spawn_bundle(NodeBundle {
+                        style: Style {
+                            position_type: PositionType::Absolute,
+                            position: Rect {
+                                left: Val::Percent(0.0),
+                                top: Val::Px(-KNOB_SIZE / 4.0),
+                                ..Default::default()
+                            },
+                            size: Size::new(Val::Px(KNOB_SIZE), Val::Px(KNOB_SIZE)),
+                            ..Default::default()
+                        },
+                        material: materials.add(Color::rgb_u8(81, 183, 194).into()),
+                        ..Default::default()
+                    })
+                    .insert(ReplayKnob);
+            });
+
+            root.spawn_bundle(NodeBundle {
+                material: none,
+                ..Default::default()
+            })
+            .with_children(|parent| {
+                parent
+                    .spawn_button(&button_assets, ButtonElement::StepBack)
+                    .spawn_button(&button_assets, ButtonElement::PlayPause)
+                    .spawn_button(&button_assets, ButtonElement::StepForward)
+                    .spawn_button(&button_assets, ButtonElement::ReplaySpeed);
+            });
+        });
+}
+
+fn replay_control_system(
+    keyboard_input: Res<Input<KeyCode>>,
+    interaction_query: Query<(&Interaction, &ButtonElement), (Changed<Interaction>, With<Button>)>,
+    history: Res<History>,
+    mut replay: ResMut<Replay>,
+    mut timer: ResMut<ReplayTimer>,
+    mut state: ResMut<State<AppState>>,
+) {
+    let mut step_back = keyboard_input.just_pressed(KeyCode::Left);
+    let mut step_forward = keyboard_input.just_pressed(KeyCode::Right);
+    let mut play_pause = keyboard_input.just_pressed(KeyCode::Space);
+    let mut speed = false;
+
+    for (interaction, button) in interaction_query.iter() {
+        if *interaction == Interaction::Clicked {
+            match button {
+                ButtonElement::StepBack => step_back = true,
+                ButtonElement::StepForward => step_forward = true,
+                ButtonElement::PlayPause => play_pause = true,
+                ButtonElement::ReplaySpeed => speed = true,
+                _ => {}
+            }
+        }
+    }
+
+    if keyboard_input.just_pressed(KeyCode::Escape) {
+        state
+            .set(AppState::GameList)
+            .expect("Couldn't enter the GameList state");
+        return;
+    }
+
+    let last = history.positions.len().saturating_sub(1);
+
+    // stepping takes over from the playback
+    if step_back && replay.ply > 0 {
+        replay.ply -= 1;
+        replay.playing = false;
+    } else if step_forward && replay.ply < last {
+        replay.ply += 1;
+        replay.playing = false;
+    }
+
+    if play_pause {
+        replay.playing = !replay.playing;
+        timer.0.reset();
+
+        // playing a finished replay starts it over
+        if replay.playing && replay.ply == last {
+            replay.ply = 0;
+        }
+    }
+
+    if speed {
+        replay.speed = (replay.speed + 1) % SPEEDS.len();
+    }
+}
+
+fn replay_playback_system(
+    time: Res<Time>,
+    history: Res<History>,
+    mut replay: ResMut<Replay>,
+    mut timer: ResMut<ReplayTimer>,
+) {
+    if !replay.playing {
+        return;
+    }
+
+    if !timer
+        .0
+        .tick(time.delta().mul_f32(SPEEDS[replay.speed]))
+        .just_finished()
+    {
+        return;
+    }
+
+    if replay.ply + 1 < history.positions.len() {
+        replay.ply += 1;
+    } else {
+        replay.playing = false;
+    }
+}
+
+fn replay_slider_system(
+    windows: Res<Windows>,
+    history: Res<History>,
+    mut replay: ResMut<Replay>,
+    slider_query: Query<(&Interaction, &Node, &GlobalTransform), With<ReplaySlider>>,
+) {
+    let (interaction, node, transform) = match slider_query.single() {
+        Ok(slider) => slider,
+        Err(_) => return,
+    };
+
+    // held down, so dragging keeps scrubbing
+    if *interaction != Interaction::Clicked {
+        return;
+    }
+
+    let cursor = match windows.get_primary().and_then(Window::cursor_position) {
+        Some(cursor) => cursor,
+        None => return,
+    };
+
+    let left = transform.translation.x - node.size.x / 2.0;
+    let fraction = ((cursor.x - left) / node.size.x).clamp(0.0, 1.0);
+    let last = history.positions.len().saturating_sub(1);
+    let ply = (fraction * last as f32).round() as usize;
+
+    if ply != replay.ply || replay.playing {
+        replay.ply = ply;
+        replay.playing = false;
+    }
+}
+
+fn replay_label_system(
+    variant: Res<Variant>,
+    history: Res<History>,
+    replay: Res<Replay>,
+    button_query: Query<(&ButtonElement, &Children)>,
+    mut text_query: Query<&mut Text>,
+    replay_text_query: Query<Entity, With<ReplayText>>,
+    mut knob_query: Query<&mut Style, With<ReplayKnob>>,
+) {
+    for (button, children) in button_query.iter() {
+        let label = match button {
+            ButtonElement::PlayPause if replay.playing => "Pause".to_string(),
+            ButtonElement::PlayPause => "Play".to_string(),
+            ButtonElement::ReplaySpeed => format!("Speed {}x", SPEEDS[replay.speed]),
+            _ => continue,
+        };
+
+        for child in children.iter() {
+            if let Ok(mut text) = text_query.get_mut(*child) {
+                text.sections[0].value = label.clone();
+            }
+        }
+    }
+
+    let last = history.positions.len().saturating_sub(1);
+    let label = match replay
+        .ply
+        .checked_sub(1)
+        .and_then(|index| history.moves.get(index))
+    {
+        Some(played) => format!(
+            "{}/{} {} {}",
+            replay.ply,
+            last,
+            played.symbol.short_name(),
+            variant.topology.notation(played.cell)
+        ),
+        None => format!("0/{}", last),
+    };
+
+    for entity in replay_text_query.iter() {
+        if let Ok(mut text) = text_query.get_mut(entity) {
+            text.sections[0].value = label.clone();
+        }
+    }
+
+    for mut style in knob_query.iter_mut() {
+        style.position.left = Val::Percent(replay.ply as f32 / last.max(1) as f32 * 100.0);
+    }
+}
+
+/// Puts the position of the replay on the board, the same way the game does.
+fn replay_board_system(
+    mut commands: Commands,
+    symbols_query: Query<Entity, With<Symbol>>,
+    symbols_materials: Res<SymbolsMaterials>,
+    variant: Res<Variant>,
+    history: Res<History>,
+    replay: Res<Replay>,
+    board_view: Res<BoardView>,
+) {
+    if !replay.is_changed() {
+        return;
+    }
+
+    if let Some(position) = history.positions.get(replay.ply) {
+        update_symbols(
+            &mut commands,
+            &symbols_query,
+            &symbols_materials,
+            &Symbols(position.symbols.clone()),
+            &variant,
+            &board_view,
+        );
+    }
+}
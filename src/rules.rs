@@ -0,0 +1,1023 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+};
+
+use bevy::reflect::{TypeUuid, Uuid};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+
+/// Axial `(q, r)` coordinates on hex boards, `(column, row)` on square ones.
+pub type Cell = (i32, i32);
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+pub enum Symbol {
+    X,
+    O,
+    Triangle,
+    Square,
+    Empty,
+}
+
+impl Symbol {
+    /// Every player symbol, games with fewer players use the first ones.
+    pub const PLAYERS: [Symbol; 4] = [Self::X, Self::O, Self::Triangle, Self::Square];
+
+    /// Name short enough for the menu and the move list.
+    pub fn short_name(&self) -> &'static str {
+        match self {
+            Self::X => "X",
+            Self::O => "O",
+            Self::Triangle => "Tri",
+            Self::Square => "Sq",
+            Self::Empty => "",
+        }
+    }
+
+    pub fn from_short_name(name: &str) -> Option<Symbol> {
+        Self::PLAYERS
+            .iter()
+            .copied()
+            .find(|symbol| symbol.short_name() == name)
+    }
+}
+
+impl Default for Symbol {
+    fn default() -> Self {
+        Self::Empty
+    }
+}
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Self::X => "X",
+                Self::O => "O",
+                Self::Triangle => "Triangle",
+                Self::Square => "Square",
+                Self::Empty => "Empty",
+            }
+        )
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Deserialize)]
+pub enum Topology {
+    Square {
+        width: i32,
+        height: i32,
+    },
+    Hex {
+        radius: i32,
+    },
+    /// Unbounded square grid.
+    Infinite,
+}
+
+impl Topology {
+    /// Every cell of the board, `None` for unbounded boards.
+    pub fn cells(&self) -> Option<Vec<Cell>> {
+        match *self {
+            Self::Square { width, height } => Some(
+                (0..height)
+                    .flat_map(|row| (0..width).map(move |column| (column, row)))
+                    .collect(),
+            ),
+            Self::Hex { radius } => Some(
+                (-radius..=radius)
+                    .flat_map(|r| (-radius..=radius).map(move |q| (q, r)))
+                    .filter(|&cell| self.contains(cell))
+                    .collect(),
+            ),
+            Self::Infinite => None,
+        }
+    }
+
+    pub fn contains(&self, (q, r): Cell) -> bool {
+        match *self {
+            Self::Square { width, height } => (0..width).contains(&q) && (0..height).contains(&r),
+            Self::Hex { radius } => q.abs().max(r.abs()).max((q + r).abs()) <= radius,
+            Self::Infinite => true,
+        }
+    }
+
+    /// One direction per line orientation, the opposite one is implied.
+    pub fn directions(&self) -> &'static [Cell] {
+        match self {
+            Self::Square { .. } | Self::Infinite => &[(1, 0), (0, 1), (1, 1), (1, -1)],
+            Self::Hex { .. } => &[(1, 0), (0, 1), (1, -1)],
+        }
+    }
+
+    /// Chess like name of a cell, files are letters from the left and ranks numbers from the bottom.
+    /// Unbounded boards have no edge to count from, their cells are named by their coordinates.
+    pub fn notation(&self, (q, r): Cell) -> String {
+        let (file, rank) = match *self {
+            Self::Square { height, .. } => (q, height - r),
+            Self::Hex { radius } => (q + radius, radius - r + 1),
+            Self::Infinite => return format!("{},{}", q, r),
+        };
+
+        // a to z, then aa, ab and so on
+        let mut letters = Vec::new();
+        let mut file = file + 1;
+        while file > 0 {
+            file -= 1;
+            letters.push((b'a' + (file % 26) as u8) as char);
+            file /= 26;
+        }
+
+        format!("{}{}", letters.iter().rev().collect::<String>(), rank)
+    }
+
+    /// Cell named `name` by `notation`, `None` if the name is malformed or off the board.
+    pub fn parse_notation(&self, name: &str) -> Option<Cell> {
+        let cell = match *self {
+            Self::Infinite => {
+                let (q, r) = name.split_once(',')?;
+                (q.trim().parse().ok()?, r.trim().parse().ok()?)
+            }
+            Self::Square { height, .. } | Self::Hex { radius: height } => {
+                let digits = name.find(|c: char| c.is_ascii_digit())?;
+                let (letters, rank) = name.split_at(digits);
+                if letters.is_empty() || !letters.bytes().all(|c| c.is_ascii_lowercase()) {
+                    return None;
+                }
+
+                let file = letters.bytes().try_fold(0i32, |file, c| {
+                    file.checked_mul(26)?.checked_add((c - b'a') as i32 + 1)
+                })? - 1;
+                let rank = rank.parse::<i32>().ok()?;
+
+                match *self {
+                    Self::Hex { radius } => (file - radius, radius - rank + 1),
+                    _ => (file, height - rank),
+                }
+            }
+        };
+
+        Some(cell).filter(|&cell| self.contains(cell))
+    }
+}
+
+fn default_players() -> usize {
+    2
+}
+
+/// Rules of a game, described by the `assets/variants/*.ron` files.
+#[derive(Clone, PartialEq, Eq, Debug, Deserialize)]
+pub struct Variant {
+    pub name: String,
+    pub topology: Topology,
+    pub win_length: usize,
+    /// Completing a line loses instead of winning.
+    #[serde(default)]
+    pub misere: bool,
+    /// Symbols fall down their column until they land on something, square boards only.
+    #[serde(default)]
+    pub gravity: bool,
+    /// Cells of the board nobody can play on.
+    #[serde(default)]
+    pub blocked: Vec<Cell>,
+    #[serde(default = "default_players")]
+    pub players: usize,
+    /// Lines carry on from one edge of the board to the opposite one, square boards only.
+    #[serde(default)]
+    pub wrap: bool,
+    /// A coin flip decides who plays each turn instead of alternating, two players only.
+    #[serde(default)]
+    pub random_turns: bool,
+    /// Players only see their own symbols, and the opponent ones they bumped into.
+    #[serde(default)]
+    pub blind: bool,
+    /// Asset path of a Rhai script with house rules.
+    #[serde(default)]
+    pub script: Option<String>,
+}
+
+impl Default for Variant {
+    fn default() -> Self {
+        Self {
+            name: "Classic".into(),
+            topology: Topology::Square {
+                width: 3,
+                height: 3,
+            },
+            win_length: 3,
+            misere: false,
+            gravity: false,
+            blocked: Vec::new(),
+            players: 2,
+            wrap: false,
+            random_turns: false,
+            blind: false,
+            script: None,
+        }
+    }
+}
+
+// loaded from the variant files by the asset server
+impl TypeUuid for Variant {
+    const TYPE_UUID: Uuid = Uuid::from_u128(0x6f1c_2d9a_83b4_4e57_a0c2_5b7e_19d4_f3a8);
+}
+
+impl fmt::Display for Variant {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name)
+    }
+}
+
+impl Variant {
+    pub fn validate(&self) -> Result<(), String> {
+        let square = matches!(self.topology, Topology::Square { .. });
+
+        if !(2..=Symbol::PLAYERS.len()).contains(&self.players) {
+            Err(format!(
+                "{} players isn't supported, only 2 to {}",
+                self.players,
+                Symbol::PLAYERS.len()
+            ))
+        } else if self.win_length == 0 {
+            Err("the win length can't be 0".into())
+        } else if (self.gravity || self.wrap) && !square {
+            Err("gravity and wrap around only work on square boards".into())
+        } else if (self.random_turns || self.blind) && self.players != 2 {
+            Err("random turns and blind games are for two players".into())
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Every playable cell, `None` for unbounded boards.
+    pub fn cells(&self) -> Option<Vec<Cell>> {
+        self.topology.cells().map(|cells| {
+            cells
+                .into_iter()
+                .filter(|cell| !self.blocked.contains(cell))
+                .collect()
+        })
+    }
+
+    pub fn is_full(&self, board: &HashMap<Cell, Symbol>) -> bool {
+        self.cells()
+            .map_or(false, |cells| cells.len() == board.len())
+    }
+
+    pub fn is_playable(&self, cell: Cell) -> bool {
+        self.topology.contains(cell) && !self.blocked.contains(&cell)
+    }
+
+    /// Cell a symbol played on `cell` ends up on, `None` if it can't be played there.
+    pub fn landing_cell(&self, board: &HashMap<Cell, Symbol>, cell: Cell) -> Option<Cell> {
+        let is_free = |cell: &Cell| self.is_playable(*cell) && !board.contains_key(cell);
+
+        if !self.gravity {
+            return Some(cell).filter(is_free);
+        }
+
+        // dropped from the top of the column, rows go down
+        let mut landing = Some((cell.0, 0)).filter(is_free)?;
+        while is_free(&(landing.0, landing.1 + 1)) {
+            landing.1 += 1;
+        }
+
+        Some(landing)
+    }
+
+    fn step(&self, (q, r): Cell, (dq, dr): Cell, steps: i32) -> Cell {
+        let next = (q + steps * dq, r + steps * dr);
+
+        match self.topology {
+            Topology::Square { width, height } if self.wrap => {
+                (next.0.rem_euclid(width), next.1.rem_euclid(height))
+            }
+            _ => next,
+        }
+    }
+
+    /// Number of consecutive `board[cell]` symbols on the line going through `cell` along `direction`.
+    pub fn line_length(&self, board: &HashMap<Cell, Symbol>, cell: Cell, direction: Cell) -> usize {
+        let symbol = match board.get(&cell) {
+            Some(symbol) => *symbol,
+            None => return 0,
+        };
+
+        // stops on the starting cell, lines wrapping around the board are cycles
+        let count = |sign: i32| {
+            (1..)
+                .map(|steps| self.step(cell, direction, sign * steps))
+                .take_while(|next| *next != cell && board.get(next) == Some(&symbol))
+                .count()
+        };
+
+        let forward = count(1);
+        if self.step(cell, direction, forward as i32 + 1) == cell {
+            return forward + 1;
+        }
+
+        1 + forward + count(-1)
+    }
+
+    /// Symbols owning at least one line of `win_length` on the board.
+    pub fn lines(&self, board: &HashMap<Cell, Symbol>) -> Vec<Symbol> {
+        let mut winners = Vec::new();
+
+        for (&cell, &symbol) in board.iter() {
+            if winners.contains(&symbol) {
+                continue;
+            }
+
+            if self
+                .topology
+                .directions()
+                .iter()
+                .any(|&direction| self.line_length(board, cell, direction) >= self.win_length)
+            {
+                winners.push(symbol);
+            }
+        }
+
+        winners
+    }
+
+    /// First and last cell of every line of `win_length` on the board, sorted.
+    /// Lines going around a wrapping board can't be drawn straight and are left out.
+    pub fn winning_lines(&self, board: &HashMap<Cell, Symbol>) -> Vec<(Cell, Cell)> {
+        let mut lines = Vec::new();
+
+        for (&(q, r), &symbol) in board.iter() {
+            for &(dq, dr) in self.topology.directions() {
+                // counted once, from the first cell of the line
+                if board.get(&(q - dq, r - dr)) == Some(&symbol) {
+                    continue;
+                }
+
+                let length = (1..)
+                    .take_while(|steps| {
+                        board.get(&(q + steps * dq, r + steps * dr)) == Some(&symbol)
+                    })
+                    .count() as i32;
+                if length + 1 >= self.win_length as i32 {
+                    lines.push(((q, r), (q + length * dq, r + length * dr)));
+                }
+            }
+        }
+
+        lines.sort_unstable();
+        lines
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Move {
+    pub cell: Cell,
+    pub symbol: Symbol,
+}
+
+/// Everything a move can change, as it stood once the move was resolved.
+#[derive(Clone, Debug, Default)]
+pub struct Position {
+    pub symbols: HashMap<Cell, Symbol>,
+    /// `Empty` once the game is over, and in random turns games until the coin is tossed.
+    pub current_symbol: Symbol,
+    pub players: Vec<Symbol>,
+    pub standings: Standings,
+    pub revealed: HashMap<Symbol, HashSet<Cell>>,
+    pub flips: usize,
+}
+
+/// Players ranked by the order they completed a line in, players finishing together share a rank.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Standings(pub Vec<Vec<Symbol>>);
+
+impl fmt::Display for Standings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let players = self.0.iter().map(Vec::len).sum::<usize>();
+
+        match self.0.first().map(Vec::as_slice) {
+            None => Ok(()),
+            Some([winner]) if players == 2 => write!(f, "The winner is {}!", winner),
+            // a draw only when nobody ranks below the players sharing the first place
+            Some([_, _, ..]) if self.0.len() == 1 => write!(f, "It's a draw!"),
+            Some(_) => {
+                for (rank, symbols) in self.0.iter().enumerate() {
+                    let symbols = symbols.iter().map(Symbol::to_string).collect::<Vec<_>>();
+                    write!(
+                        f,
+                        "{}{}. {}",
+                        if rank == 0 { "" } else { " " },
+                        rank + 1,
+                        symbols.join("/")
+                    )?;
+                }
+
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Player after `symbol` in turn order, the first one if `symbol` isn't playing.
+pub fn next_player(players: &[Symbol], symbol: Symbol) -> Symbol {
+    let index = players
+        .iter()
+        .position(|&player| player == symbol)
+        .map_or(0, |index| index + 1);
+
+    players[index % players.len()]
+}
+
+/// Ranks the players who completed a line, returns whether the game is over.
+///
+/// The others play on for the remaining ranks, in misère games the ranks are handed out from the last one.
+pub fn resolve(
+    variant: &Variant,
+    lines: &[Symbol],
+    full: bool,
+    players: &mut Vec<Symbol>,
+    standings: &mut Standings,
+) -> bool {
+    let finished = players
+        .iter()
+        .copied()
+        .filter(|symbol| lines.contains(symbol))
+        .collect::<Vec<_>>();
+
+    if !finished.is_empty() {
+        players.retain(|symbol| !finished.contains(symbol));
+        standings.0.push(finished);
+    }
+
+    if players.len() > 1 && !full {
+        return false;
+    }
+
+    if !players.is_empty() {
+        standings.0.push(std::mem::take(players));
+    }
+
+    if variant.misere {
+        standings.0.reverse();
+    }
+
+    true
+}
+
+/// Next turn order in lexicographic order of the `Symbol::PLAYERS` indices, wrapping around.
+pub fn next_turn_order(order: &[Symbol]) -> Vec<Symbol> {
+    let mut indices = order
+        .iter()
+        .map(|symbol| Symbol::PLAYERS.iter().position(|player| player == symbol))
+        .collect::<Option<Vec<_>>>()
+        .unwrap_or_default();
+
+    match (1..indices.len())
+        .rev()
+        .find(|&i| indices[i - 1] < indices[i])
+    {
+        Some(pivot) => {
+            let successor = (pivot..indices.len())
+                .rev()
+                .find(|&i| indices[i] > indices[pivot - 1])
+                .unwrap();
+            indices.swap(pivot - 1, successor);
+            indices[pivot..].reverse();
+        }
+        None => indices.sort_unstable(),
+    }
+
+    indices.into_iter().map(|i| Symbol::PLAYERS[i]).collect()
+}
+
+/// Side chosen by the coin for the `ply`-th turn of a game seeded with `seed`.
+pub fn coin_flip(seed: u64, ply: usize) -> Symbol {
+    if StdRng::seed_from_u64(seed.wrapping_add(ply as u64)).gen() {
+        Symbol::X
+    } else {
+        Symbol::O
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::Path};
+
+    use super::*;
+
+    /// A board with `symbol` on each of the `cells`.
+    fn board(symbol: Symbol, cells: &[Cell]) -> HashMap<Cell, Symbol> {
+        cells.iter().map(|&cell| (cell, symbol)).collect()
+    }
+
+    fn cell_count(topology: Topology) -> Option<usize> {
+        topology.cells().map(|cells| cells.len())
+    }
+
+    /// Two players on `topology`, winning with lines of `win_length`.
+    fn variant(topology: Topology, win_length: usize) -> Variant {
+        Variant {
+            topology,
+            win_length,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn lays_out_square_and_hex_boards() {
+        assert_eq!(
+            cell_count(Topology::Square {
+                width: 3,
+                height: 3
+            }),
+            Some(9)
+        );
+        assert_eq!(
+            cell_count(Topology::Square {
+                width: 7,
+                height: 6
+            }),
+            Some(42)
+        );
+        assert_eq!(cell_count(Topology::Hex { radius: 2 }), Some(19));
+        assert_eq!(cell_count(Topology::Hex { radius: 3 }), Some(37));
+    }
+
+    #[test]
+    fn leaves_unbounded_boards_without_edges() {
+        assert_eq!(cell_count(Topology::Infinite), None);
+        assert!(Topology::Infinite.contains((-1000, 1000)));
+
+        let far = board(
+            Symbol::X,
+            &[(-500, 90), (-499, 91), (-498, 92), (-497, 93), (-496, 94)],
+        );
+        assert_eq!(variant(Topology::Infinite, 5).lines(&far), vec![Symbol::X]);
+        assert_eq!(variant(Topology::Infinite, 6).lines(&far), vec![]);
+    }
+
+    #[test]
+    fn keeps_hex_cells_within_the_radius() {
+        let hex = Topology::Hex { radius: 2 };
+
+        assert!(hex.contains((0, 0)));
+        assert!(hex.contains((2, -2)));
+        assert!(hex.contains((-2, 0)));
+        assert!(!hex.contains((2, 1)));
+        assert!(!hex.contains((-2, -1)));
+        assert!(!hex.contains((0, 3)));
+    }
+
+    #[test]
+    fn measures_lines_both_ways_from_a_cell() {
+        let mut board = board(Symbol::X, &[(0, 0), (1, 0), (2, 0)]);
+        board.insert((3, 0), Symbol::O);
+
+        let variant = variant(Topology::Infinite, 3);
+
+        assert_eq!(variant.line_length(&board, (1, 0), (1, 0)), 3);
+        assert_eq!(variant.line_length(&board, (0, 0), (1, 0)), 3);
+        assert_eq!(variant.line_length(&board, (1, 0), (0, 1)), 1);
+        assert_eq!(variant.line_length(&board, (3, 0), (1, 0)), 1);
+        assert_eq!(variant.line_length(&board, (1, 1), (1, 0)), 0);
+    }
+
+    #[test]
+    fn finds_lines_along_the_three_hex_axes() {
+        let hex = Topology::Hex { radius: 2 };
+
+        for &(dq, dr) in hex.directions() {
+            let board = board(Symbol::O, &[(-dq, -dr), (0, 0), (dq, dr)]);
+
+            assert_eq!(variant(hex, 3).lines(&board), vec![Symbol::O]);
+            assert_eq!(variant(hex, 4).lines(&board), vec![]);
+        }
+    }
+
+    #[test]
+    fn takes_diagonals_on_square_boards_only() {
+        let board = board(Symbol::X, &[(0, 0), (1, 1), (2, 2)]);
+
+        assert_eq!(Variant::default().lines(&board), vec![Symbol::X]);
+        assert_eq!(
+            variant(Topology::Hex { radius: 2 }, 3).lines(&board),
+            vec![]
+        );
+    }
+
+    #[test]
+    fn tosses_the_same_coin_for_the_same_seed_and_turn() {
+        let flips = |seed| (0..32).map(|ply| coin_flip(seed, ply)).collect::<Vec<_>>();
+
+        assert_eq!(flips(7), flips(7));
+        assert!(flips(7).contains(&Symbol::X));
+        assert!(flips(7).contains(&Symbol::O));
+        assert!(flips(7)
+            .iter()
+            .all(|&symbol| symbol == Symbol::X || symbol == Symbol::O));
+    }
+
+    #[test]
+    fn announces_winners_draws_and_ranks() {
+        use Symbol::*;
+
+        assert_eq!(Standings::default().to_string(), "");
+        assert_eq!(
+            Standings(vec![vec![O], vec![X]]).to_string(),
+            "The winner is O!"
+        );
+        assert_eq!(Standings(vec![vec![X, O]]).to_string(), "It's a draw!");
+        assert_eq!(
+            Standings(vec![vec![X, O], vec![Triangle]]).to_string(),
+            "1. X/O 2. Triangle"
+        );
+        assert_eq!(
+            Standings(vec![vec![Triangle], vec![X], vec![O]]).to_string(),
+            "1. Triangle 2. X 3. O"
+        );
+        assert_eq!(
+            Standings(vec![vec![Square], vec![X, Triangle], vec![O]]).to_string(),
+            "1. Square 2. X/Triangle 3. O"
+        );
+    }
+
+    #[test]
+    fn goes_through_every_turn_order_in_turn() {
+        use Symbol::*;
+
+        assert_eq!(next_turn_order(&[X, O]), vec![O, X]);
+        assert_eq!(next_turn_order(&[O, X]), vec![X, O]);
+        assert_eq!(next_turn_order(&[X, O, Triangle]), vec![X, Triangle, O]);
+        assert_eq!(next_turn_order(&[X, Triangle, O]), vec![O, X, Triangle]);
+        assert_eq!(next_turn_order(&[Triangle, O, X]), vec![X, O, Triangle]);
+
+        // 4 players go through all 24 orders before coming back to the first one
+        let mut order = Symbol::PLAYERS.to_vec();
+        let mut orders = vec![order.clone()];
+        loop {
+            order = next_turn_order(&order);
+            if order == Symbol::PLAYERS {
+                break;
+            }
+            orders.push(order.clone());
+        }
+        orders.sort_unstable_by_key(|order| format!("{:?}", order));
+        orders.dedup();
+        assert_eq!(orders.len(), 24);
+    }
+
+    #[test]
+    fn reads_every_variant_file() {
+        let folder = Path::new(env!("CARGO_MANIFEST_DIR")).join("assets/variants");
+
+        for entry in fs::read_dir(folder).expect("Couldn't list the variants") {
+            let path = entry.expect("Couldn't list the variants").path();
+            let text = fs::read_to_string(&path).expect("Couldn't read the variant");
+            let variant = ron::de::from_str::<Variant>(&text)
+                .unwrap_or_else(|error| panic!("Couldn't parse {}: {}", path.display(), error));
+
+            assert_eq!(variant.validate(), Ok(()), "{}", path.display());
+        }
+    }
+
+    #[test]
+    fn rejects_rules_that_dont_go_together() {
+        let hex = Topology::Hex { radius: 2 };
+        let error = |variant: Variant| variant.validate().expect_err("The variant was accepted");
+
+        assert_eq!(Variant::default().validate(), Ok(()));
+        assert_eq!(
+            error(Variant {
+                players: 5,
+                ..Default::default()
+            }),
+            "5 players isn't supported, only 2 to 4"
+        );
+        assert_eq!(error(variant(hex, 0)), "the win length can't be 0");
+        assert_eq!(
+            error(Variant {
+                gravity: true,
+                ..variant(hex, 3)
+            }),
+            "gravity and wrap around only work on square boards"
+        );
+        assert_eq!(
+            error(Variant {
+                wrap: true,
+                ..variant(Topology::Infinite, 5)
+            }),
+            "gravity and wrap around only work on square boards"
+        );
+        assert_eq!(
+            error(Variant {
+                players: 3,
+                blind: true,
+                ..Default::default()
+            }),
+            "random turns and blind games are for two players"
+        );
+        assert_eq!(
+            error(Variant {
+                players: 4,
+                random_turns: true,
+                ..Default::default()
+            }),
+            "random turns and blind games are for two players"
+        );
+    }
+
+    #[test]
+    fn plays_free_cells_where_they_are_clicked() {
+        let mut board = HashMap::new();
+        board.insert((1, 1), Symbol::X);
+
+        assert_eq!(
+            Variant::default().landing_cell(&board, (0, 2)),
+            Some((0, 2))
+        );
+        assert_eq!(Variant::default().landing_cell(&board, (1, 1)), None);
+        assert_eq!(Variant::default().landing_cell(&board, (3, 0)), None);
+    }
+
+    #[test]
+    fn drops_symbols_to_the_bottom_of_their_column() {
+        let connect_four = Variant {
+            gravity: true,
+            ..variant(
+                Topology::Square {
+                    width: 7,
+                    height: 6,
+                },
+                4,
+            )
+        };
+        let mut board = HashMap::new();
+
+        assert_eq!(connect_four.landing_cell(&board, (3, 0)), Some((3, 5)));
+        assert_eq!(connect_four.landing_cell(&board, (3, 4)), Some((3, 5)));
+
+        board.insert((3, 5), Symbol::X);
+        assert_eq!(connect_four.landing_cell(&board, (3, 2)), Some((3, 4)));
+
+        // a full column takes no more symbols
+        board.extend((0..5).map(|row| ((3, row), Symbol::O)));
+        assert_eq!(connect_four.landing_cell(&board, (3, 0)), None);
+        assert_eq!(connect_four.landing_cell(&board, (7, 0)), None);
+    }
+
+    #[test]
+    fn leaves_blocked_cells_out() {
+        let holes = Variant {
+            blocked: vec![(1, 1)],
+            ..Default::default()
+        };
+
+        assert_eq!(holes.cells().map(|cells| cells.len()), Some(8));
+        assert!(holes.is_playable((0, 0)));
+        assert!(!holes.is_playable((1, 1)));
+        assert_eq!(holes.landing_cell(&HashMap::new(), (1, 1)), None);
+
+        // symbols land on blocked cells like on played ones
+        let gravity = Variant {
+            gravity: true,
+            ..holes
+        };
+        assert_eq!(gravity.landing_cell(&HashMap::new(), (1, 2)), Some((1, 0)));
+    }
+
+    #[test]
+    fn carries_lines_over_the_edges_of_wrapping_boards() {
+        let flat = variant(
+            Topology::Square {
+                width: 4,
+                height: 4,
+            },
+            4,
+        );
+        let torus = Variant {
+            wrap: true,
+            ..flat.clone()
+        };
+
+        let row = board(Symbol::X, &[(2, 0), (3, 0), (0, 0)]);
+        assert_eq!(torus.line_length(&row, (0, 0), (1, 0)), 3);
+        assert_eq!(flat.line_length(&row, (0, 0), (1, 0)), 1);
+
+        let diagonal = board(Symbol::O, &[(3, 3), (0, 0), (1, 1)]);
+        assert_eq!(torus.line_length(&diagonal, (0, 0), (1, 1)), 3);
+
+        // a full row goes around once, without counting a cell twice
+        let full = board(Symbol::X, &[(0, 1), (1, 1), (2, 1), (3, 1)]);
+        assert_eq!(torus.line_length(&full, (2, 1), (1, 0)), 4);
+        assert_eq!(torus.lines(&full), vec![Symbol::X]);
+        assert_eq!(torus.lines(&row), vec![]);
+    }
+
+    #[test]
+    fn names_cells_like_chess_squares() {
+        let classic = Topology::Square {
+            width: 3,
+            height: 3,
+        };
+        let hex = Topology::Hex { radius: 2 };
+        let wide = Topology::Square {
+            width: 30,
+            height: 1,
+        };
+
+        assert_eq!(classic.notation((0, 2)), "a1");
+        assert_eq!(classic.notation((2, 0)), "c3");
+        assert_eq!(hex.notation((-2, 2)), "a1");
+        assert_eq!(hex.notation((0, 0)), "c3");
+        assert_eq!(wide.notation((26, 0)), "aa1");
+        assert_eq!(Topology::Infinite.notation((-3, 12)), "-3,12");
+
+        for topology in &[classic, hex, wide] {
+            for cell in topology.cells().expect("Couldn't list the cells") {
+                assert_eq!(
+                    topology.parse_notation(&topology.notation(cell)),
+                    Some(cell)
+                );
+            }
+        }
+        assert_eq!(Topology::Infinite.parse_notation("-3, 12"), Some((-3, 12)));
+    }
+
+    #[test]
+    fn rejects_names_of_cells_off_the_board() {
+        let classic = Topology::Square {
+            width: 3,
+            height: 3,
+        };
+
+        for name in &["d1", "a0", "a4", "1a", "A1", "a", ""] {
+            assert_eq!(classic.parse_notation(name), None, "{}", name);
+        }
+        assert_eq!(Topology::Hex { radius: 2 }.parse_notation("a4"), None);
+        assert_eq!(Topology::Infinite.parse_notation("b2"), None);
+    }
+
+    #[test]
+    fn passes_the_turn_around_the_players() {
+        use Symbol::*;
+
+        assert_eq!(next_player(&[X, O, Triangle], O), Triangle);
+        assert_eq!(next_player(&[X, O, Triangle], Triangle), X);
+        assert_eq!(next_player(&[X, O, Triangle], Empty), X);
+    }
+
+    #[test]
+    fn fills_bounded_boards_only() {
+        let full = Variant::default()
+            .cells()
+            .expect("Couldn't list the cells")
+            .into_iter()
+            .map(|cell| (cell, Symbol::X))
+            .collect::<HashMap<_, _>>();
+
+        assert!(Variant::default().is_full(&full));
+        assert!(!Variant::default().is_full(&HashMap::new()));
+        assert!(!variant(Topology::Infinite, 5).is_full(&full));
+    }
+
+    #[test]
+    fn ends_two_player_games_on_the_first_line() {
+        use Symbol::*;
+        let mut players = vec![X, O];
+        let mut standings = Standings::default();
+
+        let variant = Variant::default();
+        assert!(!resolve(&variant, &[], false, &mut players, &mut standings));
+        assert!(resolve(&variant, &[O], false, &mut players, &mut standings));
+        assert_eq!(standings, Standings(vec![vec![O], vec![X]]));
+        assert!(players.is_empty());
+    }
+
+    #[test]
+    fn draws_on_a_full_board_without_lines() {
+        use Symbol::*;
+        let mut players = vec![X, O];
+        let mut standings = Standings::default();
+
+        assert!(resolve(
+            &Variant::default(),
+            &[],
+            true,
+            &mut players,
+            &mut standings
+        ));
+        assert_eq!(standings, Standings(vec![vec![X, O]]));
+    }
+
+    #[test]
+    fn plays_on_for_the_lower_ranks() {
+        use Symbol::*;
+        let variant = Variant {
+            players: 3,
+            ..Default::default()
+        };
+        let mut players = vec![X, O, Triangle];
+        let mut standings = Standings::default();
+
+        assert!(!resolve(
+            &variant,
+            &[O],
+            false,
+            &mut players,
+            &mut standings
+        ));
+        assert_eq!(players, vec![X, Triangle]);
+        assert!(resolve(
+            &variant,
+            &[Triangle],
+            false,
+            &mut players,
+            &mut standings
+        ));
+        assert_eq!(standings, Standings(vec![vec![O], vec![Triangle], vec![X]]));
+    }
+
+    #[test]
+    fn ranks_players_finishing_together_alike() {
+        use Symbol::*;
+        let variant = Variant {
+            players: 4,
+            ..Default::default()
+        };
+        let mut players = vec![X, O, Triangle, Square];
+        let mut standings = Standings::default();
+
+        assert!(!resolve(
+            &variant,
+            &[Square, X],
+            false,
+            &mut players,
+            &mut standings
+        ));
+        assert_eq!(players, vec![O, Triangle]);
+        assert!(resolve(&variant, &[], true, &mut players, &mut standings));
+        assert_eq!(
+            standings,
+            Standings(vec![vec![X, Square], vec![O, Triangle]])
+        );
+    }
+
+    #[test]
+    fn ranks_misere_players_from_the_last_place() {
+        use Symbol::*;
+        let misere = |players| Variant {
+            players,
+            misere: true,
+            ..Default::default()
+        };
+
+        let mut players = vec![X, O];
+        let mut standings = Standings::default();
+        assert!(resolve(
+            &misere(2),
+            &[X],
+            false,
+            &mut players,
+            &mut standings
+        ));
+        assert_eq!(standings, Standings(vec![vec![O], vec![X]]));
+
+        let mut players = vec![X, O, Triangle];
+        let mut standings = Standings::default();
+        assert!(!resolve(
+            &misere(3),
+            &[X],
+            false,
+            &mut players,
+            &mut standings
+        ));
+        assert!(resolve(
+            &misere(3),
+            &[O],
+            false,
+            &mut players,
+            &mut standings
+        ));
+        assert_eq!(standings, Standings(vec![vec![Triangle], vec![O], vec![X]]));
+    }
+
+    #[test]
+    fn finds_where_the_winning_lines_start_and_end() {
+        let mut played = board(Symbol::X, &[(0, 2), (1, 1), (2, 0), (0, 0), (1, 0)]);
+        played.insert((2, 2), Symbol::O);
+
+        assert_eq!(
+            Variant::default().winning_lines(&played),
+            vec![((0, 0), (2, 0)), ((0, 2), (2, 0))]
+        );
+
+        // longer lines are drawn whole, once
+        let row = board(Symbol::O, &[(0, 0), (1, 0), (2, 0), (3, 0)]);
+        assert_eq!(
+            variant(Topology::Infinite, 3).winning_lines(&row),
+            vec![((0, 0), (3, 0))]
+        );
+    }
+}
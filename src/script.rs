@@ -0,0 +1,337 @@
+use std::collections::HashMap;
+
+use rhai::{Array, Dynamic, Engine, EvalAltResult, Scope, AST};
+
+use crate::rules::{Cell, Symbol, Variant};
+
+const MAX_OPERATIONS: u64 = 100_000;
+const MAX_CALL_LEVELS: usize = 32;
+
+/// Board handed to the scripts, they can only read and write its cells.
+#[derive(Clone)]
+pub struct ScriptBoard {
+    variant: Variant,
+    cells: HashMap<Cell, Symbol>,
+}
+
+impl ScriptBoard {
+    fn get(&mut self, q: i64, r: i64) -> String {
+        self.cells
+            .get(&(q as i32, r as i32))
+            .map_or_else(String::new, Symbol::to_string)
+    }
+
+    fn set(&mut self, q: i64, r: i64, symbol: &str) -> Result<(), Box<EvalAltResult>> {
+        let cell = (q as i32, r as i32);
+
+        if !self.variant.is_playable(cell) {
+            return Err(format!("({}, {}) isn't a playable cell", q, r).into());
+        }
+
+        let symbol = parse_symbol(symbol)?;
+        self.cells.insert(cell, symbol);
+
+        Ok(())
+    }
+
+    fn clear(&mut self, q: i64, r: i64) {
+        self.cells.remove(&(q as i32, r as i32));
+    }
+
+    fn playable(&mut self, q: i64, r: i64) -> bool {
+        self.variant.is_playable((q as i32, r as i32))
+    }
+
+    fn line_length(&mut self, q: i64, r: i64, dq: i64, dr: i64) -> i64 {
+        self.variant
+            .line_length(&self.cells, (q as i32, r as i32), (dq as i32, dr as i32)) as i64
+    }
+
+    /// Occupied cells as `[q, r]` pairs.
+    fn occupied(&mut self) -> Array {
+        self.cells
+            .keys()
+            .map(|&(q, r)| vec![Dynamic::from(q as i64), Dynamic::from(r as i64)].into())
+            .collect()
+    }
+
+    fn win_length(&mut self) -> i64 {
+        self.variant.win_length as i64
+    }
+}
+
+fn parse_symbol(name: &str) -> Result<Symbol, Box<EvalAltResult>> {
+    Symbol::PLAYERS
+        .iter()
+        .copied()
+        .find(|symbol| symbol.to_string() == name)
+        .ok_or_else(|| format!("{:?} isn't a player symbol", name).into())
+}
+
+/// House rules of a variant, written as `legal_move`, `apply_move` and `outcome` Rhai hooks.
+///
+/// Hooks the script doesn't define keep the built-in rules, and so do the ones that fail,
+/// their error is kept for the game to show.
+pub struct RuleScript {
+    engine: Engine,
+    ast: Option<AST>,
+    pub error: Option<String>,
+}
+
+impl Default for RuleScript {
+    fn default() -> Self {
+        let mut engine = Engine::new();
+
+        // scripts can't reach outside of the board, nor run forever
+        engine
+            .set_max_operations(MAX_OPERATIONS)
+            .set_max_call_levels(MAX_CALL_LEVELS)
+            .disable_symbol("eval")
+            .register_type_with_name::<ScriptBoard>("Board")
+            .register_fn("get", ScriptBoard::get)
+            .register_fn("set", ScriptBoard::set)
+            .register_fn("clear", ScriptBoard::clear)
+            .register_fn("playable", ScriptBoard::playable)
+            .register_fn("line_length", ScriptBoard::line_length)
+            .register_fn("occupied", ScriptBoard::occupied)
+            .register_get("win_length", ScriptBoard::win_length);
+
+        Self {
+            engine,
+            ast: None,
+            error: None,
+        }
+    }
+}
+
+impl RuleScript {
+    /// Replaces the hooks, `None` goes back to the built-in rules.
+    pub fn load(&mut self, source: Option<&str>) {
+        self.error = None;
+        self.ast = source.and_then(|source| match self.engine.compile(source) {
+            Ok(ast) => Some(ast),
+            Err(error) => {
+                self.error = Some(format!("Script error: {}", error));
+                None
+            }
+        });
+    }
+
+    fn call<T: Clone + Send + Sync + 'static>(
+        &mut self,
+        hook: &str,
+        variant: &Variant,
+        board: &HashMap<Cell, Symbol>,
+        args: Vec<Dynamic>,
+    ) -> Option<T> {
+        let ast = self.ast.as_ref()?;
+        if !ast.iter_functions().any(|function| function.name == hook) {
+            return None;
+        }
+
+        let board = ScriptBoard {
+            variant: variant.clone(),
+            cells: board.clone(),
+        };
+        let mut args = args;
+        args.insert(0, Dynamic::from(board));
+
+        match self.engine.call_fn::<T>(&mut Scope::new(), ast, hook, args) {
+            Ok(result) => Some(result),
+            Err(error) => {
+                self.error = Some(format!("Script error in {}: {}", hook, error));
+                None
+            }
+        }
+    }
+
+    fn move_args((q, r): Cell, player: Symbol) -> Vec<Dynamic> {
+        vec![
+            Dynamic::from(q as i64),
+            Dynamic::from(r as i64),
+            Dynamic::from(player.to_string()),
+        ]
+    }
+
+    pub fn legal_move(
+        &mut self,
+        variant: &Variant,
+        board: &HashMap<Cell, Symbol>,
+        cell: Cell,
+        player: Symbol,
+    ) -> bool {
+        self.call::<bool>("legal_move", variant, board, Self::move_args(cell, player))
+            .unwrap_or(true)
+    }
+
+    pub fn apply_move(
+        &mut self,
+        variant: &Variant,
+        board: &mut HashMap<Cell, Symbol>,
+        cell: Cell,
+        player: Symbol,
+    ) {
+        match self.call::<ScriptBoard>("apply_move", variant, board, Self::move_args(cell, player))
+        {
+            Some(result) => *board = result.cells,
+            None => {
+                board.insert(cell, player);
+            }
+        }
+    }
+
+    /// Symbols which completed the game's goal, the script returns `()` to keep the built-in lines.
+    pub fn outcome(&mut self, variant: &Variant, board: &HashMap<Cell, Symbol>) -> Vec<Symbol> {
+        let finished = self
+            .call::<Dynamic>("outcome", variant, board, Vec::new())
+            .filter(|result| !result.is::<()>())
+            .map(|result| {
+                result
+                    .into_array()
+                    .map_err(|kind| format!("expected an array of symbols, got {}", kind))?
+                    .into_iter()
+                    .map(|name| match name.into_string() {
+                        Ok(name) => parse_symbol(&name).map_err(|error| error.to_string()),
+                        Err(kind) => Err(format!("expected a symbol, got {}", kind)),
+                    })
+                    .collect::<Result<Vec<_>, _>>()
+            });
+
+        match finished {
+            Some(Ok(finished)) => finished,
+            Some(Err(error)) => {
+                self.error = Some(format!("Script error in outcome: {}", error));
+                variant.lines(board)
+            }
+            None => variant.lines(board),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rules::Topology;
+
+    const SANDWICH: &str = include_str!("../assets/scripts/sandwich.rhai");
+
+    fn script(source: &str) -> RuleScript {
+        let mut script = RuleScript::default();
+        script.load(Some(source));
+
+        script
+    }
+
+    /// Error the script kept once `legal_move` ran on an empty Classic board.
+    fn legal_move_error(source: &str) -> String {
+        let mut script = script(source);
+
+        assert!(script.legal_move(&Variant::default(), &HashMap::new(), (0, 0), Symbol::X));
+        script.error.expect("The script didn't fail")
+    }
+
+    #[test]
+    fn keeps_the_built_in_rules_without_a_script() {
+        let variant = Variant::default();
+        let mut script = RuleScript::default();
+        let mut board = HashMap::new();
+
+        assert!(script.legal_move(&variant, &board, (1, 1), Symbol::X));
+        script.apply_move(&variant, &mut board, (1, 1), Symbol::X);
+        assert_eq!(board.get(&(1, 1)), Some(&Symbol::X));
+
+        board.insert((0, 0), Symbol::X);
+        board.insert((2, 2), Symbol::X);
+        assert_eq!(script.outcome(&variant, &board), vec![Symbol::X]);
+        assert_eq!(script.error, None);
+    }
+
+    #[test]
+    fn runs_the_hooks_of_the_script() {
+        let variant = Variant {
+            topology: Topology::Square {
+                width: 5,
+                height: 5,
+            },
+            win_length: 4,
+            ..Default::default()
+        };
+        let mut sandwich = script(SANDWICH);
+        let mut board = HashMap::new();
+
+        assert!(!sandwich.legal_move(&variant, &board, (2, 2), Symbol::X));
+        assert!(sandwich.legal_move(&variant, &board, (1, 1), Symbol::X));
+
+        board.insert((0, 0), Symbol::X);
+        board.insert((1, 0), Symbol::O);
+        sandwich.apply_move(&variant, &mut board, (2, 0), Symbol::X);
+        assert_eq!(board.get(&(1, 0)), Some(&Symbol::X));
+        assert_eq!(board.get(&(2, 0)), Some(&Symbol::X));
+        assert_eq!(sandwich.error, None);
+    }
+
+    #[test]
+    fn takes_the_outcome_the_script_returns() {
+        let variant = Variant::default();
+        let mut script = script("fn outcome(board) { if board.occupied().len() > 1 { [\"O\"] } }");
+        let mut board = HashMap::new();
+
+        // nothing returned keeps the lines
+        board.insert((0, 0), Symbol::X);
+        assert_eq!(script.outcome(&variant, &board), vec![]);
+
+        board.insert((1, 1), Symbol::X);
+        assert_eq!(script.outcome(&variant, &board), vec![Symbol::O]);
+        assert_eq!(script.error, None);
+    }
+
+    #[test]
+    fn falls_back_to_the_built_in_rules_on_errors() {
+        let variant = Variant::default();
+        let mut board = HashMap::new();
+
+        let broken = script("fn legal_move(board, q, r, player) {");
+        assert!(broken
+            .error
+            .expect("The script compiled")
+            .starts_with("Script error: "));
+
+        let mut failing =
+            script("fn apply_move(board, q, r, player) { board.set(q, r, \"Z\"); board }");
+        failing.apply_move(&variant, &mut board, (0, 0), Symbol::X);
+        assert_eq!(board.get(&(0, 0)), Some(&Symbol::X));
+        assert!(failing
+            .error
+            .expect("The script didn't fail")
+            .contains("\"Z\" isn't a player symbol"));
+
+        let mut wrong = script("fn outcome(board) { 42 }");
+        board.insert((1, 1), Symbol::X);
+        board.insert((2, 2), Symbol::X);
+        assert_eq!(wrong.outcome(&variant, &board), vec![Symbol::X]);
+        assert!(wrong
+            .error
+            .expect("The script didn't fail")
+            .starts_with("Script error in outcome: expected an array of symbols"));
+    }
+
+    #[test]
+    fn keeps_scripts_on_the_board() {
+        let error = legal_move_error(
+            "fn legal_move(board, q, r, player) { board.set(3, 0, player); true }",
+        );
+
+        assert!(error.contains("(3, 0) isn't a playable cell"), "{}", error);
+    }
+
+    #[test]
+    fn stops_scripts_running_forever() {
+        let looping = legal_move_error("fn legal_move(board, q, r, player) { loop {} }");
+        let recursing = legal_move_error(
+            "fn legal_move(board, q, r, player) { legal_move(board, q, r, player) }",
+        );
+
+        assert!(looping.contains("Too many operations"), "{}", looping);
+        assert!(recursing.contains("Stack overflow"), "{}", recursing);
+    }
+}
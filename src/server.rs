@@ -0,0 +1,900 @@
+//! Rooms of the headless server, the players meet in them and the server checks their moves.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    io::ErrorKind,
+    iter,
+    net::{SocketAddr, TcpListener},
+    time::{Duration, Instant},
+};
+
+use log::{info, warn};
+use rand::random;
+
+use crate::{
+    export::load_variant,
+    notation::{standings_notation, today, ChatLine, GameRecord, RecordedMove},
+    protocol::{
+        chat_text, Connection, Message, Offer, CHAT_LIMIT, CHAT_WINDOW_SECONDS, GRACE_SECONDS,
+        OFFER_SECONDS, PROTOCOL_VERSION,
+    },
+    rules::{Cell, Symbol, Variant},
+    script::RuleScript,
+};
+
+struct Client {
+    connection: Connection,
+    address: SocketAddr,
+    /// `None` until it said hello.
+    name: Option<String>,
+    room: Option<String>,
+    /// When its last chat messages were sent, to drop the ones over `CHAT_LIMIT`.
+    chatted: VecDeque<Instant>,
+}
+
+struct Seat {
+    /// `None` while the player is away.
+    client: Option<usize>,
+    name: String,
+    /// Handed to the player with the welcome, resuming the game takes it.
+    token: String,
+    /// When the player lost the connection.
+    away_since: Option<Instant>,
+}
+
+struct Spectator {
+    client: usize,
+    /// Moves sent so far.
+    shown: usize,
+    /// Chat lines of the players sent so far.
+    heard: usize,
+}
+
+struct PendingOffer {
+    offer: Offer,
+    /// Seat of the player who made it.
+    seat: usize,
+    deadline: Instant,
+}
+
+struct Room {
+    variant: Variant,
+    rule_script: RuleScript,
+    /// Taken in the order the players came in, they play the symbols in that order.
+    seats: Vec<Seat>,
+    spectators: Vec<Spectator>,
+    /// `None` until every seat is taken.
+    record: Option<GameRecord>,
+    /// Cells of the moves and when they were played, for the spectators.
+    played: Vec<(Cell, Instant)>,
+    /// When the chat lines of the game were said, the spectators hear them as late as they see
+    /// the moves.
+    said: Vec<Instant>,
+    /// Waiting for the other player's answer, nobody plays meanwhile.
+    offer: Option<PendingOffer>,
+}
+
+/// Clients and rooms of a server, `update` them every now and then.
+#[derive(Default)]
+pub struct Server {
+    clients: HashMap<usize, Client>,
+    rooms: HashMap<String, Room>,
+    next_id: usize,
+    /// Time the spectators see the moves after the players.
+    delay: Duration,
+}
+
+impl Server {
+    /// `delay` is the time the spectators see the moves after the players.
+    pub fn new(delay: Duration) -> Self {
+        Self {
+            delay,
+            ..Default::default()
+        }
+    }
+
+    /// Takes the new clients in, handles what the clients sent, and closes the rooms and declines
+    /// the offers whose time is up.
+    pub fn update(&mut self, listener: &TcpListener) {
+        self.accept(listener);
+        self.poll();
+        self.flush_all();
+        self.expire();
+    }
+
+    fn accept(&mut self, listener: &TcpListener) {
+        loop {
+            let (stream, address) = match listener.accept() {
+                Ok(accepted) => accepted,
+                Err(error) if error.kind() == ErrorKind::WouldBlock => return,
+                Err(error) => {
+                    warn!("Couldn't accept a client: {}", error);
+                    return;
+                }
+            };
+
+            match Connection::accept(stream) {
+                Ok(connection) => {
+                    info!("{} connected", address);
+
+                    self.clients.insert(
+                        self.next_id,
+                        Client {
+                            connection,
+                            address,
+                            name: None,
+                            room: None,
+                            chatted: VecDeque::new(),
+                        },
+                    );
+                    self.next_id += 1;
+                }
+                Err(error) => warn!("Couldn't talk to {}: {}", address, error),
+            }
+        }
+    }
+
+    fn poll(&mut self) {
+        let mut ids = self.clients.keys().copied().collect::<Vec<_>>();
+        ids.sort_unstable();
+
+        for id in ids {
+            // stops once the client left, maybe while handling another client's message
+            while let Some(client) = self.clients.get(&id) {
+                match client.connection.receive() {
+                    Ok(Some(message)) => self.handle(id, message),
+                    Ok(None) => break,
+                    Err(error) => {
+                        self.leave(id, &error.to_string());
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    fn send(&self, id: usize, message: &Message) {
+        // a failed send shows up as the connection closing, when receiving
+        if let Some(client) = self.clients.get(&id) {
+            let _ = client.connection.send(message);
+        }
+    }
+
+    /// Turns a client away, the connection gets closed right after.
+    fn refuse(&mut self, id: usize, reason: String) {
+        self.send(id, &Message::Refused { reason });
+        self.leave(id, "refused");
+    }
+
+    fn handle(&mut self, id: usize, message: Message) {
+        let client = match self.clients.get_mut(&id) {
+            Some(client) => client,
+            None => return,
+        };
+
+        match (message, client.name.is_some()) {
+            (Message::Hello { version, name }, false) => {
+                if version != PROTOCOL_VERSION {
+                    return self.refuse(
+                        id,
+                        format!(
+                            "the server speaks version {} of the protocol, not {}",
+                            PROTOCOL_VERSION, version
+                        ),
+                    );
+                }
+
+                info!("{} is {}", client.address, name);
+                client.name = Some(name);
+            }
+            (Message::JoinRoom { room, variant }, true) if client.room.is_none() => {
+                self.join_room(id, room, &variant)
+            }
+            (Message::Resume { room, token }, true) if client.room.is_none() => {
+                self.resume(id, room, &token)
+            }
+            (Message::Watch { room }, true) if client.room.is_none() => self.watch(id, room),
+            (Message::QuickMatch { variant }, true) if client.room.is_none() => {
+                self.quick_match(id, &variant)
+            }
+            (Message::Move { ply, cell }, true) => self.play(id, ply, cell),
+            (Message::Chat { text, .. }, true) => self.chat(id, &text),
+            (Message::Offer { offer, ply }, true) => self.offer(id, offer, ply),
+            (Message::Answer { offer, accepted }, true) => self.answer(id, offer, accepted),
+            (message, _) => self.refuse(id, format!("unexpected message {:?}", message)),
+        }
+    }
+
+    fn join_room(&mut self, id: usize, name: String, variant: &str) {
+        if !self.rooms.contains_key(&name) {
+            let (variant, rule_script) = match load_variant(variant) {
+                Ok(loaded) => loaded,
+                Err(error) => return self.refuse(id, error),
+            };
+
+            // every player would see the whole board
+            if variant.blind {
+                return self.refuse(id, "blind games are played on one screen".to_string());
+            }
+
+            info!("Room {} opened for {}", name, variant);
+            self.rooms.insert(
+                name.clone(),
+                Room {
+                    variant,
+                    rule_script,
+                    seats: Vec::new(),
+                    spectators: Vec::new(),
+                    record: None,
+                    played: Vec::new(),
+                    said: Vec::new(),
+                    offer: None,
+                },
+            );
+        }
+
+        let room = self.rooms.get_mut(&name).expect("Couldn't find the room");
+        if room.record.is_some() {
+            return self.refuse(id, format!("the game of room {} has started", name));
+        }
+
+        let client = self.clients.get_mut(&id).expect("Couldn't find the client");
+        client.room = Some(name.clone());
+        room.seats.push(Seat {
+            client: Some(id),
+            name: client.name.clone().unwrap_or_default(),
+            token: format!("{:016x}", random::<u64>()),
+            away_since: None,
+        });
+
+        if room.seats.len() < room.variant.players {
+            self.send_waiting(&name);
+        } else {
+            self.start(&name);
+        }
+    }
+
+    /// Seats a player in a room waiting for players of the variant, the one with the first name,
+    /// or in a new room.
+    fn quick_match(&mut self, id: usize, variant: &str) {
+        let waiting = self
+            .rooms
+            .iter()
+            .filter(|(_, room)| room.record.is_none() && room.variant.name == variant)
+            .map(|(name, room)| (name, room.seats.len(), room.variant.players))
+            .min_by_key(|&(name, _, _)| name);
+
+        let name = match waiting {
+            Some((name, players, seats)) => {
+                let name = name.clone();
+
+                // the welcome doesn't name the room, resuming the game needs it
+                if players + 1 == seats {
+                    let waiting = Message::Waiting {
+                        room: name.clone(),
+                        players: seats,
+                        seats,
+                    };
+                    self.send(id, &waiting);
+                }
+
+                name
+            }
+            None => format!("quick-{:08x}", random::<u32>()),
+        };
+
+        self.join_room(id, name, variant);
+    }
+
+    /// Gives a player who lost the connection their seat back, with the whole game.
+    fn resume(&mut self, id: usize, name: String, token: &str) {
+        let room = match self.rooms.get_mut(&name) {
+            Some(room) => room,
+            None => return self.refuse(id, format!("room {} is closed", name)),
+        };
+        // the seats are handed out with the game
+        if room.record.is_none() {
+            return self.refuse(id, format!("the game of room {} hasn't started", name));
+        }
+        let index = match room.seats.iter().position(|seat| seat.token == token) {
+            Some(index) => index,
+            None => return self.refuse(id, format!("no seat of room {} is yours", name)),
+        };
+
+        // the old connection may not have noticed it's gone yet
+        let seat = &mut room.seats[index];
+        if let Some(old) = seat.client.replace(id) {
+            self.clients.remove(&old);
+        }
+        seat.away_since = None;
+
+        let player = seat.name.clone();
+        if let Some(client) = self.clients.get_mut(&id) {
+            client.room = Some(name.clone());
+        }
+        info!("Room {}: {} is back", name, player);
+
+        self.send(id, &self.welcome(&name, index));
+        self.send_room(&name, &Message::Back { name: player }, Some(id));
+    }
+
+    /// Seats a spectator in a room, with the game as far as the spectators got.
+    fn watch(&mut self, id: usize, name: String) {
+        let (shown, heard) = match self.rooms.get(&name) {
+            Some(room) => (
+                room.played
+                    .iter()
+                    .take_while(|(_, at)| at.elapsed() >= self.delay)
+                    .count(),
+                room.said
+                    .iter()
+                    .take_while(|at| at.elapsed() >= self.delay)
+                    .count(),
+            ),
+            None => return self.refuse(id, format!("there's no room {}", name)),
+        };
+
+        if let Some(client) = self.clients.get_mut(&id) {
+            client.room = Some(name.clone());
+        }
+        let room = self.rooms.get_mut(&name).expect("Couldn't find the room");
+        room.spectators.push(Spectator {
+            client: id,
+            shown,
+            heard,
+        });
+
+        if room.record.is_some() {
+            self.send(id, &self.spectator_welcome(&name, shown, heard));
+        } else {
+            self.send_waiting(&name);
+        }
+        self.send_spectators(&name);
+    }
+
+    /// Sends a message to the players of a room who are there but `except`, and to its spectators.
+    fn send_room(&self, name: &str, message: &Message, except: Option<usize>) {
+        let room = &self.rooms[name];
+        let clients = room
+            .seats
+            .iter()
+            .filter_map(|seat| seat.client)
+            .chain(room.spectators.iter().map(|spectator| spectator.client));
+
+        for client in clients.filter(|&client| Some(client) != except) {
+            self.send(client, message);
+        }
+    }
+
+    fn send_players(&self, name: &str, message: &Message) {
+        for client in self.rooms[name].seats.iter().filter_map(|seat| seat.client) {
+            self.send(client, message);
+        }
+    }
+
+    fn send_spectators(&self, name: &str) {
+        let count = self.rooms[name].spectators.len();
+
+        self.send_room(name, &Message::Spectators { count }, None);
+    }
+
+    /// Tells the players of a room how many are there so far.
+    fn send_waiting(&self, name: &str) {
+        let room = &self.rooms[name];
+
+        self.send_room(
+            name,
+            &Message::Waiting {
+                room: name.to_string(),
+                players: room.seats.len(),
+                seats: room.variant.players,
+            },
+            None,
+        );
+    }
+
+    /// The game of a room so far, for the player of the seat at `index`.
+    fn welcome(&self, name: &str, index: usize) -> Message {
+        let room = &self.rooms[name];
+        let others = room
+            .seats
+            .iter()
+            .enumerate()
+            .filter(|&(other, _)| other != index)
+            .map(|(_, seat)| seat.name.as_str())
+            .collect::<Vec<_>>();
+
+        Message::Welcome {
+            name: others.join(", "),
+            record: room
+                .record
+                .as_ref()
+                .map(GameRecord::to_string)
+                .unwrap_or_default(),
+            symbol: Symbol::PLAYERS[index],
+            token: Some(room.seats[index].token.clone()),
+        }
+    }
+
+    /// The game of a room up to the `shown` moves and the `heard` chat lines, for a spectator.
+    fn spectator_welcome(&self, name: &str, shown: usize, heard: usize) -> Message {
+        let room = &self.rooms[name];
+        let players = room
+            .seats
+            .iter()
+            .map(|seat| seat.name.as_str())
+            .collect::<Vec<_>>();
+
+        let mut record = room.record.clone().unwrap_or_default();
+        // the result would give the delayed moves away
+        if shown < record.moves.len() {
+            record.moves.truncate(shown);
+            record.result = None;
+        }
+        record.chat.truncate(heard);
+        record.chat.retain(|line| line.ply <= shown);
+
+        Message::Welcome {
+            name: players.join(", "),
+            record: record.to_string(),
+            symbol: Symbol::Empty,
+            token: None,
+        }
+    }
+
+    fn start(&mut self, name: &str) {
+        let room = self.rooms.get_mut(name).expect("Couldn't find the room");
+
+        room.record = Some(GameRecord {
+            variant: room.variant.name.clone(),
+            players: Symbol::PLAYERS[..room.variant.players].to_vec(),
+            date: today(),
+            game: None,
+            result: None,
+            seed: if room.variant.random_turns {
+                random()
+            } else {
+                0
+            },
+            setup: None,
+            ply: None,
+            slot: None,
+            moves: Vec::new(),
+            chat: Vec::new(),
+            signature: None,
+        });
+
+        let names = room
+            .seats
+            .iter()
+            .map(|seat| seat.name.as_str())
+            .collect::<Vec<_>>();
+        info!("Room {}: {} started", name, names.join(", "));
+
+        self.send_welcomes(name);
+    }
+
+    /// Sends the game as it is to the players and spectators of a room.
+    fn send_welcomes(&self, name: &str) {
+        let room = &self.rooms[name];
+        for (index, seat) in room.seats.iter().enumerate() {
+            if let Some(client) = seat.client {
+                self.send(client, &self.welcome(name, index));
+            }
+        }
+        for spectator in room.spectators.iter() {
+            self.send(
+                spectator.client,
+                &self.spectator_welcome(name, spectator.shown, spectator.heard),
+            );
+        }
+    }
+
+    /// Checks a move by replaying the game with it, then sends it to every player of the room, the
+    /// spectators get it once it's old enough.
+    fn play(&mut self, id: usize, ply: usize, cell: Cell) {
+        let name = match self.clients.get(&id).and_then(|client| client.room.clone()) {
+            Some(name) => name,
+            None => return self.refuse(id, "moves are played in a room".to_string()),
+        };
+        let room = self.rooms.get_mut(&name).expect("Couldn't find the room");
+
+        let seat = room.seats.iter().position(|seat| seat.client == Some(id));
+        let away = room.seats.iter().find(|seat| seat.away_since.is_some());
+        let refusal = match (room.record.as_ref(), away) {
+            _ if seat.is_none() => Some("spectators can't play".to_string()),
+            (None, _) => Some("the game hasn't started".to_string()),
+            (_, Some(away)) => Some(format!("waiting for {} to come back", away.name)),
+            _ if room.offer.is_some() => Some("an offer is waiting for an answer".to_string()),
+            // over by agreement, the moves don't tell
+            (Some(record), None) if record.result.is_some() => Some("the game is over".to_string()),
+            (Some(record), None) if ply != record.moves.len() => {
+                Some("the game moved on".to_string())
+            }
+            _ => None,
+        };
+        if let Some(reason) = refusal {
+            return self.send(id, &Message::Rejected { reason });
+        }
+
+        let mut played = room.record.clone().expect("Couldn't find the game");
+        played.moves.push(RecordedMove {
+            symbol: Symbol::PLAYERS[seat.expect("Couldn't find the seat")],
+            cell: room.variant.topology.notation(cell),
+            line: 0,
+            column: 0,
+        });
+
+        let positions = match played.replay(&room.variant, &mut room.rule_script) {
+            Ok((_, positions)) => positions,
+            Err(error) => {
+                return self.send(
+                    id,
+                    &Message::Rejected {
+                        reason: error.message,
+                    },
+                )
+            }
+        };
+
+        // the game is over once nobody is left to play
+        if let Some(last) = positions
+            .last()
+            .filter(|position| position.players.is_empty())
+        {
+            played.result = Some(last.standings.clone());
+            info!("Room {}: {}", name, standings_notation(&played.result));
+        }
+        room.record = Some(played);
+        room.played.push((cell, Instant::now()));
+
+        self.send_players(&name, &Message::Move { ply, cell });
+        self.flush(&name, self.delay);
+    }
+
+    /// Sends the spectators of a room the moves played and the chat lines said at least `delay`
+    /// ago.
+    fn flush(&mut self, name: &str, delay: Duration) {
+        let room = match self.rooms.get_mut(name) {
+            Some(room) => room,
+            None => return,
+        };
+        let visible = room
+            .played
+            .iter()
+            .take_while(|(_, at)| at.elapsed() >= delay)
+            .count();
+        let audible = room
+            .said
+            .iter()
+            .take_while(|at| at.elapsed() >= delay)
+            .count();
+        let lines = room
+            .record
+            .as_ref()
+            .map_or(&[][..], |record| &record.chat[..audible]);
+
+        let mut messages = Vec::new();
+        for spectator in room.spectators.iter_mut() {
+            // each line comes after the moves played before it was said
+            let heard = lines
+                .iter()
+                .skip(spectator.heard)
+                .map(|line| (line.ply.min(visible), Some(line)));
+
+            for (until, line) in heard.chain(iter::once((visible, None))) {
+                for ply in spectator.shown..until {
+                    let cell = room.played[ply].0;
+                    messages.push((spectator.client, Message::Move { ply, cell }));
+                }
+                spectator.shown = spectator.shown.max(until);
+
+                if let Some(line) = line {
+                    let chat = Message::Chat {
+                        name: line.name.clone(),
+                        text: line.text.clone(),
+                    };
+                    messages.push((spectator.client, chat));
+                }
+            }
+            spectator.heard = spectator.heard.max(lines.len());
+        }
+
+        for (client, message) in messages {
+            self.send(client, &message);
+        }
+    }
+
+    fn flush_all(&mut self) {
+        let names = self.rooms.keys().cloned().collect::<Vec<_>>();
+
+        for name in names {
+            self.flush(&name, self.delay);
+        }
+    }
+
+    /// Passes a chat message on to the room, the players' messages are kept in the game record.
+    fn chat(&mut self, id: usize, text: &str) {
+        let client = self.clients.get_mut(&id).expect("Couldn't find the client");
+        let window = Duration::from_secs(CHAT_WINDOW_SECONDS);
+        while client
+            .chatted
+            .front()
+            .map_or(false, |sent| sent.elapsed() > window)
+        {
+            client.chatted.pop_front();
+        }
+
+        if client.chatted.len() >= CHAT_LIMIT {
+            return self.send(
+                id,
+                &Message::Rejected {
+                    reason: "too many chat messages, slow down".to_string(),
+                },
+            );
+        }
+        client.chatted.push_back(Instant::now());
+
+        let (name, text) = match (client.room.clone(), chat_text(text)) {
+            (Some(name), text) if !text.is_empty() => (name, text),
+            _ => return,
+        };
+        let chat = Message::Chat {
+            name: client.name.clone().unwrap_or_default(),
+            text: text.clone(),
+        };
+
+        let room = self.rooms.get_mut(&name).expect("Couldn't find the room");
+        if room.seats.iter().any(|seat| seat.client == Some(id)) {
+            let record = match room.record {
+                Some(ref mut record) => record,
+                // nothing to give away before the game starts
+                None => return self.send_room(&name, &chat, Some(id)),
+            };
+            record.chat.push(ChatLine {
+                ply: record.moves.len(),
+                name: client.name.clone().unwrap_or_default(),
+                text,
+            });
+            room.said.push(Instant::now());
+
+            // the spectators hear it as late as they see the moves
+            let players = room
+                .seats
+                .iter()
+                .filter_map(|seat| seat.client)
+                .filter(|&client| client != id)
+                .collect::<Vec<_>>();
+            for client in players {
+                self.send(client, &chat);
+            }
+            self.flush(&name, self.delay);
+        } else {
+            // spectators don't get to tell the players anything
+            let spectators = room
+                .spectators
+                .iter()
+                .map(|spectator| spectator.client)
+                .filter(|&client| client != id)
+                .collect::<Vec<_>>();
+
+            for client in spectators {
+                self.send(client, &chat);
+            }
+        }
+    }
+
+    /// Passes an offer on to the other player of a two-player room.
+    fn offer(&mut self, id: usize, offer: Offer, ply: usize) {
+        let name = match self.clients.get(&id).and_then(|client| client.room.clone()) {
+            Some(name) => name,
+            None => return self.refuse(id, "offers are made in a room".to_string()),
+        };
+        let room = self.rooms.get_mut(&name).expect("Couldn't find the room");
+
+        let seat = room.seats.iter().position(|seat| seat.client == Some(id));
+        let allowed = match (seat, room.record.as_ref()) {
+            (None, _) => Err("spectators can't make offers".to_string()),
+            (_, None) => Err("the game hasn't started".to_string()),
+            _ if room.seats.len() != 2 => Err("offers are for two-player games".to_string()),
+            _ if room.seats.iter().any(|seat| seat.away_since.is_some()) => {
+                Err("the other player is away".to_string())
+            }
+            _ if room.offer.is_some() => Err("an offer is waiting for an answer".to_string()),
+            (_, Some(record)) if ply != record.moves.len() => Err("the game moved on".to_string()),
+            (_, Some(record)) => offer.allowed(record.moves.len(), record.result.is_some()),
+        };
+        if let Err(reason) = allowed {
+            return self.send(id, &Message::Rejected { reason });
+        }
+
+        let seat = seat.expect("Couldn't find the seat");
+        room.offer = Some(PendingOffer {
+            offer,
+            seat,
+            deadline: Instant::now() + Duration::from_secs(OFFER_SECONDS),
+        });
+
+        if let Some(client) = room.seats[1 - seat].client {
+            self.send(client, &Message::Offer { offer, ply });
+        }
+    }
+
+    /// Tells the player who made the offer, and carries it out once it's accepted.
+    fn answer(&mut self, id: usize, offer: Offer, accepted: bool) {
+        let name = match self.clients.get(&id).and_then(|client| client.room.clone()) {
+            Some(name) => name,
+            None => return,
+        };
+        let room = self.rooms.get_mut(&name).expect("Couldn't find the room");
+
+        // answers coming after the offer timed out are dropped
+        let seat = room.seats.iter().position(|seat| seat.client == Some(id));
+        let pending = match (room.offer.take(), seat) {
+            (Some(pending), Some(seat)) if pending.offer == offer && pending.seat != seat => {
+                pending
+            }
+            (pending, _) => {
+                room.offer = pending;
+                return;
+            }
+        };
+
+        if let Some(client) = room.seats[pending.seat].client {
+            self.send(client, &Message::Answer { offer, accepted });
+        }
+        if accepted {
+            self.carry_out(&name, offer);
+        }
+    }
+
+    fn carry_out(&mut self, name: &str, offer: Offer) {
+        let room = self.rooms.get_mut(name).expect("Couldn't find the room");
+        let record = room.record.as_ref().expect("Couldn't find the game");
+
+        match offer.accept(record, &room.variant, &mut room.rule_script) {
+            Ok(accepted) => room.record = Some(accepted),
+            Err(error) => return warn!("Room {}: couldn't carry {:?} out: {}", name, offer, error),
+        }
+        info!("Room {}: {:?} accepted", name, offer);
+
+        match offer {
+            // the players swap their symbols
+            Offer::Rematch => {
+                room.seats.reverse();
+                room.played.clear();
+                room.said.clear();
+            }
+            Offer::Takeback => {
+                room.played.pop();
+            }
+            Offer::Draw => {}
+        }
+        for spectator in room.spectators.iter_mut() {
+            spectator.shown = spectator.shown.min(room.played.len());
+            spectator.heard = spectator.heard.min(room.said.len());
+        }
+
+        // nothing left to give away once the game is over
+        if offer == Offer::Draw {
+            self.flush(name, Duration::from_secs(0));
+        }
+        self.send_welcomes(name);
+    }
+
+    /// Forgets a client, the seat it played in is kept while the game goes on.
+    fn leave(&mut self, id: usize, reason: &str) {
+        let client = match self.clients.remove(&id) {
+            Some(client) => client,
+            None => return,
+        };
+        info!(
+            "{} left: {}",
+            client
+                .name
+                .as_deref()
+                .unwrap_or(&client.address.to_string()),
+            reason
+        );
+
+        let name = match client.room {
+            Some(name) => name,
+            None => return,
+        };
+        let room = self.rooms.get_mut(&name).expect("Couldn't find the room");
+        let index = match room.seats.iter().position(|seat| seat.client == Some(id)) {
+            Some(index) => index,
+            None => {
+                room.spectators.retain(|spectator| spectator.client != id);
+                return self.send_spectators(&name);
+            }
+        };
+
+        match room.record {
+            Some(ref record) if record.result.is_none() => {
+                room.offer = None;
+                let seat = &mut room.seats[index];
+                seat.client = None;
+                seat.away_since = Some(Instant::now());
+
+                let away = Message::Away {
+                    name: seat.name.clone(),
+                    seconds: GRACE_SECONDS,
+                };
+                self.send_room(&name, &away, None);
+            }
+            // the other players see the game end as their connection closes
+            Some(_) => self.close(&name),
+            None => {
+                room.seats.remove(index);
+
+                if room.seats.is_empty() {
+                    self.close(&name);
+                } else {
+                    self.send_waiting(&name);
+                }
+            }
+        }
+    }
+
+    /// Closes the rooms whose players didn't come back in time, and declines the offers nobody
+    /// answered.
+    fn expire(&mut self) {
+        let grace = Duration::from_secs(GRACE_SECONDS);
+        let expired = self
+            .rooms
+            .iter()
+            .filter(|(_, room)| {
+                room.seats.iter().any(|seat| {
+                    seat.away_since
+                        .map_or(false, |away_since| away_since.elapsed() > grace)
+                })
+            })
+            .map(|(name, _)| name.clone())
+            .collect::<Vec<_>>();
+
+        for name in expired {
+            self.close(&name);
+        }
+
+        let now = Instant::now();
+        let declined = self
+            .rooms
+            .iter_mut()
+            .filter(|(_, room)| {
+                room.offer
+                    .as_ref()
+                    .map_or(false, |pending| pending.deadline <= now)
+            })
+            .filter_map(|(name, room)| Some((name.clone(), room.offer.take()?.offer)))
+            .collect::<Vec<_>>();
+
+        for (name, offer) in declined {
+            let answer = Message::Answer {
+                offer,
+                accepted: false,
+            };
+            self.send_players(&name, &answer);
+        }
+    }
+
+    /// Drops a room and the connections of its players and spectators.
+    fn close(&mut self, name: &str) {
+        // nothing left to give away, the spectators see the end of the game
+        self.flush(name, Duration::from_secs(0));
+
+        if let Some(room) = self.rooms.remove(name) {
+            let spectators = room.spectators.iter().map(|spectator| spectator.client);
+            for client in room
+                .seats
+                .iter()
+                .filter_map(|seat| seat.client)
+                .chain(spectators)
+            {
+                self.clients.remove(&client);
+            }
+
+            info!("Room {} closed", name);
+        }
+    }
+}
@@ -0,0 +1,245 @@
+use std::{fs, path::PathBuf};
+
+use bevy::{app::AppExit, prelude::*};
+
+use crate::{
+    button::ButtonElement, coin::CoinToss, history::History, notation::GameRecord,
+    record::PendingRecord, rules::Variant, ui::Status, variant::Variants, AppState,
+};
+
+/// Folder of the save slots, next to the executable's working directory.
+pub const SAVES_FOLDER: &str = "saves";
+const SAVE_SLOTS: usize = 5;
+const SLOT_NAME_LENGTH: usize = 24;
+
+pub struct SessionPlugin;
+
+impl Plugin for SessionPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<SaveSlots>()
+            .add_system_set(SystemSet::on_enter(AppState::Menu).with_system(scan_save_slots))
+            .add_system_set(
+                SystemSet::on_update(AppState::Menu)
+                    .with_system(save_slot_button_system)
+                    .with_system(slot_name_input_system),
+            )
+            .add_system_set(SystemSet::on_update(AppState::Game).with_system(autosave_system));
+    }
+}
+
+/// The game in progress is saved to the selected slot, and continued from it.
+#[derive(Default)]
+pub struct SaveSlots {
+    pub selected: usize,
+    /// Variant and date of the game saved in each slot, `None` for the empty ones.
+    pub summaries: Vec<Option<String>>,
+    /// Names the player gave the slots, saved with their games.
+    pub names: Vec<Option<String>>,
+    /// Name being typed for the selected slot.
+    pub naming: Option<String>,
+}
+
+impl SaveSlots {
+    pub fn path(slot: usize) -> PathBuf {
+        PathBuf::from(SAVES_FOLDER).join(format!("slot-{}.ttt", slot + 1))
+    }
+
+    pub fn name(&self) -> Option<&String> {
+        self.names.get(self.selected).and_then(Option::as_ref)
+    }
+
+    pub fn label(&self) -> String {
+        if let Some(ref naming) = self.naming {
+            return format!("Slot {}: {}_", self.selected + 1, naming);
+        }
+
+        let slot = match self.name() {
+            Some(name) => name.clone(),
+            None => format!("Slot {}", self.selected + 1),
+        };
+
+        match self.summaries.get(self.selected) {
+            Some(Some(summary)) => format!("{}: {}", slot, summary),
+            _ => format!("{}: empty", slot),
+        }
+    }
+}
+
+fn scan_save_slots(mut save_slots: ResMut<SaveSlots>) {
+    let records = (0..SAVE_SLOTS)
+        .map(|slot| {
+            let text = fs::read_to_string(SaveSlots::path(slot)).ok()?;
+
+            GameRecord::parse(&text).ok()
+        })
+        .collect::<Vec<_>>();
+
+    save_slots.summaries = records
+        .iter()
+        .map(|record| {
+            record
+                .as_ref()
+                .map(|record| format!("{}, {}", record.variant, record.date))
+        })
+        .collect();
+    // empty slots keep their name until a game is saved in them
+    save_slots.names = records
+        .into_iter()
+        .enumerate()
+        .map(|(slot, record)| match record {
+            Some(record) => record.slot,
+            None => save_slots.names.get(slot).cloned().flatten(),
+        })
+        .collect();
+    save_slots.naming = None;
+}
+
+fn save_slot_button_system(
+    interaction_query: Query<(&Interaction, &ButtonElement), (Changed<Interaction>, With<Button>)>,
+    variant_assets: Res<Assets<Variant>>,
+    mut variants: ResMut<Variants>,
+    mut save_slots: ResMut<SaveSlots>,
+    mut pending_record: ResMut<PendingRecord>,
+    mut status: ResMut<Status>,
+) {
+    for (interaction, button) in interaction_query.iter() {
+        if *interaction != Interaction::Clicked {
+            continue;
+        }
+
+        match button {
+            ButtonElement::SaveSlot => {
+                save_slots.selected = (save_slots.selected + 1) % SAVE_SLOTS;
+                save_slots.naming = None;
+            }
+            ButtonElement::NameSlot => {
+                save_slots.naming = Some(save_slots.name().cloned().unwrap_or_default())
+            }
+            ButtonElement::Continue => {
+                if !matches!(save_slots.summaries.get(save_slots.selected), Some(Some(_))) {
+                    status.0 = Some(format!("Slot {} is empty", save_slots.selected + 1));
+                    continue;
+                }
+
+                // started by the record plugin, like the records dropped on the menu
+                let path = SaveSlots::path(save_slots.selected);
+                if let Err(error) =
+                    pending_record.open(&path, &variant_assets, &mut variants, AppState::Game)
+                {
+                    status.0 = Some(error);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Typing goes to the name of the selected slot once Name is clicked, Enter keeps it and Escape
+/// leaves it as it was.
+fn slot_name_input_system(
+    mut received_characters: EventReader<ReceivedCharacter>,
+    mut keyboard_input: ResMut<Input<KeyCode>>,
+    mut save_slots: ResMut<SaveSlots>,
+    mut status: ResMut<Status>,
+) {
+    // read all along, what was typed before Name was clicked isn't part of the name
+    let typed = received_characters
+        .iter()
+        .map(|event| event.char)
+        .filter(|character| !character.is_control())
+        .collect::<Vec<_>>();
+
+    let naming = match save_slots.naming {
+        Some(ref mut naming) => naming,
+        None => return,
+    };
+
+    for character in typed {
+        if naming.chars().count() < SLOT_NAME_LENGTH {
+            naming.push(character);
+        }
+    }
+
+    if keyboard_input.just_pressed(KeyCode::Back) {
+        naming.pop();
+    }
+
+    // the keys are taken, so they don't also reach the position string
+    if keyboard_input.just_pressed(KeyCode::Escape) {
+        keyboard_input.reset(KeyCode::Escape);
+        save_slots.naming = None;
+        return;
+    }
+
+    if !keyboard_input.just_pressed(KeyCode::Return) {
+        return;
+    }
+    keyboard_input.reset(KeyCode::Return);
+
+    // an empty name goes back to the slot's number
+    let name = save_slots
+        .naming
+        .take()
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty());
+    let selected = save_slots.selected;
+    save_slots.names.resize(SAVE_SLOTS, None);
+    save_slots.names[selected] = name.clone();
+
+    // the game saved in the slot takes the name now, an empty slot with its first move
+    let path = SaveSlots::path(selected);
+    let mut record = match fs::read_to_string(&path)
+        .ok()
+        .and_then(|text| GameRecord::parse(&text).ok())
+    {
+        Some(record) => record,
+        None => return,
+    };
+    record.slot = name;
+    if let Err(error) = fs::write(&path, record.to_string()) {
+        status.0 = Some(format!("Couldn't name {}: {}", path.display(), error));
+    }
+}
+
+/// Saves the game after every move, undo and redo, and when the app exits, once it has a move.
+fn autosave_system(
+    mut app_exit_events: EventReader<AppExit>,
+    variant: Res<Variant>,
+    history: Res<History>,
+    coin_toss: Res<CoinToss>,
+    save_slots: Res<SaveSlots>,
+    mut status: ResMut<Status>,
+) {
+    let exiting = app_exit_events.iter().count() > 0;
+
+    // a move played this frame waits for the position it leads to
+    if (!history.is_changed() && !exiting) || history.positions.len() != history.moves.len() + 1 {
+        return;
+    }
+
+    // a new game leaves the game saved in the slot alone until its first move
+    if history.moves.is_empty() {
+        return;
+    }
+
+    // the undone moves are kept, so they can still be redone
+    let mut record = GameRecord::new(
+        &variant,
+        if variant.random_turns {
+            coin_toss.seed
+        } else {
+            0
+        },
+        &history.moves,
+        &history.positions,
+    );
+    record.ply = Some(history.cursor).filter(|&ply| ply != history.moves.len());
+    record.slot = save_slots.name().cloned();
+
+    let path = SaveSlots::path(save_slots.selected);
+    if let Err(error) =
+        fs::create_dir_all(SAVES_FOLDER).and_then(|_| fs::write(&path, record.to_string()))
+    {
+        status.0 = Some(format!("Couldn't save {}: {}", path.display(), error));
+    }
+}
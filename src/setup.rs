@@ -0,0 +1,187 @@
+use arboard::Clipboard;
+use bevy::prelude::*;
+
+use crate::{
+    coin::CoinToss,
+    history::History,
+    notation::{position_string, Setup},
+    rules::Variant,
+    script::RuleScript,
+    session::SaveSlots,
+    symbol::{BoardView, CurrentSymbol, Players, Symbols},
+    ui::{Status, TextElement},
+    variant::{ScriptSource, ScriptState, Variants},
+    AppState,
+};
+
+pub struct SetupPlugin;
+
+impl Plugin for SetupPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<PendingSetup>()
+            .add_system_set(
+                SystemSet::on_update(AppState::Menu)
+                    .with_system(setup_input_system)
+                    .with_system(start_setup_system),
+            )
+            .add_system_set(SystemSet::on_update(AppState::Game).with_system(copy_position_system));
+    }
+}
+
+/// Position entered in the menu, waiting for its variant and rule script to be ready.
+#[derive(Default)]
+pub struct PendingSetup(pub Option<Setup>);
+
+fn clipboard_text() -> Result<String, String> {
+    Clipboard::new()
+        .and_then(|mut clipboard| clipboard.get_text())
+        .map_err(|error| error.to_string())
+}
+
+/// Typing goes to the position string, Enter sets the board up from it.
+fn setup_input_system(
+    mut received_characters: EventReader<ReceivedCharacter>,
+    keyboard_input: Res<Input<KeyCode>>,
+    variant_assets: Res<Assets<Variant>>,
+    mut variants: ResMut<Variants>,
+    save_slots: Res<SaveSlots>,
+    mut pending_setup: ResMut<PendingSetup>,
+    mut status: ResMut<Status>,
+    mut text_query: Query<&mut TextElement>,
+) {
+    // the slot being named takes the typing then
+    if save_slots.naming.is_some() {
+        received_characters.iter().for_each(drop);
+        return;
+    }
+
+    let control =
+        keyboard_input.pressed(KeyCode::LControl) || keyboard_input.pressed(KeyCode::RControl);
+
+    for mut text_element in text_query.iter_mut() {
+        let text = match *text_element {
+            TextElement::Setup(ref mut text) => text,
+            _ => continue,
+        };
+
+        // shortcuts come as control characters too
+        for event in received_characters.iter() {
+            if !event.char.is_control() && !control {
+                text.push(event.char);
+            }
+        }
+
+        if keyboard_input.just_pressed(KeyCode::Back) {
+            text.pop();
+        }
+
+        if keyboard_input.just_pressed(KeyCode::Escape) {
+            text.clear();
+        }
+
+        if control && keyboard_input.just_pressed(KeyCode::V) {
+            match clipboard_text() {
+                Ok(pasted) => text.push_str(pasted.trim()),
+                Err(error) => status.0 = Some(format!("Couldn't paste: {}", error)),
+            }
+        }
+
+        if !keyboard_input.just_pressed(KeyCode::Return) || text.is_empty() {
+            continue;
+        }
+
+        let setup = match Setup::parse(text) {
+            Ok(setup) => setup,
+            Err(error) => {
+                status.0 = Some(format!("Invalid position: {}", error));
+                continue;
+            }
+        };
+
+        // the selected variant gets synced to the rules by the variant plugin
+        match variants
+            .loaded(&variant_assets)
+            .into_iter()
+            .find(|(_, variant)| variant.name == setup.variant)
+        {
+            Some((handle, _)) => {
+                variants.selected = handle;
+                pending_setup.0 = Some(setup);
+            }
+            None => status.0 = Some(format!("Unknown variant {}", setup.variant)),
+        }
+    }
+}
+
+fn start_setup_system(
+    asset_server: Res<AssetServer>,
+    variant: Res<Variant>,
+    variants: Res<Variants>,
+    script_sources: Res<Assets<ScriptSource>>,
+    mut pending_setup: ResMut<PendingSetup>,
+    mut rule_script: ResMut<RuleScript>,
+    mut status: ResMut<Status>,
+    mut coin_toss: ResMut<CoinToss>,
+    mut board_view: ResMut<BoardView>,
+    mut history: ResMut<History>,
+    mut state: ResMut<State<AppState>>,
+) {
+    let setup = match pending_setup.0 {
+        Some(ref setup) if setup.variant == variant.name => setup,
+        _ => return,
+    };
+
+    let source = match variants.script_state(&asset_server, &script_sources) {
+        ScriptState::Loading => return,
+        ScriptState::Failed => {
+            status.0 = Some(format!("Couldn't load the script of {}", variant.name));
+            pending_setup.0 = None;
+            return;
+        }
+        ScriptState::Ready(source) => source,
+    };
+
+    rule_script.load(source);
+
+    match setup.position(&variant, &mut rule_script) {
+        Ok(position) => {
+            *coin_toss = CoinToss::default();
+            *board_view = BoardView::default();
+
+            // the set up position is the start of the game, there is nothing to undo
+            *history = History::resume(Vec::new(), vec![position], 0);
+
+            state
+                .set(AppState::Game)
+                .expect("Couldn't enter the Game state");
+        }
+        Err(error) => status.0 = Some(format!("Invalid position: {}", error)),
+    }
+
+    pending_setup.0 = None;
+}
+
+/// Ctrl+C puts the position string of the board in the clipboard, and shows it.
+fn copy_position_system(
+    keyboard_input: Res<Input<KeyCode>>,
+    variant: Res<Variant>,
+    symbols: Res<Symbols>,
+    current_symbol: Res<CurrentSymbol>,
+    players: Res<Players>,
+    mut status: ResMut<Status>,
+) {
+    let control =
+        keyboard_input.pressed(KeyCode::LControl) || keyboard_input.pressed(KeyCode::RControl);
+    if !control || !keyboard_input.just_pressed(KeyCode::C) {
+        return;
+    }
+
+    let position = position_string(&variant, &symbols.0, current_symbol.0, &players.0);
+
+    status.0 = Some(
+        match Clipboard::new().and_then(|mut clipboard| clipboard.set_text(position.clone())) {
+            Ok(_) => format!("Copied {}", position),
+            Err(error) => format!("{} (couldn't copy it: {})", position, error),
+        },
+    );
+}
@@ -0,0 +1,151 @@
+//! Signatures of the game files exchanged by mail, so the moves can't be changed on the way.
+
+use std::env;
+
+use hmac::{Hmac, Mac, NewMac};
+use sha2::Sha256;
+
+use crate::notation::GameRecord;
+
+/// Environment variable holding the key both teams agreed on.
+pub const KEY_VARIABLE: &str = "TTT_KEY";
+
+/// The key files are signed with, from `KEY_VARIABLE`.
+pub fn key() -> Result<String, String> {
+    env::var(KEY_VARIABLE)
+        .ok()
+        .filter(|key| !key.is_empty())
+        .ok_or_else(|| format!("Set {} to the key agreed with the other team", KEY_VARIABLE))
+}
+
+/// HMAC-SHA256 of the record as written, without its signature.
+fn mac(record: &GameRecord, key: &str) -> Hmac<Sha256> {
+    let unsigned = GameRecord {
+        signature: None,
+        ..record.clone()
+    };
+    let mut mac = Hmac::<Sha256>::new_from_slice(key.as_bytes()).expect("Couldn't use the key");
+    mac.update(unsigned.to_string().as_bytes());
+
+    mac
+}
+
+/// The record with its signature.
+pub fn sign(record: &GameRecord, key: &str) -> GameRecord {
+    let signature = mac(record, key)
+        .finalize()
+        .into_bytes()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+
+    GameRecord {
+        signature: Some(signature),
+        ..record.clone()
+    }
+}
+
+pub fn verify(record: &GameRecord, key: &str) -> Result<(), String> {
+    let signature = record
+        .signature
+        .as_ref()
+        .ok_or_else(|| "the file isn't signed".to_string())?;
+
+    let bytes = (0..signature.len())
+        .step_by(2)
+        .map(|index| {
+            signature
+                .get(index..index + 2)
+                .and_then(|byte| u8::from_str_radix(byte, 16).ok())
+        })
+        .collect::<Option<Vec<_>>>()
+        .ok_or_else(|| "the signature is malformed".to_string())?;
+
+    mac(record, key).verify(&bytes).map_err(|_| {
+        "the signature doesn't match, the file was changed or signed with another key".to_string()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: &str = "agreed";
+
+    fn record() -> GameRecord {
+        GameRecord::parse(
+            "[Variant \"Classic\"]
+[Players \"X O\"]
+[Date \"2021-08-14\"]
+[Game \"abc\"]
+[Result \"*\"]
+
+1. X b2 2. O a1
+",
+        )
+        .expect("Couldn't parse the record")
+    }
+
+    /// The signed record with its signature changed by `change`.
+    fn tampered(change: impl FnOnce(&mut String)) -> GameRecord {
+        let mut signed = sign(&record(), KEY);
+        change(
+            signed
+                .signature
+                .as_mut()
+                .expect("Couldn't find the signature"),
+        );
+
+        signed
+    }
+
+    #[test]
+    fn verifies_what_it_signed() {
+        let signed = sign(&record(), KEY);
+        assert_eq!(verify(&signed, KEY), Ok(()));
+
+        // read back from the file
+        let read = GameRecord::parse(&signed.to_string()).expect("Couldn't parse the record");
+        assert_eq!(verify(&read, KEY), Ok(()));
+    }
+
+    #[test]
+    fn rejects_changed_files() {
+        let changed =
+            "the signature doesn't match, the file was changed or signed with another key";
+
+        assert_eq!(
+            verify(&record(), KEY),
+            Err("the file isn't signed".to_string())
+        );
+        assert_eq!(
+            verify(&sign(&record(), KEY), "another"),
+            Err(changed.to_string())
+        );
+
+        let mut moved = sign(&record(), KEY);
+        moved.moves[1].cell = "c3".to_string();
+        assert_eq!(verify(&moved, KEY), Err(changed.to_string()));
+
+        // a byte of the signature, the first one
+        let flipped = tampered(|signature| {
+            let byte = if signature.starts_with('0') { "1" } else { "0" };
+            signature.replace_range(..1, byte);
+        });
+        assert_eq!(verify(&flipped, KEY), Err(changed.to_string()));
+    }
+
+    #[test]
+    fn rejects_malformed_signatures() {
+        let malformed = Err("the signature is malformed".to_string());
+
+        // odd-length hex
+        let cut = tampered(|signature| {
+            signature.pop();
+        });
+        assert_eq!(verify(&cut, KEY), malformed);
+
+        let not_hex = tampered(|signature| signature.replace_range(..2, "zz"));
+        assert_eq!(verify(&not_hex, KEY), malformed);
+    }
+}
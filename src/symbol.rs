@@ -1,10 +1,20 @@
-use std::fmt;
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+};
 
 use bevy::prelude::*;
-use rand::random;
+use rand::{random, seq::SliceRandom, thread_rng};
 
-pub const SYMBOL_SIZE: f32 = 64.0;
-pub const SPACE_SIZE: f32 = SYMBOL_SIZE / 3.0;
+use crate::{
+    rules::{self, Cell, Standings, Symbol, Topology, Variant},
+    theme::square_offset,
+};
+// the layout is shared with the exports
+pub use crate::theme::{cell_position, symbol_color, HEX_RADIUS, SPACE_SIZE, SYMBOL_SIZE};
+
+/// Side, in cells, of the squares unbounded boards are drawn by.
+pub const CHUNK_SIZE: i32 = 8;
 
 pub struct SymbolPlugin;
 
@@ -12,14 +22,22 @@ impl Plugin for SymbolPlugin {
     fn build(&self, app: &mut AppBuilder) {
         app.init_resource::<SymbolsMaterials>()
             .init_resource::<Symbols>()
-            .init_resource::<CurrentSymbol>();
+            .init_resource::<CurrentSymbol>()
+            .init_resource::<BoardView>()
+            .init_resource::<Variant>()
+            .init_resource::<TurnOrder>()
+            .init_resource::<Players>()
+            .init_resource::<Standings>();
     }
 }
 
 pub struct SymbolsMaterials {
     x: Handle<ColorMaterial>,
     o: Handle<ColorMaterial>,
+    triangle: Handle<ColorMaterial>,
+    square: Handle<ColorMaterial>,
     empty: Handle<ColorMaterial>,
+    hex_empty: Handle<ColorMaterial>,
 }
 
 impl FromWorld for SymbolsMaterials {
@@ -30,74 +48,173 @@ impl FromWorld for SymbolsMaterials {
 
         asset_server.watch_for_changes().unwrap();
 
-        let (x, o, empty) = (
+        let (x, o, triangle, square, empty, hex_empty) = (
             asset_server.load("textures/symbols/x.png"),
             asset_server.load("textures/symbols/o.png"),
+            asset_server.load("textures/symbols/triangle.png"),
+            asset_server.load("textures/symbols/square.png"),
             asset_server.load("textures/symbols/empty.png"),
+            asset_server.load("textures/symbols/hex_empty.png"),
         );
 
         let mut materials = world
             .get_resource_mut::<Assets<ColorMaterial>>()
             .expect("Couldn't get ColorMaterial assets");
 
+        // player symbols are white and get tinted with their player's color
         Self {
-            x: materials.add(x.into()),
-            o: materials.add(o.into()),
+            x: materials.add(ColorMaterial::modulated_texture(x, symbol_color(Symbol::X))),
+            o: materials.add(ColorMaterial::modulated_texture(o, symbol_color(Symbol::O))),
+            triangle: materials.add(ColorMaterial::modulated_texture(
+                triangle,
+                symbol_color(Symbol::Triangle),
+            )),
+            square: materials.add(ColorMaterial::modulated_texture(
+                square,
+                symbol_color(Symbol::Square),
+            )),
             empty: materials.add(empty.into()),
+            hex_empty: materials.add(hex_empty.into()),
         }
     }
 }
 
-#[derive(Clone, Copy, PartialEq, Eq, Debug)]
-pub enum Symbol {
-    X,
-    O,
-    Empty,
-}
+pub struct SymbolCell(pub Cell);
 
-pub struct SymbolIndex(pub usize);
+#[derive(Clone, Copy, Debug)]
+pub struct CurrentSymbol(pub Symbol);
 
-impl Default for Symbol {
+impl Default for CurrentSymbol {
     fn default() -> Self {
-        Self::Empty
+        Self(if random() { Symbol::X } else { Symbol::O })
     }
 }
 
-impl fmt::Display for Symbol {
+impl fmt::Display for CurrentSymbol {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(
-            f,
-            "{}",
-            match self {
-                Self::X => "X",
-                Self::O => "O",
-                Self::Empty => "Empty",
-            }
-        )
+        write!(f, "{}", self.0)
     }
 }
 
-#[derive(Clone, Copy, Debug)]
-pub struct CurrentSymbol(pub Symbol);
+/// Turn order picked in the menu, `None` shuffles the players of every game.
+#[derive(Clone, Debug, Default)]
+pub struct TurnOrder(pub Option<Vec<Symbol>>);
 
-impl Default for CurrentSymbol {
-    fn default() -> Self {
-        Self(if random() { Symbol::X } else { Symbol::O })
+/// Players still in the game, in turn order.
+#[derive(Clone, Debug, Default)]
+pub struct Players(pub Vec<Symbol>);
+
+impl Players {
+    pub fn new(turn_order: &TurnOrder, variant: &Variant) -> Self {
+        let players = &Symbol::PLAYERS[..variant.players];
+
+        match turn_order.0 {
+            Some(ref order) if order.len() == players.len() => Self(
+                order
+                    .iter()
+                    .copied()
+                    .filter(|symbol| players.contains(symbol))
+                    .collect(),
+            ),
+            _ => {
+                let mut players = players.to_vec();
+                players.shuffle(&mut thread_rng());
+                Self(players)
+            }
+        }
+    }
+
+    pub fn next_after(&self, symbol: Symbol) -> Symbol {
+        rules::next_player(&self.0, symbol)
     }
 }
 
-impl fmt::Display for CurrentSymbol {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}", self.0)
+/// Played cells only, empty cells are left out.
+#[derive(Clone, Debug, Default)]
+pub struct Symbols(pub HashMap<Cell, Symbol>);
+
+/// What the player in front of the screen is allowed to see of the board.
+#[derive(Clone, Debug, Default)]
+pub struct BoardView {
+    /// `None` shows every symbol.
+    pub viewer: Option<Symbol>,
+    /// Opponent cells each player found out about by trying to play on them.
+    pub revealed: HashMap<Symbol, HashSet<Cell>>,
+    /// Chunks of an unbounded board around the camera, the only ones drawn.
+    pub chunks: HashSet<Cell>,
+}
+
+impl BoardView {
+    /// Hands the screen to `current_symbol` in blind games, shows everything otherwise.
+    pub fn follow(&mut self, variant: &Variant, current_symbol: &CurrentSymbol) {
+        self.viewer = if variant.blind && current_symbol.0 != Symbol::Empty {
+            Some(current_symbol.0)
+        } else {
+            None
+        };
+    }
+
+    pub fn shows(&self, cell: Cell, symbol: Symbol) -> bool {
+        match self.viewer {
+            Some(viewer) if viewer != symbol => self
+                .revealed
+                .get(&viewer)
+                .map_or(false, |cells| cells.contains(&cell)),
+            _ => true,
+        }
     }
 }
 
-#[derive(Clone, Copy, Debug)]
-pub struct Symbols(pub [Symbol; 9]);
+pub fn chunk_of((column, row): Cell) -> Cell {
+    (column.div_euclid(CHUNK_SIZE), row.div_euclid(CHUNK_SIZE))
+}
 
-impl Default for Symbols {
-    fn default() -> Self {
-        Self([Symbol::default(); 9])
+fn chunk_cells((x, y): Cell) -> impl Iterator<Item = Cell> {
+    (0..CHUNK_SIZE).flat_map(move |row| {
+        (0..CHUNK_SIZE).map(move |column| (x * CHUNK_SIZE + column, y * CHUNK_SIZE + row))
+    })
+}
+
+pub fn cell_at(topology: &Topology, position: Vec2) -> Option<Cell> {
+    let cell = match *topology {
+        Topology::Square { .. } | Topology::Infinite => {
+            let offset = square_offset(topology);
+            let step = SYMBOL_SIZE + SPACE_SIZE;
+            let cell = (
+                (position.x / step + offset.x).round() as i32,
+                (offset.y - position.y / step).round() as i32,
+            );
+
+            // the spacing between squares isn't part of any cell
+            let extents = Vec2::splat(SYMBOL_SIZE) / 2.0;
+            let distance = (position - cell_position(topology, cell)).abs();
+            if distance.x > extents.x || distance.y > extents.y {
+                return None;
+            }
+
+            cell
+        }
+        Topology::Hex { .. } => {
+            let q = (3f32.sqrt() / 3.0 * position.x + position.y / 3.0) / HEX_RADIUS;
+            let r = -2.0 / 3.0 * position.y / HEX_RADIUS;
+            let s = -q - r;
+
+            let (mut rq, mut rr, rs) = (q.round(), r.round(), s.round());
+            let (dq, dr, ds) = ((rq - q).abs(), (rr - r).abs(), (rs - s).abs());
+            if dq > dr && dq > ds {
+                rq = -rr - rs;
+            } else if dr > ds {
+                rr = -rq - rs;
+            }
+
+            (rq as i32, rr as i32)
+        }
+    };
+
+    if topology.contains(cell) {
+        Some(cell)
+    } else {
+        None
     }
 }
 
@@ -105,36 +222,47 @@ pub fn update_symbols(
     commands: &mut Commands,
     symbols_query: &Query<Entity, With<Symbol>>,
     symbols_materials: &Res<SymbolsMaterials>,
-    symbols: &ResMut<Symbols>,
+    symbols: &Symbols,
+    variant: &Variant,
+    board_view: &BoardView,
 ) {
     for entity in symbols_query.iter() {
         commands.entity(entity).despawn_recursive()
     }
 
-    let mut i = 0;
-    for row in -1..=1 {
-        for column in -1..=1 {
-            let symbol = symbols.0[i];
-            let current_material = match symbol {
-                Symbol::X => symbols_materials.x.clone(),
-                Symbol::O => symbols_materials.o.clone(),
-                Symbol::Empty => symbols_materials.empty.clone(),
-            };
-
-            (*commands)
-                .spawn_bundle(SpriteBundle {
-                    material: current_material,
-                    transform: Transform::from_translation(Vec3::new(
-                        row as f32 * (SYMBOL_SIZE + SPACE_SIZE),
-                        column as f32 * (SYMBOL_SIZE + SPACE_SIZE),
-                        0.0,
-                    )),
-                    ..Default::default()
-                })
-                .insert(symbol)
-                .insert(SymbolIndex(i));
-
-            i += 1;
-        }
+    let topology = variant.topology;
+    let cells = variant.cells().unwrap_or_else(|| {
+        board_view
+            .chunks
+            .iter()
+            .flat_map(|&chunk| chunk_cells(chunk))
+            .collect()
+    });
+
+    // blocked cells are left out of the board
+    for cell in cells.into_iter().filter(|&cell| variant.is_playable(cell)) {
+        let symbol = symbols
+            .0
+            .get(&cell)
+            .copied()
+            .filter(|&symbol| board_view.shows(cell, symbol))
+            .unwrap_or_default();
+        let current_material = match (symbol, topology) {
+            (Symbol::X, _) => symbols_materials.x.clone(),
+            (Symbol::O, _) => symbols_materials.o.clone(),
+            (Symbol::Triangle, _) => symbols_materials.triangle.clone(),
+            (Symbol::Square, _) => symbols_materials.square.clone(),
+            (Symbol::Empty, Topology::Hex { .. }) => symbols_materials.hex_empty.clone(),
+            (Symbol::Empty, _) => symbols_materials.empty.clone(),
+        };
+
+        (*commands)
+            .spawn_bundle(SpriteBundle {
+                material: current_material,
+                transform: Transform::from_translation(cell_position(&topology, cell).extend(0.0)),
+                ..Default::default()
+            })
+            .insert(symbol)
+            .insert(SymbolCell(cell));
     }
 }
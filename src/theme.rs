@@ -0,0 +1,110 @@
+use bevy::prelude::*;
+
+use crate::rules::{Cell, Symbol, Topology, Variant};
+
+pub const SYMBOL_SIZE: f32 = 64.0;
+pub const SPACE_SIZE: f32 = SYMBOL_SIZE / 3.0;
+pub const HEX_RADIUS: f32 = SYMBOL_SIZE / 2.0 + SPACE_SIZE / 4.0;
+
+/// Color the window gets cleared with, exports use it too.
+pub fn background_color() -> Color {
+    Color::rgb_u8(40, 44, 52)
+}
+
+pub fn button_color(interaction: Interaction) -> Color {
+    match interaction {
+        Interaction::None => Color::rgb_u8(190, 105, 177),
+        Interaction::Hovered => Color::rgb_u8(220, 124, 217),
+        Interaction::Clicked => Color::rgb_u8(120, 85, 136),
+    }
+}
+
+pub fn symbol_color(symbol: Symbol) -> Color {
+    match symbol {
+        Symbol::X | Symbol::Empty => Color::rgb_u8(81, 183, 194),
+        Symbol::O => Color::rgb_u8(229, 192, 123),
+        Symbol::Triangle => Color::rgb_u8(224, 108, 117),
+        Symbol::Square => Color::rgb_u8(152, 195, 121),
+    }
+}
+
+/// Square boards are centered on the origin, unbounded ones start from it.
+pub fn square_offset(topology: &Topology) -> Vec2 {
+    match *topology {
+        Topology::Square { width, height } => {
+            Vec2::new((width - 1) as f32, (height - 1) as f32) / 2.0
+        }
+        _ => Vec2::ZERO,
+    }
+}
+
+pub fn cell_position(topology: &Topology, (q, r): Cell) -> Vec2 {
+    match *topology {
+        Topology::Square { .. } | Topology::Infinite => {
+            let offset = square_offset(topology);
+
+            Vec2::new(q as f32 - offset.x, offset.y - r as f32) * (SYMBOL_SIZE + SPACE_SIZE)
+        }
+        Topology::Hex { .. } => Vec2::new(
+            HEX_RADIUS * 3f32.sqrt() * (q as f32 + r as f32 / 2.0),
+            -HEX_RADIUS * 1.5 * r as f32,
+        ),
+    }
+}
+
+/// Playable cells of an exported board, unbounded boards are drawn one cell around the `played` ones.
+pub fn drawn_cells(variant: &Variant, played: impl Iterator<Item = Cell>) -> Vec<Cell> {
+    let cells = variant.cells().unwrap_or_else(|| {
+        let played = played.chain(std::iter::once((0, 0))).collect::<Vec<_>>();
+        let (min_q, max_q) = (
+            played.iter().map(|cell| cell.0).min().unwrap_or_default() - 1,
+            played.iter().map(|cell| cell.0).max().unwrap_or_default() + 1,
+        );
+        let (min_r, max_r) = (
+            played.iter().map(|cell| cell.1).min().unwrap_or_default() - 1,
+            played.iter().map(|cell| cell.1).max().unwrap_or_default() + 1,
+        );
+
+        (min_r..=max_r)
+            .flat_map(|r| (min_q..=max_q).map(move |q| (q, r)))
+            .collect()
+    });
+
+    cells
+        .into_iter()
+        .filter(|&cell| variant.is_playable(cell))
+        .collect()
+}
+
+/// Lower left and upper right corners around `cells`, with some room on every side.
+pub fn board_bounds(topology: &Topology, cells: &[Cell]) -> (Vec2, Vec2) {
+    let extent = Vec2::splat(SYMBOL_SIZE / 2.0 + SPACE_SIZE);
+
+    let (min, max) = cells.iter().fold(
+        (Vec2::splat(f32::MAX), Vec2::splat(f32::MIN)),
+        |(min, max), &cell| {
+            let position = cell_position(topology, cell);
+
+            (min.min(position), max.max(position))
+        },
+    );
+
+    (min - extent, max + extent)
+}
+
+/// Ends of the stroke through a winning line, sticking out a bit past its first and last cells.
+pub fn strike_through(topology: &Topology, (first, last): (Cell, Cell)) -> (Vec2, Vec2) {
+    let (from, to) = (
+        cell_position(topology, first),
+        cell_position(topology, last),
+    );
+
+    // a line of one symbol has no direction to stick out along
+    let overhang = if from == to {
+        Vec2::ZERO
+    } else {
+        (to - from).normalize() * SYMBOL_SIZE / 3.0
+    };
+
+    (from - overhang, to + overhang)
+}
@@ -0,0 +1,277 @@
+//! Ways the messages of a connection get to the peer.
+
+#[cfg(target_arch = "wasm32")]
+use std::{
+    cell::RefCell,
+    collections::{HashMap, VecDeque},
+    rc::Rc,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+use std::{io, time::Duration};
+#[cfg(not(target_arch = "wasm32"))]
+use std::{
+    io::{BufRead, BufReader, Write},
+    net::TcpStream,
+};
+
+#[cfg(not(target_arch = "wasm32"))]
+use tungstenite::{Error, WebSocket};
+#[cfg(target_arch = "wasm32")]
+use wasm_bindgen::{closure::Closure, JsCast};
+#[cfg(target_arch = "wasm32")]
+use web_sys::{CloseEvent, MessageEvent};
+
+/// Time a transport waits for the peer before checking for messages to send.
+pub const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Carries the messages of a connection as JSON texts.
+pub trait Transport: Send {
+    /// Next text of the peer, `None` when nothing came within `POLL_INTERVAL`.
+    fn receive(&mut self) -> io::Result<Option<String>>;
+    fn send(&mut self, text: &str) -> io::Result<()>;
+}
+
+fn closed() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::ConnectionAborted,
+        "the connection was closed",
+    )
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn timed_out(error: &io::Error) -> bool {
+    matches!(
+        error.kind(),
+        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+    )
+}
+
+/// One message a line, what the game and the server speak natively.
+#[cfg(not(target_arch = "wasm32"))]
+pub struct TcpTransport {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+    /// Read so far of a line that didn't end yet.
+    line: Vec<u8>,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl TcpTransport {
+    pub fn new(stream: TcpStream) -> io::Result<Self> {
+        stream.set_read_timeout(Some(POLL_INTERVAL))?;
+
+        Ok(Self {
+            reader: BufReader::new(stream.try_clone()?),
+            writer: stream,
+            line: Vec::new(),
+        })
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Transport for TcpTransport {
+    fn receive(&mut self) -> io::Result<Option<String>> {
+        match self.reader.read_until(b'\n', &mut self.line) {
+            Ok(0) => Err(closed()),
+            Ok(_) if self.line.ends_with(b"\n") => {
+                let line = std::mem::take(&mut self.line);
+
+                String::from_utf8(line)
+                    .map(Some)
+                    .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))
+            }
+            // the end of the stream comes next
+            Ok(_) => Ok(None),
+            Err(error) if timed_out(&error) => Ok(None),
+            Err(error) => Err(error),
+        }
+    }
+
+    fn send(&mut self, text: &str) -> io::Result<()> {
+        self.writer.write_all(text.as_bytes())?;
+        self.writer.write_all(b"\n")
+    }
+}
+
+/// One message a text frame, for the clients that can't open plain sockets.
+#[cfg(not(target_arch = "wasm32"))]
+pub struct WebSocketTransport(WebSocket<TcpStream>);
+
+#[cfg(not(target_arch = "wasm32"))]
+impl WebSocketTransport {
+    /// Opens the WebSocket with the server at `url`, `ws://address/`.
+    pub fn connect(stream: TcpStream, url: &str) -> io::Result<Self> {
+        let (socket, _) = tungstenite::client(url, stream)
+            .map_err(|error| io::Error::new(io::ErrorKind::Other, error.to_string()))?;
+
+        Self::new(socket)
+    }
+
+    /// Answers the opening handshake of a client.
+    pub fn accept(stream: TcpStream) -> io::Result<Self> {
+        let socket = tungstenite::accept(stream)
+            .map_err(|error| io::Error::new(io::ErrorKind::Other, error.to_string()))?;
+
+        Self::new(socket)
+    }
+
+    fn new(socket: WebSocket<TcpStream>) -> io::Result<Self> {
+        // the handshake waits for the peer, the messages don't
+        socket.get_ref().set_read_timeout(Some(POLL_INTERVAL))?;
+
+        Ok(Self(socket))
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Transport for WebSocketTransport {
+    fn receive(&mut self) -> io::Result<Option<String>> {
+        match self.0.read_message() {
+            Ok(tungstenite::Message::Text(text)) => Ok(Some(text)),
+            Ok(tungstenite::Message::Close(_)) => Err(closed()),
+            // pings are answered by the socket itself
+            Ok(_) => Ok(None),
+            Err(Error::Io(error)) if timed_out(&error) => Ok(None),
+            Err(Error::Io(error)) => Err(error),
+            Err(Error::ConnectionClosed) | Err(Error::AlreadyClosed) => Err(closed()),
+            Err(error) => Err(io::Error::new(io::ErrorKind::InvalidData, error)),
+        }
+    }
+
+    fn send(&mut self, text: &str) -> io::Result<()> {
+        self.0
+            .write_message(tungstenite::Message::Text(text.to_string()))
+            .map_err(|error| match error {
+                Error::Io(error) => error,
+                error => io::Error::new(io::ErrorKind::Other, error),
+            })
+    }
+}
+
+/// A WebSocket of the browser, with what it received until the transport takes it.
+#[cfg(target_arch = "wasm32")]
+struct BrowserSocket {
+    socket: web_sys::WebSocket,
+    incoming: Rc<RefCell<VecDeque<io::Result<String>>>>,
+    /// Sent while the socket was opening, the browser refuses them until it's open.
+    unsent: Vec<String>,
+    // the socket calls them for as long as it lives
+    _on_message: Closure<dyn FnMut(MessageEvent)>,
+    _on_close: Closure<dyn FnMut(CloseEvent)>,
+}
+
+#[cfg(target_arch = "wasm32")]
+thread_local! {
+    /// Browser sockets can't leave their thread, the transports find theirs here.
+    static SOCKETS: RefCell<HashMap<usize, BrowserSocket>> = RefCell::new(HashMap::new());
+}
+
+#[cfg(target_arch = "wasm32")]
+static NEXT_SOCKET: AtomicUsize = AtomicUsize::new(0);
+
+#[cfg(target_arch = "wasm32")]
+fn browser_error(error: wasm_bindgen::JsValue) -> io::Error {
+    io::Error::new(io::ErrorKind::Other, format!("{:?}", error))
+}
+
+/// One message a text frame of the browser's WebSocket, for the game running in a web page.
+#[cfg(target_arch = "wasm32")]
+pub struct WebSocketTransport(usize);
+
+#[cfg(target_arch = "wasm32")]
+impl WebSocketTransport {
+    /// Opens the WebSocket with the server at `url`, `ws://address/`. The messages sent meanwhile
+    /// go once it's open.
+    pub fn connect(url: &str) -> io::Result<Self> {
+        let socket = web_sys::WebSocket::new(url).map_err(browser_error)?;
+        let incoming = Rc::new(RefCell::new(VecDeque::new()));
+
+        let received = incoming.clone();
+        let on_message = Closure::wrap(Box::new(move |event: MessageEvent| {
+            // the server only speaks text frames
+            if let Some(text) = event.data().as_string() {
+                received.borrow_mut().push_back(Ok(text));
+            }
+        }) as Box<dyn FnMut(MessageEvent)>);
+        socket.set_onmessage(Some(on_message.as_ref().unchecked_ref()));
+
+        let ended = incoming.clone();
+        let on_close = Closure::wrap(Box::new(move |_: CloseEvent| {
+            ended.borrow_mut().push_back(Err(closed()));
+        }) as Box<dyn FnMut(CloseEvent)>);
+        socket.set_onclose(Some(on_close.as_ref().unchecked_ref()));
+
+        let id = NEXT_SOCKET.fetch_add(1, Ordering::Relaxed);
+        SOCKETS.with(|sockets| {
+            sockets.borrow_mut().insert(
+                id,
+                BrowserSocket {
+                    socket,
+                    incoming,
+                    unsent: Vec::new(),
+                    _on_message: on_message,
+                    _on_close: on_close,
+                },
+            )
+        });
+
+        Ok(Self(id))
+    }
+
+    fn with_socket<T>(&self, f: impl FnOnce(&mut BrowserSocket) -> io::Result<T>) -> io::Result<T> {
+        SOCKETS.with(|sockets| match sockets.borrow_mut().get_mut(&self.0) {
+            Some(socket) => f(socket),
+            None => Err(closed()),
+        })
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+impl BrowserSocket {
+    /// Sends what waited for the socket to open.
+    fn flush(&mut self) -> io::Result<()> {
+        match self.socket.ready_state() {
+            web_sys::WebSocket::CONNECTING => Ok(()),
+            web_sys::WebSocket::OPEN => {
+                let socket = &self.socket;
+
+                self.unsent
+                    .drain(..)
+                    .try_for_each(|text| socket.send_with_str(&text))
+                    .map_err(browser_error)
+            }
+            _ => Err(closed()),
+        }
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+impl Transport for WebSocketTransport {
+    fn receive(&mut self) -> io::Result<Option<String>> {
+        self.with_socket(|socket| {
+            let received = socket.incoming.borrow_mut().pop_front();
+
+            match received {
+                Some(text) => text.map(Some),
+                None => socket.flush().map(|_| None),
+            }
+        })
+    }
+
+    fn send(&mut self, text: &str) -> io::Result<()> {
+        self.with_socket(|socket| {
+            socket.unsent.push(text.to_string());
+            socket.flush()
+        })
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+impl Drop for WebSocketTransport {
+    fn drop(&mut self) {
+        // what the browser was given goes out ahead of the closing frame
+        if let Some(socket) = SOCKETS.with(|sockets| sockets.borrow_mut().remove(&self.0)) {
+            let _ = socket.socket.close();
+        }
+    }
+}
@@ -2,11 +2,22 @@ use core::fmt;
 
 use crate::{
     button::{ButtonAssets, ButtonElement, SpawnButton},
-    symbol::{CurrentSymbol, Symbol, Symbols, SymbolsMaterials},
-    update_symbols, AppState, WinningEvent,
+    coin::CoinToss,
+    history::History,
+    net::Remote,
+    rules::{next_turn_order, Standings, Symbol, Variant},
+    script::RuleScript,
+    session::SaveSlots,
+    symbol::{
+        symbol_color, BoardView, CurrentSymbol, Players, Symbols, SymbolsMaterials, TurnOrder,
+    },
+    update_symbols, AppState,
 };
 use bevy::{app::AppExit, prelude::*};
 
+/// Width kept free for the panel on the right of the board.
+pub const PANEL_WIDTH: f32 = 140.0;
+
 #[derive(Debug)]
 
 pub struct UIPlugin;
@@ -14,43 +25,128 @@ pub struct UIPlugin;
 impl Plugin for UIPlugin {
     fn build(&self, app: &mut AppBuilder) {
         app.init_resource::<ButtonAssets>()
+            .init_resource::<Status>()
             .add_system_set(SystemSet::on_enter(AppState::Menu).with_system(setup_menu))
             .add_system_set(SystemSet::on_enter(AppState::Game).with_system(setup_game_menu))
             .add_system(button_color_system)
             .add_system(button_click_system)
             .add_system_set(SystemSet::on_exit(AppState::Menu).with_system(despawn_menu))
-            .add_system_set(SystemSet::on_update(AppState::Game).with_system(text_system))
-            .add_system_set(SystemSet::on_exit(AppState::Game).with_system(despawn_menu));
+            .add_system_set(
+                SystemSet::on_update(AppState::Menu)
+                    .with_system(menu_label_system)
+                    .with_system(text_system)
+                    .with_system(status_system),
+            )
+            .add_system_set(
+                SystemSet::on_update(AppState::Game)
+                    .with_system(text_system)
+                    .with_system(status_system),
+            )
+            .add_system_set(SystemSet::on_exit(AppState::Game).with_system(despawn_menu))
+            .add_system_set(
+                SystemSet::on_update(AppState::GameList)
+                    .with_system(text_system)
+                    .with_system(status_system),
+            )
+            .add_system_set(SystemSet::on_exit(AppState::GameList).with_system(despawn_menu))
+            .add_system_set(
+                SystemSet::on_update(AppState::Replay)
+                    .with_system(text_system)
+                    .with_system(status_system),
+            )
+            .add_system_set(SystemSet::on_exit(AppState::Replay).with_system(despawn_menu))
+            .add_system_set(
+                SystemSet::on_update(AppState::Lobby)
+                    .with_system(text_system)
+                    .with_system(status_system),
+            )
+            .add_system_set(SystemSet::on_exit(AppState::Lobby).with_system(despawn_menu))
+            .add_system_set(SystemSet::on_enter(AppState::HandOver).with_system(setup_hand_over))
+            .add_system_set(SystemSet::on_exit(AppState::HandOver).with_system(despawn_hand_over));
     }
 }
 pub enum TextElement {
-    CurrentSymbol(CurrentSymbol),
-    Winner(Option<WinningEvent>),
+    /// The current symbol, and the coin face shown while a coin toss is spinning.
+    CurrentSymbol(CurrentSymbol, Option<Symbol>),
+    Winner(Option<Standings>),
+    /// Last error of the variant's rule script, or else the last status message.
+    Status(Option<String>),
+    /// Position string typed or pasted in the menu.
+    Setup(String),
+    /// Address of the host typed in the lobby.
+    Address(String),
+    /// Spectators of a networked game.
+    Spectators(usize),
+    /// Chat message being typed, `None` until Enter is pressed.
+    ChatInput(Option<String>),
 }
 
 impl fmt::Display for TextElement {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Self::CurrentSymbol(symbol) => write!(f, "Current symbol is {}", symbol),
-            Self::Winner(symbol) => write!(
-                f,
-                "{}",
-                match symbol {
-                    Some(symbol) => match symbol {
-                        WinningEvent::X => "The winner is X!",
-                        WinningEvent::O => "The winner is O!",
-                        WinningEvent::Draw => "It's a draw!",
-                    },
-                    None => "",
-                }
-            ),
+            Self::CurrentSymbol(_, Some(face)) => write!(f, "Tossing the coin... {}", face),
+            Self::CurrentSymbol(symbol, None) => write!(f, "Current symbol is {}", symbol),
+            Self::Winner(Some(standings)) => write!(f, "{}", standings),
+            Self::Winner(None) | Self::Status(None) => Ok(()),
+            Self::Status(Some(message)) => write!(f, "{}", message),
+            Self::Setup(text) if text.is_empty() => write!(f, "Paste a position with Ctrl+V"),
+            Self::Setup(text) => write!(f, "{}", text),
+            Self::Address(text) if text.is_empty() => write!(f, "Host address"),
+            Self::Address(text) => write!(f, "{}", text),
+            Self::Spectators(0) => Ok(()),
+            Self::Spectators(count) => write!(f, "{} watching", count),
+            Self::ChatInput(None) => write!(f, "Enter to chat"),
+            Self::ChatInput(Some(text)) => write!(f, "> {}_", text),
         }
     }
 }
 
+/// Message about what just happened, cleared when leaving the menu or the game.
+#[derive(Default)]
+pub struct Status(pub Option<String>);
+
 pub enum NodeElement {
     Root,
     Text,
+    HandOver,
+    /// Holds the entries of the move list, rebuilt as the game goes.
+    MoveList,
+    /// Holds the entries of the game list.
+    GameList,
+    /// Holds the games found on the local network.
+    DiscoveredGames,
+    /// Covers the board while a player of a networked game is away.
+    Gap,
+    /// Holds the chat lines of a networked game, rebuilt as they come.
+    Chat,
+    /// Covers the board while the other player's offer waits for an answer.
+    Offer,
+}
+
+/// Over the board, right above the buttons.
+pub fn status_text(button_assets: &ButtonAssets) -> TextBundle {
+    TextBundle {
+        text: Text::with_section(
+            "",
+            TextStyle {
+                font: button_assets.font.clone(),
+                font_size: 20.0,
+                color: symbol_color(Symbol::O),
+            },
+            Default::default(),
+        ),
+        style: Style {
+            position_type: PositionType::Absolute,
+            position: Rect {
+                left: Val::Px(16.0),
+                bottom: Val::Px(96.0),
+                ..Default::default()
+            },
+            max_size: Size::new(Val::Px(568.0), Val::Undefined),
+            ..Default::default()
+        },
+        ..Default::default()
+    }
 }
 
 fn setup_menu(
@@ -64,7 +160,7 @@ fn setup_menu(
                 size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
                 justify_content: JustifyContent::Center,
                 align_items: AlignItems::Center,
-                flex_wrap: FlexWrap::WrapReverse,
+                flex_direction: FlexDirection::ColumnReverse,
                 ..Default::default()
             },
             material: materials.add(Color::NONE.into()),
@@ -72,8 +168,70 @@ fn setup_menu(
         })
         .insert(NodeElement::Root)
         .with_children(|root| {
-            root.spawn_button(&button_assets, ButtonElement::StartGame)
-                .spawn_button(&button_assets, ButtonElement::QuitGame);
+            root.spawn_button(&button_assets, ButtonElement::Variant)
+                .spawn_button(&button_assets, ButtonElement::TurnOrder);
+
+            root.spawn_bundle(NodeBundle {
+                material: materials.add(Color::NONE.into()),
+                ..Default::default()
+            })
+            .with_children(|parent| {
+                parent
+                    .spawn_button(&button_assets, ButtonElement::SaveSlot)
+                    .spawn_button(&button_assets, ButtonElement::NameSlot);
+            });
+
+            root.spawn_bundle(NodeBundle {
+                material: materials.add(Color::NONE.into()),
+                ..Default::default()
+            })
+            .with_children(|parent| {
+                parent
+                    .spawn_button(&button_assets, ButtonElement::StartGame)
+                    .spawn_button(&button_assets, ButtonElement::Continue);
+            });
+
+            root.spawn_bundle(NodeBundle {
+                material: materials.add(Color::NONE.into()),
+                ..Default::default()
+            })
+            .with_children(|parent| {
+                parent
+                    .spawn_button(&button_assets, ButtonElement::Host)
+                    .spawn_button(&button_assets, ButtonElement::Join)
+                    .spawn_button(&button_assets, ButtonElement::PlayByFile);
+            });
+
+            root.spawn_bundle(NodeBundle {
+                material: materials.add(Color::NONE.into()),
+                ..Default::default()
+            })
+            .with_children(|parent| {
+                parent
+                    .spawn_button(&button_assets, ButtonElement::GameList)
+                    .spawn_button(&button_assets, ButtonElement::QuitGame);
+            });
+
+            root.spawn_bundle(TextBundle {
+                text: Text::with_section(
+                    "",
+                    TextStyle {
+                        font: button_assets.font.clone(),
+                        font_size: 20.0,
+                        color: Color::rgb_u8(171, 178, 191),
+                    },
+                    Default::default(),
+                ),
+                style: Style {
+                    margin: Rect::all(Val::Px(16.0)),
+                    ..Default::default()
+                },
+                ..Default::default()
+            })
+            .insert(TextElement::Setup(String::new()));
+
+            root.spawn_bundle(status_text(&button_assets))
+                .insert(TextElement::Status(None));
         });
 }
 
@@ -82,6 +240,7 @@ fn setup_game_menu(
     mut materials: ResMut<Assets<ColorMaterial>>,
     current_symbol: Option<Res<CurrentSymbol>>,
     button_assets: Res<ButtonAssets>,
+    remote: Res<Remote>,
 ) {
     let none = materials.add(Color::NONE.into());
 
@@ -128,7 +287,7 @@ fn setup_game_menu(
                         },
                         ..Default::default()
                     })
-                    .insert(TextElement::CurrentSymbol(*current_symbol.unwrap()));
+                    .insert(TextElement::CurrentSymbol(*current_symbol.unwrap(), None));
                 parent
                     .spawn_bundle(TextBundle {
                         text: Text::with_section(
@@ -149,6 +308,76 @@ fn setup_game_menu(
                     .insert(TextElement::Winner(None));
             });
 
+            root.spawn_bundle(NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    position: Rect {
+                        right: Val::Px(0.0),
+                        top: Val::Px(96.0),
+                        ..Default::default()
+                    },
+                    size: Size::new(Val::Px(PANEL_WIDTH), Val::Auto),
+                    flex_direction: FlexDirection::ColumnReverse,
+                    align_items: AlignItems::Center,
+                    ..Default::default()
+                },
+                material: none.clone(),
+                ..Default::default()
+            })
+            .with_children(|panel| {
+                // the other player of a networked game gets asked first, spectators just watch
+                match remote.0 {
+                    None => {
+                        panel
+                            .spawn_button(&button_assets, ButtonElement::Undo)
+                            .spawn_button(&button_assets, ButtonElement::Redo);
+                    }
+                    Some(ref remote_game) if remote_game.symbol != Symbol::Empty => {
+                        panel
+                            .spawn_button(&button_assets, ButtonElement::Takeback)
+                            .spawn_button(&button_assets, ButtonElement::OfferDraw);
+                    }
+                    Some(_) => {}
+                }
+
+                if remote.0.is_some() {
+                    panel
+                        .spawn_bundle(TextBundle {
+                            text: Text::with_section(
+                                "",
+                                TextStyle {
+                                    font: button_assets.font.clone(),
+                                    font_size: 20.0,
+                                    color: Color::rgb_u8(171, 178, 191),
+                                },
+                                Default::default(),
+                            ),
+                            style: Style {
+                                margin: Rect::all(Val::Px(8.0)),
+                                ..Default::default()
+                            },
+                            ..Default::default()
+                        })
+                        .insert(TextElement::Spectators(0));
+                }
+
+                panel
+                    .spawn_bundle(NodeBundle {
+                        style: Style {
+                            size: Size::new(Val::Percent(100.0), Val::Auto),
+                            flex_direction: FlexDirection::ColumnReverse,
+                            align_items: AlignItems::FlexStart,
+                            ..Default::default()
+                        },
+                        material: none.clone(),
+                        ..Default::default()
+                    })
+                    .insert(NodeElement::MoveList);
+            });
+
+            root.spawn_bundle(status_text(&button_assets))
+                .insert(TextElement::Status(None));
+
             root.spawn_bundle(NodeBundle {
                 style: Style {
                     size: Size::new(Val::Percent(100.0), Default::default()),
@@ -160,17 +389,118 @@ fn setup_game_menu(
                 ..Default::default()
             })
             .with_children(|parent| {
+                match remote.0 {
+                    None => {
+                        parent.spawn_button(&button_assets, ButtonElement::RestartGame);
+                    }
+                    Some(ref remote_game) if remote_game.symbol != Symbol::Empty => {
+                        parent.spawn_button(&button_assets, ButtonElement::Rematch);
+                    }
+                    Some(_) => {}
+                }
                 parent
-                    .spawn_button(&button_assets, ButtonElement::RestartGame)
+                    .spawn_button(&button_assets, ButtonElement::BackToMenu)
                     .spawn_button(&button_assets, ButtonElement::QuitGame);
             });
         });
 }
 
-fn despawn_menu(mut commands: Commands, query: Query<Entity, With<NodeElement>>) {
+fn setup_hand_over(
+    mut commands: Commands,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    clear_color: Res<ClearColor>,
+    current_symbol: Res<CurrentSymbol>,
+    button_assets: Res<ButtonAssets>,
+) {
+    // opaque, so the next player doesn't get a look at the previous one's board
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                flex_direction: FlexDirection::ColumnReverse,
+                ..Default::default()
+            },
+            material: materials.add(clear_color.0.into()),
+            ..Default::default()
+        })
+        .insert(NodeElement::HandOver)
+        .with_children(|root| {
+            for line in [
+                format!("Hand over to {}", *current_symbol),
+                "Click when ready".to_string(),
+            ]
+            .iter()
+            {
+                root.spawn_bundle(TextBundle {
+                    text: Text::with_section(
+                        line.as_str(),
+                        TextStyle {
+                            font: button_assets.font.clone(),
+                            font_size: 40.0,
+                            color: Color::WHITE,
+                        },
+                        Default::default(),
+                    ),
+                    style: Style {
+                        margin: Rect::all(Val::Px(16.0)),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                });
+            }
+        });
+}
+
+fn despawn_hand_over(mut commands: Commands, query: Query<(Entity, &NodeElement)>) {
+    for (entity, node_element) in query.iter() {
+        if let NodeElement::HandOver = node_element {
+            commands.entity(entity).despawn_recursive()
+        }
+    }
+}
+
+fn despawn_menu(
+    mut commands: Commands,
+    mut status: ResMut<Status>,
+    query: Query<Entity, With<NodeElement>>,
+) {
     for entity in query.iter() {
         commands.entity(entity).despawn_recursive()
     }
+
+    status.0 = None;
+}
+
+fn menu_label_system(
+    variant: Res<Variant>,
+    turn_order: Res<TurnOrder>,
+    save_slots: Res<SaveSlots>,
+    button_query: Query<(&ButtonElement, &Children)>,
+    mut text_query: Query<&mut Text>,
+) {
+    for (button, children) in button_query.iter() {
+        let label = match button {
+            ButtonElement::Variant => format!("Variant: {}", *variant),
+            ButtonElement::TurnOrder => match turn_order.0 {
+                Some(ref order) => {
+                    let order = order.iter().map(Symbol::short_name).collect::<Vec<_>>();
+
+                    format!("Order: {}", order.join(" "))
+                }
+                None => "Order: random".to_string(),
+            },
+            ButtonElement::SaveSlot => save_slots.label(),
+            _ => continue,
+        };
+
+        for child in children.iter() {
+            if let Ok(mut text) = text_query.get_mut(*child) {
+                text.sections[0].value = label.clone();
+            }
+        }
+    }
 }
 
 fn text_system(mut query: Query<(&mut Text, &TextElement)>) {
@@ -179,6 +509,22 @@ fn text_system(mut query: Query<(&mut Text, &TextElement)>) {
     }
 }
 
+fn status_system(
+    rule_script: Res<RuleScript>,
+    status: Res<Status>,
+    mut query: Query<&mut TextElement>,
+) {
+    let message = rule_script.error.clone().or_else(|| status.0.clone());
+
+    for mut text_element in query.iter_mut() {
+        if let TextElement::Status(ref mut shown) = *text_element {
+            if *shown != message {
+                *shown = message.clone();
+            }
+        }
+    }
+}
+
 pub fn update_texts(
     text_query: &mut Query<(Entity, &mut TextElement)>,
     current_symbol: &ResMut<CurrentSymbol>,
@@ -186,12 +532,20 @@ pub fn update_texts(
 ) {
     for (_, mut text_element) in text_query.iter_mut() {
         match *text_element {
-            TextElement::CurrentSymbol(ref mut symbol) => *symbol = **current_symbol,
+            TextElement::CurrentSymbol(ref mut symbol, ref mut face) => {
+                *symbol = **current_symbol;
+                *face = None;
+            }
             TextElement::Winner(ref mut winner) => {
                 if reset {
                     *winner = None
                 }
             }
+            TextElement::Status(_)
+            | TextElement::Setup(_)
+            | TextElement::Address(_)
+            | TextElement::Spectators(_)
+            | TextElement::ChatInput(_) => {}
         };
     }
 }
@@ -222,31 +576,101 @@ fn button_click_system(
     symbols_materials: Res<SymbolsMaterials>,
     mut current_symbol: Option<ResMut<CurrentSymbol>>,
     mut symbols: ResMut<Symbols>,
+    current_variant: Res<Variant>,
+    mut turn_order: ResMut<TurnOrder>,
+    mut players: ResMut<Players>,
+    mut standings: ResMut<Standings>,
+    mut coin_toss: ResMut<CoinToss>,
+    mut board_view: ResMut<BoardView>,
+    mut history: ResMut<History>,
 ) {
     for (interaction, button) in interaction_query.iter() {
         if *interaction == Interaction::Clicked {
             match button {
-                ButtonElement::StartGame => state
-                    .set(AppState::Game)
-                    .expect("Couldn't enter the Game state"),
+                ButtonElement::StartGame => {
+                    *players = Players::new(&turn_order, &current_variant);
+                    *current_symbol
+                        .as_deref_mut()
+                        .expect("can't get the current symbol to reset it") =
+                        CurrentSymbol(players.0[0]);
+                    *standings = Standings::default();
+                    *symbols = Symbols::default();
+                    *coin_toss = CoinToss::default();
+                    *board_view = BoardView::default();
+                    *history = History::start(&current_variant, &players.0);
+
+                    state
+                        .set(AppState::Game)
+                        .expect("Couldn't enter the Game state")
+                }
+                // picked by the variant plugin, from the loaded variant files
+                ButtonElement::Variant => {}
+                // handled by the session plugin
+                ButtonElement::Continue | ButtonElement::SaveSlot | ButtonElement::NameSlot => {}
+                ButtonElement::TurnOrder => {
+                    let player_symbols = &Symbol::PLAYERS[..current_variant.players];
+
+                    turn_order.0 = match turn_order.0 {
+                        None => Some(player_symbols.to_vec()),
+                        Some(ref order) => {
+                            Some(next_turn_order(order)).filter(|order| order != player_symbols)
+                        }
+                    };
+                }
                 ButtonElement::RestartGame => {
+                    *players = Players::new(&turn_order, &current_variant);
                     *current_symbol
                         .as_deref_mut()
                         .expect("can't get the current symbol to reset it") =
-                        CurrentSymbol::default();
+                        CurrentSymbol(players.0[0]);
+                    *standings = Standings::default();
 
                     *symbols = Symbols::default();
+                    *coin_toss = CoinToss::default();
+                    *history = History::start(&current_variant, &players.0);
+
+                    let current_symbol = current_symbol
+                        .as_ref()
+                        .expect("can't get the current symbol to reset its text");
+
+                    *board_view = BoardView::default();
+                    board_view.follow(&current_variant, current_symbol);
 
-                    update_texts(
-                        &mut text_query,
-                        current_symbol
-                            .as_ref()
-                            .expect("can't get the current symbol to reset its text"),
-                        true,
+                    update_texts(&mut text_query, current_symbol, true);
+                    update_symbols(
+                        &mut commands,
+                        &texts_query,
+                        &symbols_materials,
+                        &symbols,
+                        &current_variant,
+                        &board_view,
                     );
-                    update_symbols(&mut commands, &texts_query, &symbols_materials, &symbols);
                 }
+                // leaves the states pushed over the game too
+                ButtonElement::BackToMenu => state
+                    .replace(AppState::Menu)
+                    .expect("Couldn't enter the Menu state"),
                 ButtonElement::QuitGame => app_exit_events.send(AppExit),
+                ButtonElement::GameList => state
+                    .set(AppState::GameList)
+                    .expect("Couldn't enter the GameList state"),
+                // handled by the history plugin
+                ButtonElement::Undo | ButtonElement::Redo => {}
+                // handled by the net plugin
+                ButtonElement::Host | ButtonElement::Join | ButtonElement::Watch => {}
+                // handled by the offer plugin
+                ButtonElement::Rematch
+                | ButtonElement::Takeback
+                | ButtonElement::OfferDraw
+                | ButtonElement::Accept
+                | ButtonElement::Decline => {}
+                // handled by the correspondence plugin
+                ButtonElement::PlayByFile => {}
+                // handled by the replay plugin
+                ButtonElement::StepBack
+                | ButtonElement::PlayPause
+                | ButtonElement::StepForward
+                | ButtonElement::ReplaySpeed => {}
             }
         }
     }
@@ -0,0 +1,258 @@
+use bevy::{
+    asset::{AssetLoader, BoxedFuture, LoadContext, LoadState, LoadedAsset},
+    prelude::*,
+    reflect::{TypeUuid, Uuid},
+};
+
+use crate::{
+    button::ButtonElement, rules::Variant, script::RuleScript, symbol::TurnOrder, AppState,
+};
+
+pub struct VariantPlugin;
+
+impl Plugin for VariantPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_asset::<Variant>()
+            .add_asset::<ScriptSource>()
+            .init_asset_loader::<VariantLoader>()
+            .init_asset_loader::<ScriptLoader>()
+            .init_resource::<Variants>()
+            .init_resource::<RuleScript>()
+            .add_system_set(SystemSet::on_enter(AppState::Menu).with_system(load_variants))
+            .add_system_set(
+                SystemSet::on_update(AppState::Menu)
+                    .with_system(variant_button_system)
+                    .with_system(selected_variant_system),
+            )
+            // records opened from the game list pick their own variant
+            .add_system_set(
+                SystemSet::on_update(AppState::GameList).with_system(selected_variant_system),
+            )
+            .add_system_set(
+                SystemSet::on_update(AppState::Lobby).with_system(selected_variant_system),
+            )
+            .add_system_set(SystemSet::on_enter(AppState::Game).with_system(load_rule_script))
+            .add_system_set(SystemSet::on_exit(AppState::Game).with_system(unload_rule_script))
+            .add_system_set(SystemSet::on_exit(AppState::Replay).with_system(unload_rule_script))
+            .add_system_set(
+                SystemSet::on_update(AppState::Game).with_system(rule_script_reload_system),
+            );
+    }
+}
+
+#[derive(Default)]
+pub struct VariantLoader;
+
+impl AssetLoader for VariantLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
+        Box::pin(async move {
+            let variant = ron::de::from_bytes::<Variant>(bytes)?;
+            variant.validate().map_err(anyhow::Error::msg)?;
+
+            load_context.set_default_asset(LoadedAsset::new(variant));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["ron"]
+    }
+}
+
+/// Source of a variant's rule script, compiled when a game starts.
+pub struct ScriptSource(pub String);
+
+impl TypeUuid for ScriptSource {
+    const TYPE_UUID: Uuid = Uuid::from_u128(0x2b8e_71c5_0d3f_4a96_b1e4_c827_5a0f_9d63);
+}
+
+#[derive(Default)]
+pub struct ScriptLoader;
+
+impl AssetLoader for ScriptLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
+        Box::pin(async move {
+            let source = String::from_utf8(bytes.to_vec())?;
+
+            load_context.set_default_asset(LoadedAsset::new(ScriptSource(source)));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["rhai"]
+    }
+}
+
+/// Variant files found in `assets/variants`, and the one picked in the menu.
+pub struct Variants {
+    pub handles: Vec<Handle<Variant>>,
+    pub selected: Handle<Variant>,
+    /// Rule script of the selected variant.
+    pub script: Option<Handle<ScriptSource>>,
+}
+
+impl FromWorld for Variants {
+    fn from_world(world: &mut World) -> Self {
+        let asset_server = world
+            .get_resource::<AssetServer>()
+            .expect("Couldn't get AssetServer");
+
+        Self {
+            handles: Vec::new(),
+            selected: asset_server.load("variants/classic.ron"),
+            script: None,
+        }
+    }
+}
+
+impl Variants {
+    /// Loaded variants sorted by name, files that failed to load are left out.
+    pub fn loaded<'a>(&self, assets: &'a Assets<Variant>) -> Vec<(Handle<Variant>, &'a Variant)> {
+        let mut loaded = self
+            .handles
+            .iter()
+            .filter_map(|handle| Some((handle.clone(), assets.get(handle)?)))
+            .collect::<Vec<_>>();
+        loaded.sort_by(|(_, a), (_, b)| a.name.cmp(&b.name));
+
+        loaded
+    }
+
+    pub fn script_state<'a>(
+        &self,
+        asset_server: &AssetServer,
+        script_sources: &'a Assets<ScriptSource>,
+    ) -> ScriptState<'a> {
+        let handle = match self.script {
+            Some(ref handle) => handle,
+            None => return ScriptState::Ready(None),
+        };
+
+        match script_sources.get(handle) {
+            Some(source) => ScriptState::Ready(Some(&source.0)),
+            None if asset_server.get_load_state(handle) == LoadState::Failed => ScriptState::Failed,
+            None => ScriptState::Loading,
+        }
+    }
+}
+
+/// Loading state of the selected variant's rule script.
+pub enum ScriptState<'a> {
+    Loading,
+    Failed,
+    /// Source of the script, `None` for variants without one.
+    Ready(Option<&'a str>),
+}
+
+/// Scans the folder again every time the menu opens, so new files show up without a restart.
+fn load_variants(asset_server: Res<AssetServer>, mut variants: ResMut<Variants>) {
+    variants.handles = asset_server
+        .load_folder("variants")
+        .expect("Couldn't load the variants folder")
+        .into_iter()
+        .map(HandleUntyped::typed)
+        .collect();
+}
+
+fn variant_button_system(
+    interaction_query: Query<(&Interaction, &ButtonElement), (Changed<Interaction>, With<Button>)>,
+    variant_assets: Res<Assets<Variant>>,
+    mut variants: ResMut<Variants>,
+) {
+    for (interaction, button) in interaction_query.iter() {
+        if *interaction == Interaction::Clicked && matches!(button, ButtonElement::Variant) {
+            let loaded = variants.loaded(&variant_assets);
+            let index = loaded
+                .iter()
+                .position(|(handle, _)| *handle == variants.selected)
+                .map_or(0, |index| index + 1);
+
+            if let Some((handle, _)) = loaded.get(index % loaded.len().max(1)) {
+                variants.selected = handle.clone();
+            }
+        }
+    }
+}
+
+/// Keeps the rules in sync with the selected file, games in progress keep the rules they started with.
+fn selected_variant_system(
+    asset_server: Res<AssetServer>,
+    mut variants: ResMut<Variants>,
+    variant_assets: Res<Assets<Variant>>,
+    mut variant: ResMut<Variant>,
+    mut turn_order: ResMut<TurnOrder>,
+) {
+    if let Some(selected) = variant_assets.get(&variants.selected) {
+        if *selected != *variant {
+            *variant = selected.clone();
+            variants.script = variant
+                .script
+                .as_ref()
+                .map(|path| asset_server.load(path.as_str()));
+
+            // a turn order only makes sense for the player count it was picked for
+            if turn_order.0.as_ref().map(Vec::len) != Some(variant.players) {
+                turn_order.0 = None;
+            }
+        }
+    }
+}
+
+fn load_rule_script(
+    asset_server: Res<AssetServer>,
+    variants: Res<Variants>,
+    script_sources: Res<Assets<ScriptSource>>,
+    variant: Res<Variant>,
+    mut rule_script: ResMut<RuleScript>,
+) {
+    let handle = match variants.script {
+        Some(ref handle) => handle,
+        None => return rule_script.load(None),
+    };
+
+    match script_sources.get(handle) {
+        Some(source) => rule_script.load(Some(&source.0)),
+        None => {
+            rule_script.load(None);
+
+            // still loading scripts get compiled by the reload system once they're there
+            if asset_server.get_load_state(handle) == LoadState::Failed {
+                rule_script.error = Some(format!(
+                    "Couldn't load the script {}",
+                    variant.script.as_deref().unwrap_or_default()
+                ));
+            }
+        }
+    }
+}
+
+fn unload_rule_script(mut rule_script: ResMut<RuleScript>) {
+    rule_script.load(None);
+}
+
+/// Edited scripts apply to the game in progress.
+fn rule_script_reload_system(
+    mut script_events: EventReader<AssetEvent<ScriptSource>>,
+    variants: Res<Variants>,
+    script_sources: Res<Assets<ScriptSource>>,
+    mut rule_script: ResMut<RuleScript>,
+) {
+    for event in script_events.iter() {
+        if let AssetEvent::Created { handle } | AssetEvent::Modified { handle } = event {
+            if Some(handle) == variants.script.as_ref() {
+                if let Some(source) = script_sources.get(handle) {
+                    rule_script.load(Some(&source.0));
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,368 @@
+//! Players of a server room losing the connection and resuming the game.
+
+use std::{
+    net::{TcpListener, TcpStream},
+    thread,
+    time::{Duration, Instant},
+};
+
+use bevy_tic_tac_toe::{
+    notation::GameRecord,
+    protocol::{Connection, Message, GRACE_SECONDS, PROTOCOL_VERSION},
+    rules::{Cell, Symbol},
+    server::Server,
+    transport::POLL_INTERVAL,
+};
+
+const ROOM: &str = "tests";
+
+/// Address of a server updated on a thread of its own until the tests end, its spectators see
+/// the game `delay` late.
+fn serve(delay: Duration) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").expect("Couldn't listen");
+    listener
+        .set_nonblocking(true)
+        .expect("Couldn't make the listener non-blocking");
+    let address = listener
+        .local_addr()
+        .expect("Couldn't find the address")
+        .to_string();
+
+    thread::spawn(move || {
+        let mut server = Server::new(delay);
+
+        loop {
+            server.update(&listener);
+            thread::sleep(POLL_INTERVAL);
+        }
+    });
+
+    address
+}
+
+/// A client who said hello as `name`.
+fn connect(address: &str, name: &str) -> Connection {
+    let stream = TcpStream::connect(address).expect("Couldn't connect");
+    let connection = Connection::new(stream).expect("Couldn't open the connection");
+
+    send(
+        &connection,
+        Message::Hello {
+            version: PROTOCOL_VERSION,
+            name: name.to_string(),
+        },
+    );
+
+    connection
+}
+
+fn send(connection: &Connection, message: Message) {
+    connection.send(&message).expect("Couldn't send");
+}
+
+/// What the server sends next, waiting a second at most.
+fn next(connection: &Connection) -> Message {
+    let deadline = Instant::now() + Duration::from_secs(1);
+
+    loop {
+        match connection.receive().expect("Couldn't receive") {
+            Some(message) => return message,
+            None if Instant::now() < deadline => thread::sleep(POLL_INTERVAL),
+            None => panic!("Nothing came"),
+        }
+    }
+}
+
+fn play(connection: &Connection, ply: usize, cell: Cell) {
+    send(connection, Message::Move { ply, cell });
+}
+
+/// Checks the move reached every one of the `players`.
+fn played(players: &[&Connection], ply: usize, cell: Cell) {
+    for player in players {
+        assert_eq!(next(player), Message::Move { ply, cell });
+    }
+}
+
+fn token(message: Message) -> String {
+    match message {
+        Message::Welcome {
+            token: Some(token), ..
+        } => token,
+        message => panic!("Expected a welcome, got {:?}", message),
+    }
+}
+
+/// Ann and Bob in a Classic game, with the tokens of their seats.
+fn start(address: &str) -> ((Connection, String), (Connection, String)) {
+    let join = Message::JoinRoom {
+        room: ROOM.to_string(),
+        variant: "Classic".to_string(),
+    };
+
+    let ann = connect(address, "Ann");
+    send(&ann, join.clone());
+    assert_eq!(
+        next(&ann),
+        Message::Waiting {
+            room: ROOM.to_string(),
+            players: 1,
+            seats: 2,
+        }
+    );
+
+    let bob = connect(address, "Bob");
+    send(&bob, join);
+    let (ann_token, bob_token) = (token(next(&ann)), token(next(&bob)));
+
+    ((ann, ann_token), (bob, bob_token))
+}
+
+/// Starts a game where Ann plays the first move and Bob loses the connection.
+fn drop_bob(address: &str) -> (Connection, String) {
+    let ((ann, _), (bob, bob_token)) = start(address);
+
+    play(&ann, 0, (1, 1));
+    played(&[&ann, &bob], 0, (1, 1));
+
+    drop(bob);
+    assert_eq!(
+        next(&ann),
+        Message::Away {
+            name: "Bob".to_string(),
+            seconds: GRACE_SECONDS,
+        }
+    );
+
+    (ann, bob_token)
+}
+
+/// Bob back in the game Ann started.
+fn resume(address: &str, ann: &Connection, token: String) -> Connection {
+    let bob = connect(address, "Bob");
+    send(
+        &bob,
+        Message::Resume {
+            room: ROOM.to_string(),
+            token: token.clone(),
+        },
+    );
+
+    match next(&bob) {
+        Message::Welcome {
+            name,
+            record,
+            symbol,
+            token: resumed,
+        } => {
+            let record = GameRecord::parse(&record).expect("Couldn't parse the game");
+
+            assert_eq!(name, "Ann");
+            assert_eq!(record.moves.len(), 1);
+            assert_eq!(symbol, Symbol::O);
+            assert_eq!(resumed, Some(token));
+        }
+        message => panic!("Expected a welcome, got {:?}", message),
+    }
+    assert_eq!(
+        next(ann),
+        Message::Back {
+            name: "Bob".to_string(),
+        }
+    );
+
+    bob
+}
+
+#[test]
+fn holds_the_game_while_a_player_is_away() {
+    let address = serve(Duration::from_secs(0));
+    let (ann, _) = drop_bob(&address);
+
+    play(&ann, 1, (0, 0));
+    assert_eq!(
+        next(&ann),
+        Message::Rejected {
+            reason: "waiting for Bob to come back".to_string(),
+        }
+    );
+}
+
+#[test]
+fn refuses_resuming_with_another_token() {
+    let address = serve(Duration::from_secs(0));
+    let (_ann, bob_token) = drop_bob(&address);
+
+    let stranger = connect(&address, "Eve");
+    send(
+        &stranger,
+        Message::Resume {
+            room: ROOM.to_string(),
+            token: format!("{}0", bob_token),
+        },
+    );
+
+    assert_eq!(
+        next(&stranger),
+        Message::Refused {
+            reason: format!("no seat of room {} is yours", ROOM),
+        }
+    );
+}
+
+#[test]
+fn refuses_resuming_a_game_that_hasnt_started() {
+    let address = serve(Duration::from_secs(0));
+
+    let ann = connect(&address, "Ann");
+    send(
+        &ann,
+        Message::JoinRoom {
+            room: ROOM.to_string(),
+            variant: "Classic".to_string(),
+        },
+    );
+    assert!(matches!(next(&ann), Message::Waiting { players: 1, .. }));
+
+    let stranger = connect(&address, "Eve");
+    send(
+        &stranger,
+        Message::Resume {
+            room: ROOM.to_string(),
+            token: "0".repeat(16),
+        },
+    );
+
+    assert_eq!(
+        next(&stranger),
+        Message::Refused {
+            reason: format!("the game of room {} hasn't started", ROOM),
+        }
+    );
+}
+
+#[test]
+fn resumes_the_game_with_its_moves() {
+    let address = serve(Duration::from_secs(0));
+    let (ann, bob_token) = drop_bob(&address);
+
+    resume(&address, &ann, bob_token);
+}
+
+#[test]
+fn plays_on_once_the_player_is_back() {
+    let address = serve(Duration::from_secs(0));
+    let (ann, bob_token) = drop_bob(&address);
+    let bob = resume(&address, &ann, bob_token);
+
+    play(&bob, 1, (0, 0));
+    played(&[&ann, &bob], 1, (0, 0));
+
+    play(&ann, 2, (2, 2));
+    played(&[&ann, &bob], 2, (2, 2));
+}
+
+#[test]
+fn delays_the_players_chat_like_their_moves() {
+    let delay = Duration::from_millis(300);
+    let address = serve(delay);
+    let ((ann, _), (bob, _)) = start(&address);
+
+    let spectator = connect(&address, "Eve");
+    send(
+        &spectator,
+        Message::Watch {
+            room: ROOM.to_string(),
+        },
+    );
+    assert!(matches!(
+        next(&spectator),
+        Message::Welcome { token: None, .. }
+    ));
+    for watching in [&ann, &bob, &spectator].iter() {
+        assert_eq!(next(watching), Message::Spectators { count: 1 });
+    }
+
+    play(&ann, 0, (1, 1));
+    played(&[&ann, &bob], 0, (1, 1));
+    send(
+        &ann,
+        Message::Chat {
+            name: String::new(),
+            text: "b2, of course".to_string(),
+        },
+    );
+    let chat = Message::Chat {
+        name: "Ann".to_string(),
+        text: "b2, of course".to_string(),
+    };
+    assert_eq!(next(&bob), chat);
+
+    thread::sleep(delay / 2);
+    assert_eq!(spectator.receive().expect("Couldn't receive"), None);
+    played(&[&spectator], 0, (1, 1));
+    assert_eq!(next(&spectator), chat);
+
+    // a spectator coming later gets the line with the game
+    let latecomer = connect(&address, "Tom");
+    send(
+        &latecomer,
+        Message::Watch {
+            room: ROOM.to_string(),
+        },
+    );
+    match next(&latecomer) {
+        Message::Welcome { record, .. } => {
+            let record = GameRecord::parse(&record).expect("Couldn't parse the game");
+
+            assert_eq!(record.moves.len(), 1);
+            assert_eq!(record.chat.len(), 1);
+        }
+        message => panic!("Expected a welcome, got {:?}", message),
+    }
+}
+
+#[test]
+fn quick_matches_fill_the_waiting_room() {
+    let address = serve(Duration::from_secs(0));
+    let quick_match = Message::QuickMatch {
+        variant: "Classic".to_string(),
+    };
+
+    let ann = connect(&address, "Ann");
+    send(&ann, quick_match.clone());
+    let room = match next(&ann) {
+        Message::Waiting {
+            room,
+            players: 1,
+            seats: 2,
+        } => room,
+        message => panic!("Expected to wait, got {:?}", message),
+    };
+
+    // told the room before the game starts, to resume it
+    let bob = connect(&address, "Bob");
+    send(&bob, quick_match.clone());
+    assert_eq!(
+        next(&bob),
+        Message::Waiting {
+            room: room.clone(),
+            players: 2,
+            seats: 2,
+        }
+    );
+    token(next(&ann));
+    token(next(&bob));
+
+    // a started game takes nobody in
+    let eve = connect(&address, "Eve");
+    send(&eve, quick_match);
+    match next(&eve) {
+        Message::Waiting {
+            room: other,
+            players: 1,
+            ..
+        } => assert_ne!(other, room),
+        message => panic!("Expected to wait, got {:?}", message),
+    }
+}